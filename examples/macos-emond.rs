@@ -1,7 +1,14 @@
 use std::{env, error::Error, fs::OpenOptions, io::Write, path::Path};
 
 use log::LevelFilter;
-use macos_emond::emond::EmondData;
+use macos_emond::{
+    context::{ParseContext, ParseOptions},
+    detect::detect_rules,
+    detection_rules::{load_detection_rules, match_rules},
+    emond::EmondData,
+    snapshot::EmondSnapshot,
+    validate::validate_rules,
+};
 use simplelog::{Config, SimpleLogger};
 
 fn main() {
@@ -11,27 +18,117 @@ fn main() {
 
     let args: Vec<String> = env::args().collect();
 
-    if args.len() == 2 {
+    if args.len() >= 3 && args[1] == "--root" {
+        let options = &args[3..];
+        let context = ParseContext::new(&args[2]).with_options(ParseOptions {
+            hashes: options.contains(&String::from("--hashes")),
+            resolve_commands: options.contains(&String::from("--resolve-commands")),
+            ..Default::default()
+        });
+        output_snapshot(&EmondSnapshot::collect(&context));
+    } else if args.len() == 4 && args[1] == "--detection-rules" {
+        let path = &args[3];
+        match parse_path(path) {
+            Ok(results) => output_matches(&args[2], &results),
+            Err(error) => println!("Failed to parse Emond rules at {}: {}", path, error),
+        }
+    } else if args.len() == 3 && args[1] == "--detect" {
+        let path = &args[2];
+        match parse_path(path) {
+            Ok(results) => output_detections(&results),
+            Err(error) => println!("Failed to parse Emond rules at {}: {}", path, error),
+        }
+    } else if args.len() == 3 && args[1] == "--validate" {
+        let path = &args[2];
+        match parse_path(path) {
+            Ok(results) => output_findings(&results),
+            Err(error) => println!("Failed to parse Emond rules at {}: {}", path, error),
+        }
+    } else if args.len() == 2 {
         let path = &args[1];
-        if Path::new(path).is_file() {
-            let emond_results = macos_emond::parser::parse_emond_file(path).unwrap();
-            output_emond(&emond_results);
-        } else {
-            let emond_results = macos_emond::parser::parse_emond_rules(path).unwrap();
-            output_emond(&emond_results);
+        match parse_path(path) {
+            Ok(results) => output_emond(&results),
+            Err(error) => println!("Failed to parse Emond rules at {}: {}", path, error),
         }
     } else {
-        let emond_paths = macos_emond::parser::get_emond_rules_paths().unwrap();
-        for paths in emond_paths {
-            let emond_results = macos_emond::parser::parse_emond_rules(&paths).unwrap();
-            output_emond(&emond_results);
+        let snapshot = macos_emond::parser::collect_emond("/");
+        output_snapshot(&snapshot);
+    }
+}
+
+// Parse a single rules file or every rules file in a directory
+fn parse_path(path: &str) -> Result<Vec<EmondData>, macos_emond::EmondError> {
+    if Path::new(path).is_file() {
+        macos_emond::parser::parse_emond_file(path)
+    } else {
+        macos_emond::parser::parse_emond_rules(path)
+    }
+}
+
+fn output_findings(results: &[EmondData]) {
+    let findings = validate_rules(results);
+    for finding in &findings {
+        println!("{} at {}", finding, finding.key_path);
+    }
+    println!(
+        "Validated {} rules: {} findings",
+        results.len(),
+        findings.len()
+    );
+}
+
+fn output_detections(results: &[EmondData]) {
+    let mut detections = detect_rules(results);
+    detections.sort_by_key(|detection| std::cmp::Reverse(detection.score));
+    for detection in &detections {
+        println!("{}", detection);
+        for indicator in &detection.indicators {
+            println!("  +{} {}", indicator.score, indicator.rationale);
         }
     }
 }
 
+fn output_matches(detection_path: &str, results: &[EmondData]) {
+    let detection_results = match load_detection_rules(detection_path) {
+        Ok(results) => results,
+        Err(error) => {
+            println!(
+                "Failed to load detection rules at {}: {}",
+                detection_path, error
+            );
+            return;
+        }
+    };
+    let mut detection_rules = Vec::new();
+    for detection_result in detection_results {
+        match detection_result {
+            Ok(detection_rule) => detection_rules.push(detection_rule),
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    let matches = match_rules(&detection_rules, results);
+    for rule_match in &matches {
+        println!(
+            "[{}] {}: {} rule {} ({})",
+            rule_match.level,
+            rule_match.title,
+            rule_match.file,
+            rule_match.rule_index,
+            rule_match.rule_name
+        );
+    }
+    println!(
+        "Matched {} detection rules against {} rules: {} matches",
+        detection_rules.len(),
+        results.len(),
+        matches.len()
+    );
+}
+
 fn output_emond(results: &[EmondData]) {
     for data in results {
-        let output_results = output(data, &data.name);
+        let output_results = output(data, &output_name(&data.name));
         match output_results {
             Ok(_) => {}
             Err(error) => println!("Failed to output data: {:?}", error),
@@ -39,7 +136,33 @@ fn output_emond(results: &[EmondData]) {
     }
 }
 
-fn output(results: &EmondData, output_name: &str) -> Result<(), Box<dyn Error>> {
+fn output_snapshot(snapshot: &EmondSnapshot) {
+    let output_results = output(snapshot, "emond_snapshot");
+    match output_results {
+        Ok(_) => {}
+        Err(error) => println!("Failed to output data: {:?}", error),
+    }
+}
+
+// Rule names come from the rules file. Only keep characters that are safe in a file name
+fn output_name(rule_name: &str) -> String {
+    let name: String = rule_name
+        .chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() || character == '-' || character == '_' {
+                character
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        return String::from("emond_rule");
+    }
+    name
+}
+
+fn output<T: serde::Serialize>(results: &T, output_name: &str) -> Result<(), Box<dyn Error>> {
     let mut json_file = OpenOptions::new()
         .append(true)
         .create(true)
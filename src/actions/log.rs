@@ -1,16 +1,36 @@
-use log::warn;
 use plist::Dictionary;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::util::{get_dictionary_value, get_string_value};
+use crate::{
+    diagnostic::Expected,
+    util::{
+        get_dictionary_value, get_key_order, get_string_value, insert_dictionary_value,
+        insert_extra_value, insert_extra_values, insert_string_value, order_dictionary,
+    },
+};
 
-#[derive(Debug, Serialize)]
+/// Log Action keys and their expected value types
+pub(crate) const LOG_KEYS: [(&str, Expected); 6] = [
+    ("type", Expected::String),
+    ("message", Expected::String),
+    ("logLevel", Expected::String),
+    ("logType", Expected::String),
+    ("parameters", Expected::Dictionary),
+    ("facility", Expected::String),
+];
+
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Log {
     pub message: String,
     pub facility: String,
     pub log_level: String,
     pub log_type: String,
     pub parameters: Dictionary,
+    /// Action keys not modeled above
+    pub extra: Dictionary,
+    /// Keys in the order they were parsed, so the action is written back the same way
+    #[serde(default)]
+    pub key_order: Vec<String>,
 }
 
 impl Log {
@@ -22,28 +42,64 @@ impl Log {
             log_level: String::new(),
             log_type: String::new(),
             parameters: Dictionary::new(),
+            extra: Dictionary::new(),
+            key_order: get_key_order(action_dictionary),
         };
 
         for (key, action_value) in action_dictionary {
-            if key == "message" {
+            if key == "message" && action_value.as_string().is_some() {
                 log_data.message = get_string_value(action_value);
-            } else if key == "logLevel" {
+            } else if key == "logLevel" && action_value.as_string().is_some() {
                 log_data.log_level = get_string_value(action_value);
-            } else if key == "logType" {
+            } else if key == "logType" && action_value.as_string().is_some() {
                 log_data.log_type = get_string_value(action_value);
-            } else if key == "parameters" {
+            } else if key == "parameters" && action_value.as_dictionary().is_some() {
                 log_data.parameters = get_dictionary_value(action_value.clone());
-            } else if key == "facility" {
+            } else if key == "facility" && action_value.as_string().is_some() {
                 log_data.facility = get_string_value(action_value);
             } else if key == "type" {
                 // Skip type values. We already know the action type
                 continue;
             } else {
-                warn!("Unknown Log Action key: {}. Value: {:?}", key, action_value);
+                insert_extra_value(
+                    &mut log_data.extra,
+                    &LOG_KEYS,
+                    key,
+                    action_value,
+                    "Log Action",
+                );
             }
         }
         log_data
     }
+
+    /// Convert the Log Action back into an Emond action dictionary, without the action type
+    pub fn to_dictionary(&self) -> Dictionary {
+        let key_order = &self.key_order;
+        let mut action_dictionary = Dictionary::new();
+        insert_string_value(&mut action_dictionary, "message", &self.message, key_order);
+        insert_string_value(
+            &mut action_dictionary,
+            "logLevel",
+            &self.log_level,
+            key_order,
+        );
+        insert_string_value(&mut action_dictionary, "logType", &self.log_type, key_order);
+        insert_string_value(
+            &mut action_dictionary,
+            "facility",
+            &self.facility,
+            key_order,
+        );
+        insert_dictionary_value(
+            &mut action_dictionary,
+            "parameters",
+            &self.parameters,
+            key_order,
+        );
+        insert_extra_values(&mut action_dictionary, &self.extra);
+        order_dictionary(action_dictionary, key_order)
+    }
 }
 
 #[cfg(test)]
@@ -79,5 +135,6 @@ mod tests {
         assert_eq!(results.log_type, "type1");
         assert_eq!(results.facility, "testing");
         assert_eq!(results.parameters, Dictionary::new());
+        assert!(results.extra.is_empty());
     }
 }
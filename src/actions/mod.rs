@@ -1,4 +1,219 @@
+//! Emond Rule actions
+//!
+//! Actions run when a rule fires. Emond runs them in the order they appear in the rule
+
+use plist::{Dictionary, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    diagnostic::{check_dictionary, check_required, child_path, Diagnostic, Expected, Severity},
+    util::{get_string_value, order_dictionary},
+};
+
 pub mod command;
 pub mod log;
 pub mod send_email;
 pub mod send_notification;
+pub mod send_sms;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Action {
+    Command(command::Command),
+    Log(log::Log),
+    SendEmail(send_email::SendEmail),
+    SendSms(send_sms::SendSms),
+    SendNotification(send_notification::SendNotification),
+    /// Action type not known to this parser. The original action dictionary is kept
+    Unknown(Dictionary),
+}
+
+/// An action and its position in the rule actions array
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RuleAction {
+    pub index: usize,
+    pub action: Action,
+}
+
+impl Action {
+    /// Parse a single Emond action dictionary based on its `type` value
+    pub fn parse_action(action_dictionary: &Dictionary) -> Action {
+        let action_type = match action_dictionary.get("type") {
+            Some(value) => get_string_value(value),
+            None => String::new(),
+        };
+
+        match action_type.as_str() {
+            "Log" => Action::Log(log::Log::parse_action_log(action_dictionary)),
+            "RunCommand" => Action::Command(command::Command::parse_action_run_command(
+                action_dictionary,
+            )),
+            "SendEmail" => Action::SendEmail(send_email::SendEmail::parse_action_send_email(
+                action_dictionary,
+            )),
+            "SendSMS" => {
+                Action::SendSms(send_sms::SendSms::parse_action_send_sms(action_dictionary))
+            }
+            "SendNotification" => Action::SendNotification(
+                send_notification::SendNotification::parse_action_send_notification(
+                    action_dictionary,
+                ),
+            ),
+            _ => {
+                ::log::warn!("Unknown Action Type: {}", action_type);
+                Action::Unknown(action_dictionary.clone())
+            }
+        }
+    }
+
+    /// Check an Emond action dictionary for missing, unknown and wrongly typed values
+    pub fn diagnostics(action_dictionary: &Dictionary, key_path: &str) -> Vec<Diagnostic> {
+        let type_path = child_path(key_path, "type");
+        let action_type = match action_dictionary.get("type") {
+            Some(Value::String(action_type)) => action_type.as_str(),
+            Some(value) => return vec![Diagnostic::wrong_type(&type_path, "string", value)],
+            None => return vec![Diagnostic::missing(&type_path, "string", Severity::Warning)],
+        };
+
+        let known_keys: &[(&str, Expected)] = match action_type {
+            "Log" => &log::LOG_KEYS,
+            "RunCommand" => &command::RUN_COMMAND_KEYS,
+            "SendEmail" => &send_email::SEND_EMAIL_KEYS,
+            "SendSMS" => &send_sms::SEND_SMS_KEYS,
+            "SendNotification" => &send_notification::SEND_NOTIFICATION_KEYS,
+            // Unknown actions are kept whole, their keys are not checked
+            _ => {
+                return vec![Diagnostic::unknown_value(
+                    &type_path,
+                    "action type",
+                    action_type,
+                )]
+            }
+        };
+
+        let mut diagnostics = check_dictionary(action_dictionary, key_path, known_keys);
+        if action_type == "RunCommand" {
+            diagnostics.append(&mut check_required(
+                action_dictionary,
+                key_path,
+                &[("command", Expected::String)],
+                Severity::Warning,
+            ));
+        }
+        diagnostics
+    }
+
+    /// Get the Emond action type string
+    pub fn action_type(&self) -> String {
+        match self {
+            Action::Command(_) => String::from("RunCommand"),
+            Action::Log(_) => String::from("Log"),
+            Action::SendEmail(_) => String::from("SendEmail"),
+            Action::SendSms(_) => String::from("SendSMS"),
+            Action::SendNotification(_) => String::from("SendNotification"),
+            Action::Unknown(action_dictionary) => match action_dictionary.get("type") {
+                Some(value) => get_string_value(value),
+                None => String::new(),
+            },
+        }
+    }
+
+    /// Convert the action back into an Emond action dictionary
+    pub fn to_dictionary(&self) -> Dictionary {
+        let action_values = match self {
+            Action::Command(command) => command.to_dictionary(),
+            Action::Log(log) => log.to_dictionary(),
+            Action::SendEmail(email) => email.to_dictionary(),
+            Action::SendSms(sms) => sms.to_dictionary(),
+            Action::SendNotification(notification) => notification.to_dictionary(),
+            Action::Unknown(action_dictionary) => return action_dictionary.clone(),
+        };
+
+        let mut action_dictionary = Dictionary::new();
+        action_dictionary.insert(String::from("type"), Value::String(self.action_type()));
+        for (key, value) in action_values {
+            action_dictionary.insert(key, value);
+        }
+        order_dictionary(action_dictionary, self.key_order())
+    }
+
+    /// Get the keys of the action in the order they were parsed. Empty if the action was not parsed
+    pub fn key_order(&self) -> &[String] {
+        match self {
+            Action::Command(command) => &command.key_order,
+            Action::Log(log) => &log.key_order,
+            Action::SendEmail(email) => &email.key_order,
+            Action::SendSms(sms) => &sms.key_order,
+            Action::SendNotification(notification) => &notification.key_order,
+            Action::Unknown(_) => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plist::{Dictionary, Value};
+
+    use crate::diagnostic::DiagnosticKind;
+
+    use super::Action;
+
+    #[test]
+    fn test_parse_action() {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(String::from("type"), Value::String(String::from("Log")));
+        test_dictionary.insert(String::from("message"), Value::String(String::from("test")));
+
+        let results = Action::parse_action(&test_dictionary);
+        match &results {
+            Action::Log(log) => assert_eq!(log.message, "test"),
+            _ => panic!("Expected Log action, got: {:?}", results),
+        }
+        assert_eq!(results.action_type(), "Log");
+        assert_eq!(results.to_dictionary(), test_dictionary);
+    }
+
+    #[test]
+    fn test_parse_unknown_action() {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(
+            String::from("type"),
+            Value::String(String::from("PlaySound")),
+        );
+        test_dictionary.insert(String::from("sound"), Value::String(String::from("Basso")));
+
+        let results = Action::parse_action(&test_dictionary);
+        match &results {
+            Action::Unknown(action_dictionary) => assert_eq!(action_dictionary, &test_dictionary),
+            _ => panic!("Expected Unknown action, got: {:?}", results),
+        }
+        assert_eq!(results.to_dictionary(), test_dictionary);
+        assert_eq!(results.action_type(), "PlaySound");
+    }
+
+    #[test]
+    fn test_action_diagnostics() {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(
+            String::from("type"),
+            Value::String(String::from("RunCommand")),
+        );
+        test_dictionary.insert(String::from("arguments"), Value::String(String::from("-c")));
+
+        let results = Action::diagnostics(&test_dictionary, "actions[0]");
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].kind, DiagnosticKind::WrongType);
+        assert_eq!(results[0].key_path, "actions[0].arguments");
+        assert_eq!(results[1].kind, DiagnosticKind::Missing);
+        assert_eq!(results[1].key_path, "actions[0].command");
+
+        test_dictionary.insert(String::from("type"), Value::String(String::from("Beep")));
+        let results = Action::diagnostics(&test_dictionary, "actions[0]");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].kind, DiagnosticKind::UnknownValue);
+        assert_eq!(results[0].actual, "Beep");
+
+        let results = Action::diagnostics(&Dictionary::new(), "actions[0]");
+        assert_eq!(results[0].kind, DiagnosticKind::Missing);
+        assert_eq!(results[0].key_path, "actions[0].type");
+    }
+}
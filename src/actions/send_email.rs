@@ -1,10 +1,26 @@
-use log::warn;
 use plist::Dictionary;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::util::get_string_value;
+use crate::{
+    diagnostic::Expected,
+    util::{
+        get_key_order, get_string_value, get_string_values, insert_extra_value,
+        insert_extra_values, insert_string_value, insert_string_values, order_dictionary,
+    },
+};
 
-#[derive(Debug, Serialize)]
+/// Send Email Action keys and their expected value types
+pub(crate) const SEND_EMAIL_KEYS: [(&str, Expected); 7] = [
+    ("type", Expected::String),
+    ("message", Expected::String),
+    ("subject", Expected::String),
+    ("localization_bundle_path", Expected::String),
+    ("relay_host", Expected::String),
+    ("admin_email", Expected::String),
+    ("recipient_addresses", Expected::StringArray),
+];
+
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct SendEmail {
     pub message: String,
     pub subject: String,
@@ -12,9 +28,15 @@ pub struct SendEmail {
     pub relay_host: String,
     pub admin_email: String,
     pub recipient_addresses: Vec<String>,
+    /// Action keys not modeled above
+    pub extra: Dictionary,
+    /// Keys in the order they were parsed, so the action is written back the same way
+    #[serde(default)]
+    pub key_order: Vec<String>,
 }
 
 impl SendEmail {
+    // Parse the Send Email Action Emond Rule
     pub fn parse_action_send_email(action_dictionary: &Dictionary) -> SendEmail {
         let mut email_data = SendEmail {
             message: String::new(),
@@ -23,42 +45,101 @@ impl SendEmail {
             relay_host: String::new(),
             admin_email: String::new(),
             recipient_addresses: Vec::new(),
+            extra: Dictionary::new(),
+            key_order: get_key_order(action_dictionary),
         };
 
         for (key, action_value) in action_dictionary {
-            if key == "message" {
+            if key == "message" && action_value.as_string().is_some() {
                 email_data.message = get_string_value(action_value);
-            } else if key == "subject" {
+            } else if key == "subject" && action_value.as_string().is_some() {
                 email_data.subject = get_string_value(action_value);
-            } else if key == "localization_bundle_path" {
+            } else if key == "localization_bundle_path" && action_value.as_string().is_some() {
                 email_data.localization_bundle_path = get_string_value(action_value);
-            } else if key == "relay_host" {
+            } else if key == "relay_host" && action_value.as_string().is_some() {
                 email_data.relay_host = get_string_value(action_value);
-            } else if key == "admin_email" {
+            } else if key == "admin_email" && action_value.as_string().is_some() {
                 email_data.admin_email = get_string_value(action_value);
-            } else if key == "recipient_addresses" {
-                let arg_results = action_value.as_array();
-                let arg_array = match arg_results {
-                    Some(results) => results,
-                    None => {
-                        warn!(
-                            "Failed to parse Send Email Action array: {:?}",
-                            action_value
-                        );
-                        continue;
-                    }
-                };
-
-                for args in arg_array {
-                    email_data.recipient_addresses.push(get_string_value(args));
-                }
+            } else if key == "recipient_addresses" && action_value.as_array().is_some() {
+                email_data.recipient_addresses = get_string_values(action_value);
             } else if key == "type" {
                 // Skip type values. We already know the action type
                 continue;
             } else {
-                warn!("Unknown Log Action key: {}. Value: {:?}", key, action_value);
+                insert_extra_value(
+                    &mut email_data.extra,
+                    &SEND_EMAIL_KEYS,
+                    key,
+                    action_value,
+                    "Send Email Action",
+                );
             }
         }
         email_data
     }
+
+    /// Convert the Send Email Action back into an Emond action dictionary, without the action type
+    pub fn to_dictionary(&self) -> Dictionary {
+        let key_order = &self.key_order;
+        let mut action_dictionary = Dictionary::new();
+        insert_string_value(&mut action_dictionary, "message", &self.message, key_order);
+        insert_string_value(&mut action_dictionary, "subject", &self.subject, key_order);
+        insert_string_value(
+            &mut action_dictionary,
+            "localization_bundle_path",
+            &self.localization_bundle_path,
+            key_order,
+        );
+        insert_string_value(
+            &mut action_dictionary,
+            "relay_host",
+            &self.relay_host,
+            key_order,
+        );
+        insert_string_value(
+            &mut action_dictionary,
+            "admin_email",
+            &self.admin_email,
+            key_order,
+        );
+        insert_string_values(
+            &mut action_dictionary,
+            "recipient_addresses",
+            &self.recipient_addresses,
+            key_order,
+        );
+        insert_extra_values(&mut action_dictionary, &self.extra);
+        order_dictionary(action_dictionary, key_order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plist::{Dictionary, Value};
+
+    use crate::actions::send_email::SendEmail;
+
+    #[test]
+    fn test_parse_action_send_email() {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(String::from("message"), Value::String(String::from("test")));
+        test_dictionary.insert(
+            String::from("subject"),
+            Value::String(String::from("subject1")),
+        );
+        test_dictionary.insert(
+            String::from("admin_email"),
+            Value::String(String::from("admin@example.com")),
+        );
+        test_dictionary.insert(
+            String::from("recipient_addresses"),
+            Value::Array(vec![Value::String(String::from("user@example.com"))]),
+        );
+
+        let results = SendEmail::parse_action_send_email(&test_dictionary);
+        assert_eq!(results.message, "test");
+        assert_eq!(results.subject, "subject1");
+        assert_eq!(results.admin_email, "admin@example.com");
+        assert_eq!(results.recipient_addresses, ["user@example.com"]);
+    }
 }
@@ -1,14 +1,32 @@
-use log::warn;
 use plist::Dictionary;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::util::{get_dictionary_value, get_string_value};
+use crate::{
+    diagnostic::Expected,
+    util::{
+        get_dictionary_value, get_key_order, get_string_value, insert_dictionary_value,
+        insert_extra_value, insert_extra_values, insert_string_value, order_dictionary,
+    },
+};
 
-#[derive(Debug, Serialize)]
+/// Send Notification Action keys and their expected value types
+pub(crate) const SEND_NOTIFICATION_KEYS: [(&str, Expected); 4] = [
+    ("type", Expected::String),
+    ("name", Expected::String),
+    ("message", Expected::String),
+    ("details", Expected::Dictionary),
+];
+
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct SendNotification {
     pub name: String,
     pub message: String,
     pub details: Dictionary,
+    /// Action keys not modeled above
+    pub extra: Dictionary,
+    /// Keys in the order they were parsed, so the action is written back the same way
+    #[serde(default)]
+    pub key_order: Vec<String>,
 }
 
 impl SendNotification {
@@ -17,22 +35,41 @@ impl SendNotification {
             message: String::new(),
             name: String::new(),
             details: Dictionary::new(),
+            extra: Dictionary::new(),
+            key_order: get_key_order(action_dictionary),
         };
 
         for (key, action_value) in action_dictionary {
-            if key == "message" {
+            if key == "message" && action_value.as_string().is_some() {
                 notification.message = get_string_value(action_value);
-            } else if key == "name" {
+            } else if key == "name" && action_value.as_string().is_some() {
                 notification.name = get_string_value(action_value);
-            } else if key == "details" {
+            } else if key == "details" && action_value.as_dictionary().is_some() {
                 notification.details = get_dictionary_value(action_value.clone());
             } else if key == "type" {
                 // Skip type values. We already know the action type
                 continue;
             } else {
-                warn!("Unknown Log Action key: {}. Value: {:?}", key, action_value);
+                insert_extra_value(
+                    &mut notification.extra,
+                    &SEND_NOTIFICATION_KEYS,
+                    key,
+                    action_value,
+                    "Send Notification Action",
+                );
             }
         }
         notification
     }
+
+    /// Convert the Send Notification Action back into an Emond action dictionary, without the action type
+    pub fn to_dictionary(&self) -> Dictionary {
+        let key_order = &self.key_order;
+        let mut action_dictionary = Dictionary::new();
+        insert_string_value(&mut action_dictionary, "name", &self.name, key_order);
+        insert_string_value(&mut action_dictionary, "message", &self.message, key_order);
+        insert_dictionary_value(&mut action_dictionary, "details", &self.details, key_order);
+        insert_extra_values(&mut action_dictionary, &self.extra);
+        order_dictionary(action_dictionary, key_order)
+    }
 }
@@ -0,0 +1,170 @@
+use plist::Dictionary;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    diagnostic::Expected,
+    util::{
+        get_key_order, get_string_value, get_string_values, insert_extra_value,
+        insert_extra_values, insert_string_value, insert_string_values, order_dictionary,
+    },
+};
+
+/// Send SMS Action keys and their expected value types
+pub(crate) const SEND_SMS_KEYS: [(&str, Expected); 7] = [
+    ("type", Expected::String),
+    ("message", Expected::String),
+    ("subject", Expected::String),
+    ("localization_bundle_path", Expected::String),
+    ("relay_host", Expected::String),
+    ("admin_email", Expected::String),
+    ("recipient_addresses", Expected::StringArray),
+];
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct SendSms {
+    pub message: String,
+    pub subject: String,
+    pub localization_bundle_path: String,
+    pub relay_host: String,
+    pub admin_email: String,
+    /// SMS messages are sent by email to carrier gateways (ex: 5555550100@txt.att.net)
+    pub recipient_addresses: Vec<String>,
+    /// Phone numbers from the recipient addresses
+    pub recipient_numbers: Vec<String>,
+    /// Carrier email to SMS gateways from the recipient addresses
+    pub carrier_gateways: Vec<String>,
+    /// Action keys not modeled above
+    pub extra: Dictionary,
+    /// Keys in the order they were parsed, so the action is written back the same way
+    #[serde(default)]
+    pub key_order: Vec<String>,
+}
+
+impl SendSms {
+    // Parse the Send SMS Action Emond Rule
+    // SendSMS uses the same keys as SendEmail: https://magnusviri.com/what-is-emond.html
+    pub fn parse_action_send_sms(action_dictionary: &Dictionary) -> SendSms {
+        let mut sms_data = SendSms {
+            message: String::new(),
+            subject: String::new(),
+            localization_bundle_path: String::new(),
+            relay_host: String::new(),
+            admin_email: String::new(),
+            recipient_addresses: Vec::new(),
+            recipient_numbers: Vec::new(),
+            carrier_gateways: Vec::new(),
+            extra: Dictionary::new(),
+            key_order: get_key_order(action_dictionary),
+        };
+
+        for (key, action_value) in action_dictionary {
+            if key == "message" && action_value.as_string().is_some() {
+                sms_data.message = get_string_value(action_value);
+            } else if key == "subject" && action_value.as_string().is_some() {
+                sms_data.subject = get_string_value(action_value);
+            } else if key == "localization_bundle_path" && action_value.as_string().is_some() {
+                sms_data.localization_bundle_path = get_string_value(action_value);
+            } else if key == "relay_host" && action_value.as_string().is_some() {
+                sms_data.relay_host = get_string_value(action_value);
+            } else if key == "admin_email" && action_value.as_string().is_some() {
+                sms_data.admin_email = get_string_value(action_value);
+            } else if key == "recipient_addresses" && action_value.as_array().is_some() {
+                sms_data.recipient_addresses = get_string_values(action_value);
+            } else if key == "type" {
+                // Skip type values. We already know the action type
+                continue;
+            } else {
+                insert_extra_value(
+                    &mut sms_data.extra,
+                    &SEND_SMS_KEYS,
+                    key,
+                    action_value,
+                    "Send SMS Action",
+                );
+            }
+        }
+
+        for address in &sms_data.recipient_addresses {
+            let (number, gateway) = match address.split_once('@') {
+                Some((number, gateway)) => (number, gateway),
+                None => (address.as_str(), ""),
+            };
+            sms_data.recipient_numbers.push(number.to_string());
+            if !gateway.is_empty() && !sms_data.carrier_gateways.iter().any(|g| g == gateway) {
+                sms_data.carrier_gateways.push(gateway.to_string());
+            }
+        }
+        sms_data
+    }
+
+    /// Convert the Send SMS Action back into an Emond action dictionary, without the action type.
+    /// Recipient numbers and carrier gateways come from the recipient addresses and are not written
+    pub fn to_dictionary(&self) -> Dictionary {
+        let key_order = &self.key_order;
+        let mut action_dictionary = Dictionary::new();
+        insert_string_value(&mut action_dictionary, "message", &self.message, key_order);
+        insert_string_value(&mut action_dictionary, "subject", &self.subject, key_order);
+        insert_string_value(
+            &mut action_dictionary,
+            "localization_bundle_path",
+            &self.localization_bundle_path,
+            key_order,
+        );
+        insert_string_value(
+            &mut action_dictionary,
+            "relay_host",
+            &self.relay_host,
+            key_order,
+        );
+        insert_string_value(
+            &mut action_dictionary,
+            "admin_email",
+            &self.admin_email,
+            key_order,
+        );
+        insert_string_values(
+            &mut action_dictionary,
+            "recipient_addresses",
+            &self.recipient_addresses,
+            key_order,
+        );
+        insert_extra_values(&mut action_dictionary, &self.extra);
+        order_dictionary(action_dictionary, key_order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plist::{Dictionary, Value};
+
+    use crate::actions::send_sms::SendSms;
+
+    #[test]
+    fn test_parse_action_send_sms() {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(String::from("message"), Value::String(String::from("test")));
+        test_dictionary.insert(
+            String::from("relay_host"),
+            Value::String(String::from("smtp.example.com")),
+        );
+        test_dictionary.insert(
+            String::from("recipient_addresses"),
+            Value::Array(vec![
+                Value::String(String::from("5555550100@txt.att.net")),
+                Value::String(String::from("5555550101@txt.att.net")),
+                Value::String(String::from("5555550102")),
+            ]),
+        );
+        test_dictionary.insert(String::from("type"), Value::String(String::from("SendSMS")));
+
+        let results = SendSms::parse_action_send_sms(&test_dictionary);
+        assert_eq!(results.message, "test");
+        assert_eq!(results.relay_host, "smtp.example.com");
+        assert_eq!(results.recipient_addresses.len(), 3);
+        assert_eq!(
+            results.recipient_numbers,
+            ["5555550100", "5555550101", "5555550102"]
+        );
+        assert_eq!(results.carrier_gateways, ["txt.att.net"]);
+    }
+}
@@ -0,0 +1,236 @@
+//! Build Emond rules in code
+//!
+//! `EmondRuleBuilder` creates rules for test harnesses and detection testing. Built rules are checked like parsed rules
+//! and can be written to a rules file with the `writer` module
+
+use plist::{Dictionary, Value};
+
+use crate::{
+    actions::{
+        command::Command, log::Log, send_email::SendEmail, send_notification::SendNotification,
+        send_sms::SendSms, Action, RuleAction,
+    },
+    criterion::Criterion,
+    diagnostic::{Diagnostic, DiagnosticKind, Expected, Severity},
+    emond::EmondData,
+    error::EmondError,
+    reader::PlistFormat,
+    variable::Variable,
+};
+
+/// Build an Emond rule one value at a time
+#[derive(Debug)]
+pub struct EmondRuleBuilder {
+    rule: EmondData,
+}
+
+impl EmondRuleBuilder {
+    /// Start an enabled rule with the provided name
+    pub fn new(name: &str) -> EmondRuleBuilder {
+        EmondRuleBuilder {
+            rule: EmondData {
+                name: name.to_string(),
+                source_file: String::new(),
+                rules_directory: String::new(),
+                rule_index: 0,
+                metadata: None,
+                hashes: None,
+                format: PlistFormat::Xml,
+                enabled: true,
+                event_types: Vec::new(),
+                start_time: String::new(),
+                allow_partial_criterion_match: false,
+                actions: Vec::new(),
+                criterion: Vec::new(),
+                variables: Vec::new(),
+                extra: Dictionary::new(),
+                key_order: Vec::new(),
+                diagnostics: Vec::new(),
+            },
+        }
+    }
+
+    pub fn enabled(mut self, enabled: bool) -> EmondRuleBuilder {
+        self.rule.enabled = enabled;
+        self
+    }
+
+    /// Add an event type the rule fires on (ex: startup or auth:login)
+    pub fn event_type(mut self, event_type: &str) -> EmondRuleBuilder {
+        self.rule.event_types.push(event_type.to_string());
+        self
+    }
+
+    pub fn start_time(mut self, start_time: &str) -> EmondRuleBuilder {
+        self.rule.start_time = start_time.to_string();
+        self
+    }
+
+    pub fn allow_partial_criterion_match(mut self, allow: bool) -> EmondRuleBuilder {
+        self.rule.allow_partial_criterion_match = allow;
+        self
+    }
+
+    pub fn criterion(mut self, criterion: Criterion) -> EmondRuleBuilder {
+        self.rule.criterion.push(criterion);
+        self
+    }
+
+    pub fn variable(mut self, variable: Variable) -> EmondRuleBuilder {
+        self.rule.variables.push(variable);
+        self
+    }
+
+    /// Add a RunCommand action. Actions run in the order they are added
+    pub fn run_command(self, command: Command) -> EmondRuleBuilder {
+        self.action(Action::Command(command))
+    }
+
+    /// Add a Log action
+    pub fn log(self, log: Log) -> EmondRuleBuilder {
+        self.action(Action::Log(log))
+    }
+
+    /// Add a SendEmail action
+    pub fn send_email(self, email: SendEmail) -> EmondRuleBuilder {
+        self.action(Action::SendEmail(email))
+    }
+
+    /// Add a SendSMS action
+    pub fn send_sms(self, sms: SendSms) -> EmondRuleBuilder {
+        self.action(Action::SendSms(sms))
+    }
+
+    /// Add a SendNotification action
+    pub fn send_notification(self, notification: SendNotification) -> EmondRuleBuilder {
+        self.action(Action::SendNotification(notification))
+    }
+
+    /// Add any action, including action types not known to this parser
+    pub fn action(mut self, action: Action) -> EmondRuleBuilder {
+        self.rule.actions.push(RuleAction {
+            index: self.rule.actions.len(),
+            action,
+        });
+        self
+    }
+
+    /// Add a rule key not modeled by `EmondData`
+    pub fn extra(mut self, key: &str, value: Value) -> EmondRuleBuilder {
+        self.rule.extra.insert(key.to_string(), value);
+        self
+    }
+
+    /// Check the rule and build it. Fails if the rule has no event types, or if a value is missing, has the wrong type
+    /// or is not known to Emond (ex: a RunCommand action without a command). Extra keys are allowed
+    pub fn build(self) -> Result<EmondData, EmondError> {
+        let rule_dictionary = self.rule.to_dictionary();
+
+        let mut diagnostics: Vec<Diagnostic> = EmondData::rule_diagnostics(&rule_dictionary)
+            .into_iter()
+            .filter(|diagnostic| {
+                diagnostic.severity != Severity::Info
+                    && diagnostic.kind != DiagnosticKind::UnknownKey
+            })
+            .collect();
+        if self.rule.event_types.is_empty() {
+            diagnostics.push(Diagnostic::missing(
+                "eventTypes",
+                Expected::StringArray.as_str(),
+                Severity::Warning,
+            ));
+        }
+        if !diagnostics.is_empty() {
+            return Err(EmondError::Build {
+                name: self.rule.name,
+                diagnostics,
+            });
+        }
+
+        // Parse the rule dictionary so values derived while parsing are filled in
+        EmondData::parse_rule(rule_dictionary, "", 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plist::Value;
+
+    use crate::{
+        actions::{command::Command, send_sms::SendSms},
+        criterion::{Criterion, Operator},
+        diagnostic::DiagnosticKind,
+        emond::EmondData,
+        error::EmondError,
+        writer::emond_rules_to_xml,
+    };
+
+    use super::EmondRuleBuilder;
+
+    #[test]
+    fn test_build_rule() {
+        let mut criterion = Criterion::new(Operator::Equal);
+        criterion.event_key = String::from("username");
+        criterion.value = Some(Value::String(String::from("admin")));
+
+        let results = EmondRuleBuilder::new("test rule")
+            .event_type("auth:login")
+            .criterion(criterion)
+            .run_command(Command {
+                command: String::from("/tmp/payload.sh"),
+                user: String::from("root"),
+                ..Default::default()
+            })
+            .send_sms(SendSms {
+                message: String::from("login"),
+                recipient_addresses: vec![String::from("5555550100@txt.att.net")],
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(results.name, "test rule");
+        assert!(results.enabled);
+        assert_eq!(results.event_types, ["auth:login"]);
+        assert_eq!(results.criterion[0].event_key, "username");
+        assert_eq!(results.command_actions()[0].command, "/tmp/payload.sh");
+        assert_eq!(
+            results.send_sms_actions()[0].recipient_numbers,
+            ["5555550100"]
+        );
+        assert_eq!(results.actions[1].index, 1);
+        assert!(results.diagnostics.is_empty());
+
+        let data = emond_rules_to_xml(&[results]).unwrap();
+        let written = EmondData::parse_emond_bytes(&data, "memory").unwrap();
+        assert_eq!(written[0].command_actions()[0].user, "root");
+    }
+
+    #[test]
+    fn test_build_invalid_rule() {
+        let err = EmondRuleBuilder::new("invalid rule")
+            .criterion(Criterion::new(Operator::Unknown(String::from("Near"))))
+            .run_command(Command::default())
+            .extra("comment", Value::String(String::from("allowed")))
+            .build()
+            .unwrap_err();
+
+        let diagnostics = match &err {
+            EmondError::Build { diagnostics, .. } => diagnostics,
+            _ => panic!("Expected Build error, got: {:?}", err),
+        };
+        let key_paths: Vec<&str> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.key_path.as_str())
+            .collect();
+        assert_eq!(
+            key_paths,
+            ["actions[0].command", "criterion[0].operator", "eventTypes"]
+        );
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::Missing);
+        assert_eq!(err.path(), "");
+        assert!(err.to_string().starts_with(
+            "Failed to build Emond rule invalid rule: Missing string value at actions[0].command"
+        ));
+    }
+}
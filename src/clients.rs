@@ -0,0 +1,272 @@
+//! Parse the macOS Emond clients directory
+//!
+//! Emond will only run if at least one file is present in `/private/var/db/emondClients`
+
+use std::{
+    fs::{read, read_dir},
+    io::{Read, Seek},
+};
+
+use log::{error, warn};
+use plist::Value;
+use serde::Serialize;
+
+use crate::{
+    context::ParseContext,
+    error::EmondError,
+    hashes::Hashes,
+    metadata::FileMetadata,
+    reader::{check_plist_depth, read_reader},
+    size::get_file_size,
+};
+
+/// Location of the Emond clients directory
+pub const EMOND_CLIENTS_PATH: &str = "/private/var/db/emondClients";
+
+#[derive(Debug, Serialize)]
+pub struct EmondClients {
+    pub path: String,
+    /// Emond is enabled if any client file is present
+    pub enabled: bool,
+    pub clients: Vec<EmondClient>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmondClient {
+    pub name: String,
+    pub path: String,
+    pub metadata: Option<FileMetadata>,
+    /// MD5, SHA-1 and SHA-256 of the client file, if hashing is enabled in the parse options
+    pub hashes: Option<Hashes>,
+    /// Client file contents, if the file is a PLIST
+    pub plist: Option<Value>,
+}
+
+impl EmondClients {
+    /// Parse all files in the Emond clients directory under the context root
+    pub fn parse_clients(context: &ParseContext) -> Result<EmondClients, EmondError> {
+        let dir_results = read_dir(context.resolve(EMOND_CLIENTS_PATH));
+
+        let read_dir = match dir_results {
+            Ok(dir) => dir,
+            Err(err) => {
+                error!("Failed to read Emond clients directory: {:?}", err);
+                return Err(EmondError::Path {
+                    path: String::from(EMOND_CLIENTS_PATH),
+                    source: err,
+                });
+            }
+        };
+
+        let mut clients: Vec<EmondClient> = Vec::new();
+        for dir in read_dir {
+            let entry = match dir {
+                Ok(results) => results,
+                Err(err) => {
+                    error!(
+                        "Could not get file entry in Emond client directory {}. Issue: {:?}",
+                        EMOND_CLIENTS_PATH, err
+                    );
+                    return Err(EmondError::Path {
+                        path: String::from(EMOND_CLIENTS_PATH),
+                        source: err,
+                    });
+                }
+            };
+
+            let source = format!(
+                "{}/{}",
+                EMOND_CLIENTS_PATH,
+                entry.file_name().to_string_lossy()
+            );
+            // Resolve the client under the root so a symlinked client is not read from the analysis host
+            let client_path = context.resolve(&source);
+            if !client_path.is_file() {
+                continue;
+            }
+            // Get the metadata before reading the file updates the access time
+            let metadata = FileMetadata::get_metadata(&client_path);
+            let host_path = client_path.display().to_string();
+            let data = if get_file_size(&host_path) {
+                match read(&client_path) {
+                    Ok(data) => Some(data),
+                    Err(err) => {
+                        warn!(
+                            "[macos-emond] Failed to read Emond client {}: {:?}",
+                            host_path, err
+                        );
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
+            let mut client = match data {
+                Some(data) => {
+                    let mut client = EmondClient::parse_client_bytes(&data, &source);
+                    if context.options().hashes {
+                        client.hashes = Some(Hashes::hash_data(&data));
+                    }
+                    client
+                }
+                None => EmondClient::new(&source),
+            };
+            client.metadata = metadata;
+            clients.push(client);
+        }
+        clients.sort_by(|first, second| first.name.cmp(&second.name));
+
+        Ok(EmondClients {
+            path: String::from(EMOND_CLIENTS_PATH),
+            enabled: !clients.is_empty(),
+            clients,
+        })
+    }
+}
+
+impl EmondClient {
+    /// Parse an Emond client file from data in memory. `source` labels where the data came from
+    pub fn parse_client_bytes(data: &[u8], source: &str) -> EmondClient {
+        let mut client = EmondClient::new(source);
+        client.plist = EmondClient::parse_client_plist(data);
+        client
+    }
+
+    /// Parse an Emond client file from a reader. `source` labels where the data came from
+    pub fn parse_client_reader<R: Read + Seek>(
+        reader: R,
+        source: &str,
+    ) -> Result<EmondClient, EmondError> {
+        // Client files do not need to be PLIST files, the nesting depth is checked when parsing the PLIST
+        let data = read_reader(reader, source)?;
+        Ok(EmondClient::parse_client_bytes(&data, source))
+    }
+
+    // Client with no data. The name is the last component of the source
+    fn new(source: &str) -> EmondClient {
+        let name = match source.rsplit_once('/') {
+            Some((_, name)) => name,
+            None => source,
+        };
+        EmondClient {
+            name: name.to_string(),
+            path: source.to_string(),
+            metadata: None,
+            hashes: None,
+            plist: None,
+        }
+    }
+
+    // Client files are usually empty, but try to parse any contents as a PLIST
+    fn parse_client_plist(data: &[u8]) -> Option<Value> {
+        if data.is_empty() || check_plist_depth(data, EMOND_CLIENTS_PATH).is_err() {
+            return None;
+        }
+        match plist::from_bytes(data) {
+            Ok(result) => Some(result),
+            Err(err) => {
+                warn!("[macos-emond] Emond client is not a PLIST file: {:?}", err);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::context::ParseContext;
+
+    use super::{EmondClient, EmondClients};
+
+    #[test]
+    fn test_parse_clients() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+        let context = ParseContext::new(&test_location.display().to_string());
+
+        let results = EmondClients::parse_clients(&context).unwrap();
+        assert!(results.enabled);
+        assert_eq!(results.path, "/private/var/db/emondClients");
+        assert_eq!(results.clients.len(), 1);
+        assert_eq!(results.clients[0].name, "com.update.client");
+        assert_eq!(
+            results.clients[0].path,
+            "/private/var/db/emondClients/com.update.client"
+        );
+        assert!(results.clients[0].hashes.is_none());
+        assert!(results.clients[0].metadata.is_some());
+
+        let client_plist = results.clients[0].plist.as_ref().unwrap();
+        assert_eq!(
+            client_plist
+                .as_dictionary()
+                .unwrap()
+                .get("name")
+                .unwrap()
+                .as_string(),
+            Some("update client")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_parse_clients_symlink_outside_root() {
+        let mut test_root = std::env::temp_dir();
+        test_root.push(format!("macos_emond_clients_{}", std::process::id()));
+        let mut clients_dir = test_root.clone();
+        clients_dir.push("private/var/db/emondClients");
+        std::fs::create_dir_all(&clients_dir).unwrap();
+        std::fs::write(clients_dir.join("client"), b"").unwrap();
+        // Absolute symlink to a file that only exists on the analysis host
+        std::os::unix::fs::symlink(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"),
+            clients_dir.join("outside"),
+        )
+        .unwrap();
+
+        let context = ParseContext::new(&test_root.display().to_string());
+        let results = EmondClients::parse_clients(&context);
+        std::fs::remove_dir_all(&test_root).unwrap();
+
+        let results = results.unwrap();
+        assert_eq!(results.clients.len(), 1);
+        assert_eq!(results.clients[0].name, "client");
+    }
+
+    #[test]
+    fn test_parse_clients_missing() {
+        let context = ParseContext::new("/tmp/macos_emond_missing_root");
+        let results = EmondClients::parse_clients(&context);
+        assert!(results.is_err());
+    }
+
+    #[test]
+    #[ignore = "Check live system if Emond is enabled"]
+    fn test_parse_clients_live() {
+        let results = EmondClients::parse_clients(&ParseContext::live()).unwrap();
+        assert!(!results.enabled);
+    }
+
+    #[test]
+    fn test_parse_client_bytes() {
+        let results = EmondClient::parse_client_bytes(b"", "collection.tar:emondClients/client");
+        assert_eq!(results.name, "client");
+        assert_eq!(results.path, "collection.tar:emondClients/client");
+        assert!(results.metadata.is_none());
+        assert!(results.hashes.is_none());
+        assert!(results.plist.is_none());
+
+        let reader = std::io::Cursor::new(b"test".to_vec());
+        let results = EmondClient::parse_client_reader(reader, "memory").unwrap();
+        assert_eq!(results.name, "memory");
+    }
+
+    #[test]
+    fn test_parse_client_plist() {
+        assert!(EmondClient::parse_client_plist(&[]).is_none());
+        assert!(EmondClient::parse_client_plist(b"not a plist").is_none());
+    }
+}
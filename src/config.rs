@@ -0,0 +1,344 @@
+//! Parse the macOS Emond config PLIST
+//!
+//! The config at `/etc/emond.d/emond.plist` controls where Emond looks for rules, where it logs and the initial global values
+
+use std::io::{Read, Seek};
+
+use log::error;
+use plist::Dictionary;
+use serde::Serialize;
+
+use crate::{
+    diagnostic::Expected,
+    error::EmondError,
+    hashes::Hashes,
+    reader::{check_plist_depth, read_plist_file, read_plist_reader},
+    util::{
+        get_boolean_value, get_dictionary_value, get_string_value, get_string_values,
+        insert_extra_value,
+    },
+};
+
+/// Location of the Emond config
+pub const EMOND_CONFIG_PATH: &str = "/etc/emond.d/emond.plist";
+
+/// Default location of the Emond rules
+pub const DEFAULT_RULES_PATH: &str = "/etc/emond.d/rules";
+
+/// Config keys and their expected value types
+const SETTINGS_KEYS: [(&str, Expected); 6] = [
+    ("additionalRulesPaths", Expected::StringArray),
+    ("errorLogPath", Expected::String),
+    ("eventLogPath", Expected::String),
+    ("filterByUID", Expected::String),
+    ("filterByGID", Expected::String),
+    ("logEvents", Expected::Boolean),
+];
+
+/// Top level config keys and their expected value types
+const CONFIG_KEYS: [(&str, Expected); 2] = [
+    ("config", Expected::Dictionary),
+    ("initialGlobals", Expected::Dictionary),
+];
+
+#[derive(Debug, Serialize)]
+pub struct EmondConfig {
+    /// Where the config was parsed from (ex: /etc/emond.d/emond.plist)
+    pub source: String,
+    /// Config file hashes, if hashing is enabled in the parse options
+    pub hashes: Option<Hashes>,
+    pub config: ConfigSettings,
+    pub initial_globals: InitialGlobals,
+    /// Top level keys not modeled above
+    pub extra: Dictionary,
+}
+
+/// Values in the `config` dictionary
+#[derive(Debug, Serialize)]
+pub struct ConfigSettings {
+    pub additional_rules_paths: Vec<String>,
+    pub error_log_path: String,
+    pub event_log_path: String,
+    pub filter_by_uid: String,
+    pub filter_by_gid: String,
+    pub log_events: bool,
+    /// Config keys not modeled above
+    pub extra: Dictionary,
+}
+
+/// Values in the `initialGlobals` dictionary
+#[derive(Debug, Serialize)]
+pub struct InitialGlobals {
+    pub notification_contacts: Vec<String>,
+    /// Global values not modeled above
+    pub extra: Dictionary,
+}
+
+impl EmondConfig {
+    /// Parse the Emond config PLIST file at provided path
+    pub fn parse_emond_config(path: &str) -> Result<EmondConfig, EmondError> {
+        let data = read_plist_file(path, path)?;
+        EmondConfig::parse_config_data(&data, path)
+    }
+
+    /// Parse the Emond config from PLIST data in memory. `source` labels where the data came from
+    pub fn parse_emond_config_bytes(data: &[u8], source: &str) -> Result<EmondConfig, EmondError> {
+        check_plist_depth(data, source)?;
+        EmondConfig::parse_config_data(data, source)
+    }
+
+    /// Parse the Emond config from a reader. `source` labels where the data came from
+    pub fn parse_emond_config_reader<R: Read + Seek>(
+        reader: R,
+        source: &str,
+    ) -> Result<EmondConfig, EmondError> {
+        let data = read_plist_reader(reader, source)?;
+        EmondConfig::parse_config_data(&data, source)
+    }
+
+    // Parse config data that has passed the nesting depth check
+    pub(crate) fn parse_config_data(data: &[u8], source: &str) -> Result<EmondConfig, EmondError> {
+        let emond_plist_result: Result<Dictionary, plist::Error> = plist::from_bytes(data);
+        let emond_plist = match emond_plist_result {
+            Ok(results) => results,
+            Err(err) => {
+                error!("Failed to parse Emond Config PLIST file: {:?}", err);
+                return Err(EmondError::Plist {
+                    path: source.to_string(),
+                    source: err,
+                });
+            }
+        };
+        let mut emond_config = EmondConfig::parse_config_dictionary(emond_plist);
+        emond_config.source = source.to_string();
+        Ok(emond_config)
+    }
+
+    /// Get the default Emond Rules directory and any additional paths at additionalRulesPaths
+    pub fn rules_paths(&self) -> Vec<String> {
+        let mut emond_rules_paths: Vec<String> = vec![String::from(DEFAULT_RULES_PATH)];
+        emond_rules_paths.append(&mut self.config.additional_rules_paths.clone());
+        emond_rules_paths
+    }
+
+    // Parse the top level Emond config dictionary
+    fn parse_config_dictionary(emond_plist: Dictionary) -> EmondConfig {
+        let mut emond_config = EmondConfig {
+            source: String::new(),
+            hashes: None,
+            config: ConfigSettings {
+                additional_rules_paths: Vec::new(),
+                error_log_path: String::new(),
+                event_log_path: String::new(),
+                filter_by_uid: String::new(),
+                filter_by_gid: String::new(),
+                log_events: false,
+                extra: Dictionary::new(),
+            },
+            initial_globals: InitialGlobals {
+                notification_contacts: Vec::new(),
+                extra: Dictionary::new(),
+            },
+            extra: Dictionary::new(),
+        };
+
+        for (key, value) in emond_plist {
+            if key == "config" && value.as_dictionary().is_some() {
+                emond_config.config = EmondConfig::parse_settings(get_dictionary_value(value));
+            } else if key == "initialGlobals" && value.as_dictionary().is_some() {
+                emond_config.initial_globals =
+                    EmondConfig::parse_initial_globals(get_dictionary_value(value));
+            } else {
+                insert_extra_value(
+                    &mut emond_config.extra,
+                    &CONFIG_KEYS,
+                    &key,
+                    &value,
+                    "Emond Config",
+                );
+            }
+        }
+        emond_config
+    }
+
+    // Parse the config dictionary
+    fn parse_settings(config_dictionary: Dictionary) -> ConfigSettings {
+        let mut settings = ConfigSettings {
+            additional_rules_paths: Vec::new(),
+            error_log_path: String::new(),
+            event_log_path: String::new(),
+            filter_by_uid: String::new(),
+            filter_by_gid: String::new(),
+            log_events: false,
+            extra: Dictionary::new(),
+        };
+
+        for (key, value) in config_dictionary {
+            if key == "additionalRulesPaths" && value.as_array().is_some() {
+                settings.additional_rules_paths = get_string_values(&value);
+            } else if key == "errorLogPath" && value.as_string().is_some() {
+                settings.error_log_path = get_string_value(&value);
+            } else if key == "eventLogPath" && value.as_string().is_some() {
+                settings.event_log_path = get_string_value(&value);
+            } else if key == "filterByUID" && value.as_string().is_some() {
+                settings.filter_by_uid = get_string_value(&value);
+            } else if key == "filterByGID" && value.as_string().is_some() {
+                settings.filter_by_gid = get_string_value(&value);
+            } else if key == "logEvents" && value.as_boolean().is_some() {
+                settings.log_events = get_boolean_value(&value);
+            } else {
+                insert_extra_value(
+                    &mut settings.extra,
+                    &SETTINGS_KEYS,
+                    &key,
+                    &value,
+                    "Emond Config setting",
+                );
+            }
+        }
+        settings
+    }
+
+    // Parse the initialGlobals dictionary
+    fn parse_initial_globals(globals_dictionary: Dictionary) -> InitialGlobals {
+        let mut globals = InitialGlobals {
+            notification_contacts: Vec::new(),
+            extra: Dictionary::new(),
+        };
+
+        for (key, value) in globals_dictionary {
+            if key == "notificationContacts" && value.as_array().is_some() {
+                globals.notification_contacts = get_string_values(&value);
+            } else {
+                globals.extra.insert(key, value);
+            }
+        }
+        globals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use plist::{Dictionary, Value};
+
+    use super::EmondConfig;
+
+    #[test]
+    fn test_parse_emond_config() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system/private/etc/emond.d/emond.plist");
+
+        let results =
+            EmondConfig::parse_emond_config(&test_location.display().to_string()).unwrap();
+        assert_eq!(results.source, test_location.display().to_string());
+        assert_eq!(
+            results.config.additional_rules_paths,
+            ["/usr/local/etc/emond.d/rules"]
+        );
+        assert_eq!(
+            results.config.error_log_path,
+            "/Library/Logs/EventMonitor/EventMonitor.error.log"
+        );
+        assert_eq!(
+            results.config.event_log_path,
+            "/Library/Logs/EventMonitor/EventMonitor.event.log"
+        );
+        assert_eq!(results.config.filter_by_uid, "");
+        assert_eq!(results.config.filter_by_gid, "");
+        assert!(!results.config.log_events);
+        assert!(results.config.extra.is_empty());
+        assert!(results.initial_globals.notification_contacts.is_empty());
+        assert!(results.extra.is_empty());
+
+        assert_eq!(
+            results.rules_paths(),
+            ["/etc/emond.d/rules", "/usr/local/etc/emond.d/rules"]
+        );
+    }
+
+    #[test]
+    fn test_parse_emond_config_bytes() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system/private/etc/emond.d/emond.plist");
+        let data = std::fs::read(test_location).unwrap();
+
+        let results =
+            EmondConfig::parse_emond_config_bytes(&data, "image.dmg:emond.plist").unwrap();
+        assert_eq!(results.source, "image.dmg:emond.plist");
+        assert_eq!(
+            results.config.additional_rules_paths,
+            ["/usr/local/etc/emond.d/rules"]
+        );
+
+        let results =
+            EmondConfig::parse_emond_config_reader(std::io::Cursor::new(data), "memory").unwrap();
+        assert_eq!(results.source, "memory");
+
+        let err = EmondConfig::parse_emond_config_bytes(b"<plist>", "memory").unwrap_err();
+        assert_eq!(err.path(), "memory");
+    }
+
+    #[test]
+    fn test_parse_config_dictionary_unknown_keys() {
+        let mut config = Dictionary::new();
+        config.insert(String::from("filterByUID"), Value::Integer(0.into()));
+        config.insert(
+            String::from("debugHook"),
+            Value::String(String::from("/tmp/hook")),
+        );
+        let mut globals = Dictionary::new();
+        globals.insert(
+            String::from("attackerHost"),
+            Value::String(String::from("example.com")),
+        );
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(String::from("config"), Value::Dictionary(config));
+        test_dictionary.insert(String::from("initialGlobals"), Value::Dictionary(globals));
+        test_dictionary.insert(String::from("version"), Value::Integer(2.into()));
+
+        let results = EmondConfig::parse_config_dictionary(test_dictionary);
+        assert_eq!(
+            results.config.extra.get("debugHook").unwrap().as_string(),
+            Some("/tmp/hook")
+        );
+        assert_eq!(
+            results
+                .initial_globals
+                .extra
+                .get("attackerHost")
+                .unwrap()
+                .as_string(),
+            Some("example.com")
+        );
+        assert_eq!(results.config.filter_by_uid, "");
+        assert_eq!(
+            results.config.extra.get("filterByUID").unwrap(),
+            &Value::Integer(0.into())
+        );
+        assert!(results.extra.contains_key("version"));
+        assert_eq!(results.rules_paths(), ["/etc/emond.d/rules"]);
+    }
+
+    #[test]
+    fn test_parse_config_dictionary_wrong_types() {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(
+            String::from("config"),
+            Value::String(String::from("/tmp/config.plist")),
+        );
+        test_dictionary.insert(String::from("initialGlobals"), Value::Array(Vec::new()));
+
+        let results = EmondConfig::parse_config_dictionary(test_dictionary);
+        assert!(results.config.extra.is_empty());
+        assert_eq!(
+            results.extra.get("config").unwrap().as_string(),
+            Some("/tmp/config.plist")
+        );
+        assert_eq!(
+            results.extra.get("initialGlobals").unwrap(),
+            &Value::Array(Vec::new())
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    fs::read_link,
+    path::{Component, Path, PathBuf},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of symlinks followed when resolving a path under an alternate root
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Location of the filesystem to parse Emond artifacts from.
+/// The live system uses `/`, mounted disk images and extracted collections use their mount or extraction directory
+#[derive(Debug, Clone)]
+pub struct ParseContext {
+    root: PathBuf,
+    options: ParseOptions,
+}
+
+/// Options that change how Emond files are parsed
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Salvage the rules from truncated or malformed XML rules files instead of failing the whole file
+    pub lenient_xml: bool,
+    /// Hash the rules files, config, client files and RunCommand targets
+    pub hashes: bool,
+    /// Resolve and inspect the file each RunCommand action runs
+    pub resolve_commands: bool,
+}
+
+/// Symlink followed while resolving a path. Both paths are on the analyzed system
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Symlink {
+    pub path: String,
+    pub target: String,
+}
+
+impl Default for ParseContext {
+    fn default() -> Self {
+        ParseContext::live()
+    }
+}
+
+impl ParseContext {
+    /// Parse Emond artifacts on the live system
+    pub fn live() -> ParseContext {
+        ParseContext {
+            root: PathBuf::from("/"),
+            options: ParseOptions::default(),
+        }
+    }
+
+    /// Parse Emond artifacts under an alternate filesystem root
+    pub fn new(root: &str) -> ParseContext {
+        ParseContext {
+            root: PathBuf::from(root),
+            options: ParseOptions::default(),
+        }
+    }
+
+    /// Use the provided parse options
+    pub fn with_options(mut self, options: ParseOptions) -> ParseContext {
+        self.options = options;
+        self
+    }
+
+    /// Options used when parsing files under the root
+    pub fn options(&self) -> &ParseOptions {
+        &self.options
+    }
+
+    /// Root directory all Emond paths are resolved against
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Check if the context points at the live system
+    pub fn is_live(&self) -> bool {
+        self.root == Path::new("/")
+    }
+
+    /// Resolve a path on the analyzed system to a path on the analysis host.
+    /// Falls back to the `/private` equivalent (or the reverse) if the path does not exist under the root.
+    /// Returns an empty path if a symlink in the path cannot be followed inside the root
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        let resolved = self.resolve_under_root(path);
+        if resolved.exists() {
+            return resolved;
+        }
+
+        if let Some(alias) = private_alias(path) {
+            let alias_resolved = self.resolve_under_root(&alias);
+            if alias_resolved.exists() {
+                return alias_resolved;
+            }
+        }
+        resolved
+    }
+
+    /// Resolve a path on the analyzed system like `resolve` and return every symlink followed on the way.
+    /// Symlinks are followed by the parser on the live system too, so the chain is always recorded
+    pub fn resolve_symlinks(&self, path: &str) -> (PathBuf, Vec<Symlink>) {
+        let resolved = self.follow_symlinks(path);
+        if resolved.0.exists() {
+            return resolved;
+        }
+
+        if let Some(alias) = private_alias(path) {
+            let alias_resolved = self.follow_symlinks(&alias);
+            if alias_resolved.0.exists() {
+                return alias_resolved;
+            }
+        }
+        resolved
+    }
+
+    // Resolve the path under the root, keeping any symlinks inside the root
+    fn resolve_under_root(&self, path: &str) -> PathBuf {
+        if self.is_live() {
+            return PathBuf::from(path);
+        }
+        self.follow_symlinks(path).0
+    }
+
+    // Follow every symlink in the path without leaving the root. A symlink that cannot be followed (too many hops or unreadable)
+    // gives an empty path, since the host would follow it outside the root
+    fn follow_symlinks(&self, path: &str) -> (PathBuf, Vec<Symlink>) {
+        let mut symlinks: Vec<Symlink> = Vec::new();
+        let mut remaining: VecDeque<OsString> = path_components(Path::new(path));
+        let mut resolved: Vec<OsString> = Vec::new();
+        let mut hops = 0;
+
+        while let Some(component) = remaining.pop_front() {
+            if component == ".." {
+                resolved.pop();
+                continue;
+            }
+
+            let mut candidate = self.root.clone();
+            candidate.extend(&resolved);
+            candidate.push(&component);
+
+            let is_symlink = match candidate.symlink_metadata() {
+                Ok(metadata) => metadata.file_type().is_symlink(),
+                Err(_) => false,
+            };
+            if !is_symlink {
+                resolved.push(component);
+                continue;
+            }
+            if hops >= MAX_SYMLINK_HOPS {
+                warn!(
+                    "[macos-emond] Too many symlinks resolving {} under {}",
+                    path,
+                    self.root.display()
+                );
+                return (PathBuf::new(), symlinks);
+            }
+
+            let target = match read_link(&candidate) {
+                Ok(result) => result,
+                Err(err) => {
+                    warn!(
+                        "[macos-emond] Failed to read symlink {}: {:?}",
+                        candidate.display(),
+                        err
+                    );
+                    return (PathBuf::new(), symlinks);
+                }
+            };
+            hops += 1;
+            symlinks.push(Symlink {
+                path: system_path(resolved.iter().chain([&component])),
+                target: target.display().to_string(),
+            });
+
+            // Absolute symlinks point to the analyzed system, not the analysis host
+            if target.is_absolute() {
+                resolved.clear();
+            }
+            let mut target_components = path_components(&target);
+            target_components.append(&mut remaining);
+            remaining = target_components;
+        }
+
+        let mut host_path = self.root.clone();
+        host_path.extend(resolved);
+        (host_path, symlinks)
+    }
+}
+
+// Join path components into an absolute path on the analyzed system
+fn system_path<'a>(components: impl Iterator<Item = &'a OsString>) -> String {
+    let mut path = String::new();
+    for component in components {
+        path.push('/');
+        path.push_str(&component.to_string_lossy());
+    }
+    path
+}
+
+// Split a path into its normal and parent components
+fn path_components(path: &Path) -> VecDeque<OsString> {
+    let mut components = VecDeque::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push_back(name.to_os_string()),
+            Component::ParentDir => components.push_back(OsString::from("..")),
+            _ => continue,
+        }
+    }
+    components
+}
+
+// macOS stores /etc, /var and /tmp under /private. Get the other spelling of the path
+fn private_alias(path: &str) -> Option<String> {
+    let aliased_dirs = ["/etc", "/var", "/tmp"];
+    for dir in aliased_dirs {
+        if path == dir || path.starts_with(&format!("{}/", dir)) {
+            return Some(format!("/private{}", path));
+        }
+        let private_dir = format!("/private{}", dir);
+        if path == private_dir || path.starts_with(&format!("{}/", private_dir)) {
+            return Some(path.trim_start_matches("/private").to_string());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{private_alias, ParseContext, Symlink};
+
+    #[test]
+    fn test_resolve_live() {
+        let context = ParseContext::live();
+        assert!(context.is_live());
+        assert_eq!(
+            context.resolve("/etc/emond.d/emond.plist"),
+            PathBuf::from("/etc/emond.d/emond.plist")
+        );
+    }
+
+    #[test]
+    fn test_resolve_private_alias() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+
+        let context = ParseContext::new(&test_location.display().to_string());
+        assert!(!context.is_live());
+
+        let results = context.resolve("/etc/emond.d/emond.plist");
+        test_location.push("private/etc/emond.d/emond.plist");
+        assert_eq!(results, test_location);
+    }
+
+    #[test]
+    fn test_resolve_missing() {
+        let context = ParseContext::new("/tmp/missing_root");
+        assert_eq!(
+            context.resolve("/etc/emond.d/rules"),
+            PathBuf::from("/tmp/missing_root/etc/emond.d/rules")
+        );
+        assert_eq!(
+            context.resolve("../../etc/passwd"),
+            PathBuf::from("/tmp/missing_root/etc/passwd")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_resolve_absolute_symlink() {
+        let mut test_root = std::env::temp_dir();
+        test_root.push(format!("macos_emond_context_{}", std::process::id()));
+        let mut private_etc = test_root.clone();
+        private_etc.push("private/etc/emond.d");
+        std::fs::create_dir_all(&private_etc).unwrap();
+
+        let mut etc_link = test_root.clone();
+        etc_link.push("etc");
+        std::os::unix::fs::symlink("/private/etc", &etc_link).unwrap();
+
+        let context = ParseContext::new(&test_root.display().to_string());
+        let results = context.resolve("/etc/emond.d");
+        std::fs::remove_dir_all(&test_root).unwrap();
+
+        assert_eq!(results, private_etc);
+    }
+
+    #[test]
+    fn test_resolve_symlinks() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+        let context = ParseContext::new(&test_location.display().to_string());
+
+        let (results, symlinks) = context.resolve_symlinks("/usr/local/bin/update");
+        test_location.push("private/tmp/.update.sh");
+        assert_eq!(results, test_location);
+        assert_eq!(
+            symlinks,
+            [Symlink {
+                path: String::from("/usr/local/bin/update"),
+                target: String::from("/private/tmp/.update.sh"),
+            }]
+        );
+
+        let (_, symlinks) = context.resolve_symlinks("/private/tmp/.update.sh");
+        assert!(symlinks.is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_resolve_symlink_loop() {
+        let mut test_root = std::env::temp_dir();
+        test_root.push(format!("macos_emond_context_loop_{}", std::process::id()));
+        std::fs::create_dir_all(&test_root).unwrap();
+
+        let mut loop_link = test_root.clone();
+        loop_link.push("loop");
+        std::os::unix::fs::symlink("/loop", &loop_link).unwrap();
+
+        let context = ParseContext::new(&test_root.display().to_string());
+        let results = context.resolve("/loop/emond.plist");
+        let (_, symlinks) = context.resolve_symlinks("/loop");
+        std::fs::remove_dir_all(&test_root).unwrap();
+
+        assert_eq!(results, PathBuf::new());
+        assert_eq!(symlinks.len(), 40);
+    }
+
+    #[test]
+    fn test_private_alias() {
+        assert_eq!(
+            private_alias("/etc/emond.d/rules").unwrap(),
+            "/private/etc/emond.d/rules"
+        );
+        assert_eq!(
+            private_alias("/private/var/db/emondClients").unwrap(),
+            "/var/db/emondClients"
+        );
+        assert_eq!(private_alias("/etcetera"), None);
+        assert_eq!(private_alias("/Library/Logs"), None);
+    }
+}
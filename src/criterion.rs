@@ -0,0 +1,443 @@
+//! Parse Emond Rule criteria
+//!
+//! Criteria decide if a rule fires for an event. Each criterion compares an event value against a rule value using an operator,
+//! or combines nested criteria with `And`, `Or` and `Not`
+
+use log::warn;
+use plist::{Dictionary, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    diagnostic::{
+        check_dictionary, check_dictionary_array, check_required, child_path, Diagnostic, Expected,
+        Severity,
+    },
+    util::{
+        get_key_order, get_string_value, insert_extra_element, insert_extra_value,
+        insert_extra_values_without_elements, insert_string_value, merge_extra_elements,
+        order_dictionary, write_value,
+    },
+};
+
+/// Criterion keys and their expected value types
+const CRITERION_KEYS: [(&str, Expected); 6] = [
+    ("operator", Expected::String),
+    ("eventKey", Expected::String),
+    ("eventType", Expected::String),
+    ("value", Expected::Any),
+    ("valueType", Expected::String),
+    ("criterion", Expected::Array),
+];
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    LessThan,
+    LessThanOrEqual,
+    Like,
+    NotLike,
+    Contains,
+    NotContains,
+    BeginsWith,
+    EndsWith,
+    True,
+    False,
+    And,
+    Or,
+    Not,
+    /// Operator not known to this parser. The original operator string is kept
+    Unknown(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Criterion {
+    pub operator: Operator,
+    /// Event value the criterion is checked against
+    pub event_key: String,
+    pub event_type: String,
+    pub value: Option<Value>,
+    pub value_type: String,
+    /// Nested criteria for `And`, `Or` and `Not`
+    pub criterion: Vec<Criterion>,
+    /// Criterion keys not modeled above
+    pub extra: Dictionary,
+    /// Keys in the order they were parsed, so the criterion is written back the same way
+    #[serde(default)]
+    pub key_order: Vec<String>,
+}
+
+impl Operator {
+    /// Get the Operator for an Emond operator string
+    pub fn parse_operator(operator: &str) -> Operator {
+        match operator {
+            "Equal" => Operator::Equal,
+            "NotEqual" => Operator::NotEqual,
+            "GreaterThan" => Operator::GreaterThan,
+            "GreaterThanOrEqual" => Operator::GreaterThanOrEqual,
+            "LessThan" => Operator::LessThan,
+            "LessThanOrEqual" => Operator::LessThanOrEqual,
+            "Like" => Operator::Like,
+            "NotLike" => Operator::NotLike,
+            "Contains" => Operator::Contains,
+            "NotContains" => Operator::NotContains,
+            "BeginsWith" => Operator::BeginsWith,
+            "EndsWith" => Operator::EndsWith,
+            "True" => Operator::True,
+            "False" => Operator::False,
+            "And" => Operator::And,
+            "Or" => Operator::Or,
+            "Not" => Operator::Not,
+            _ => {
+                warn!("Unknown Emond Criterion operator: {}", operator);
+                Operator::Unknown(operator.to_string())
+            }
+        }
+    }
+
+    /// Get the Emond operator string
+    pub fn as_str(&self) -> &str {
+        match self {
+            Operator::Equal => "Equal",
+            Operator::NotEqual => "NotEqual",
+            Operator::GreaterThan => "GreaterThan",
+            Operator::GreaterThanOrEqual => "GreaterThanOrEqual",
+            Operator::LessThan => "LessThan",
+            Operator::LessThanOrEqual => "LessThanOrEqual",
+            Operator::Like => "Like",
+            Operator::NotLike => "NotLike",
+            Operator::Contains => "Contains",
+            Operator::NotContains => "NotContains",
+            Operator::BeginsWith => "BeginsWith",
+            Operator::EndsWith => "EndsWith",
+            Operator::True => "True",
+            Operator::False => "False",
+            Operator::And => "And",
+            Operator::Or => "Or",
+            Operator::Not => "Not",
+            Operator::Unknown(operator) => operator,
+        }
+    }
+
+    /// Check if the operator combines nested criteria
+    pub fn is_logical(&self) -> bool {
+        matches!(self, Operator::And | Operator::Or | Operator::Not)
+    }
+}
+
+impl Criterion {
+    /// Create a criterion with only an operator. Set the other values as needed
+    pub fn new(operator: Operator) -> Criterion {
+        Criterion {
+            operator,
+            event_key: String::new(),
+            event_type: String::new(),
+            value: None,
+            value_type: String::new(),
+            criterion: Vec::new(),
+            extra: Dictionary::new(),
+            key_order: Vec::new(),
+        }
+    }
+
+    /// Parse an array of Emond criterion dictionaries. Elements that are not dictionaries are kept in `extra` under their
+    /// position (ex: criterion[1])
+    pub fn parse_criteria(value: &Value, extra: &mut Dictionary) -> Vec<Criterion> {
+        let mut criteria: Vec<Criterion> = Vec::new();
+        let elements = match value.as_array() {
+            Some(results) => results,
+            None => return criteria,
+        };
+        for (index, element) in elements.iter().enumerate() {
+            match element.as_dictionary() {
+                Some(criterion_dictionary) => {
+                    criteria.push(Criterion::parse_criterion(criterion_dictionary))
+                }
+                None => insert_extra_element(extra, "criterion", index, element),
+            }
+        }
+        criteria
+    }
+
+    /// Parse a single Emond criterion dictionary
+    pub fn parse_criterion(criterion_dictionary: &Dictionary) -> Criterion {
+        let mut criterion = Criterion::new(Operator::Unknown(String::new()));
+        criterion.key_order = get_key_order(criterion_dictionary);
+
+        for (key, criterion_value) in criterion_dictionary {
+            if key == "operator" && criterion_value.as_string().is_some() {
+                criterion.operator = Operator::parse_operator(&get_string_value(criterion_value));
+            } else if key == "eventKey" && criterion_value.as_string().is_some() {
+                criterion.event_key = get_string_value(criterion_value);
+            } else if key == "eventType" && criterion_value.as_string().is_some() {
+                criterion.event_type = get_string_value(criterion_value);
+            } else if key == "value" {
+                criterion.value = Some(criterion_value.clone());
+            } else if key == "valueType" && criterion_value.as_string().is_some() {
+                criterion.value_type = get_string_value(criterion_value);
+            } else if key == "criterion" && criterion_value.as_array().is_some() {
+                criterion.criterion =
+                    Criterion::parse_criteria(criterion_value, &mut criterion.extra);
+            } else {
+                insert_extra_value(
+                    &mut criterion.extra,
+                    &CRITERION_KEYS,
+                    key,
+                    criterion_value,
+                    "Emond Criterion",
+                );
+            }
+        }
+        criterion
+    }
+
+    /// Check an Emond criterion dictionary, and its nested criteria, for missing, unknown and wrongly typed values
+    pub fn diagnostics(criterion_dictionary: &Dictionary, key_path: &str) -> Vec<Diagnostic> {
+        let mut diagnostics = check_dictionary(criterion_dictionary, key_path, &CRITERION_KEYS);
+        diagnostics.append(&mut check_required(
+            criterion_dictionary,
+            key_path,
+            &[("operator", Expected::String)],
+            Severity::Warning,
+        ));
+
+        if let Some(Value::String(operator)) = criterion_dictionary.get("operator") {
+            if let Operator::Unknown(_) = Operator::parse_operator(operator) {
+                diagnostics.push(Diagnostic::unknown_value(
+                    &child_path(key_path, "operator"),
+                    "operator",
+                    operator,
+                ));
+            }
+        }
+        if let Some(nested) = criterion_dictionary.get("criterion") {
+            diagnostics.append(&mut check_dictionary_array(
+                nested,
+                &child_path(key_path, "criterion"),
+                Criterion::diagnostics,
+            ));
+        }
+        diagnostics
+    }
+
+    /// Convert the criterion back into an Emond criterion dictionary
+    pub fn to_dictionary(&self) -> Dictionary {
+        let key_order = &self.key_order;
+        let mut criterion_dictionary = Dictionary::new();
+        insert_string_value(
+            &mut criterion_dictionary,
+            "operator",
+            self.operator.as_str(),
+            key_order,
+        );
+        insert_string_value(
+            &mut criterion_dictionary,
+            "eventKey",
+            &self.event_key,
+            key_order,
+        );
+        insert_string_value(
+            &mut criterion_dictionary,
+            "eventType",
+            &self.event_type,
+            key_order,
+        );
+        if let Some(value) = &self.value {
+            criterion_dictionary.insert(String::from("value"), value.clone());
+        }
+        insert_string_value(
+            &mut criterion_dictionary,
+            "valueType",
+            &self.value_type,
+            key_order,
+        );
+        let nested: Vec<Value> = self
+            .criterion
+            .iter()
+            .map(|criterion| Value::Dictionary(criterion.to_dictionary()))
+            .collect();
+        let nested = merge_extra_elements(nested, &self.extra, "criterion");
+        if write_value(key_order, "criterion", nested.is_empty()) {
+            criterion_dictionary.insert(String::from("criterion"), Value::Array(nested));
+        }
+        insert_extra_values_without_elements(
+            &mut criterion_dictionary,
+            &self.extra,
+            &["criterion"],
+        );
+        order_dictionary(criterion_dictionary, key_order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plist::{Dictionary, Value};
+
+    use crate::diagnostic::DiagnosticKind;
+
+    use super::{Criterion, Operator};
+
+    #[test]
+    fn test_parse_operator() {
+        assert_eq!(Operator::parse_operator("Equal"), Operator::Equal);
+        assert_eq!(Operator::parse_operator("Not"), Operator::Not);
+        assert_eq!(
+            Operator::parse_operator("Matches"),
+            Operator::Unknown(String::from("Matches"))
+        );
+        assert_eq!(Operator::parse_operator("Matches").as_str(), "Matches");
+        assert!(Operator::Or.is_logical());
+        assert!(!Operator::Like.is_logical());
+    }
+
+    #[test]
+    fn test_parse_criterion() {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(
+            String::from("operator"),
+            Value::String(String::from("Equal")),
+        );
+        test_dictionary.insert(
+            String::from("eventKey"),
+            Value::String(String::from("username")),
+        );
+        test_dictionary.insert(String::from("value"), Value::String(String::from("root")));
+        test_dictionary.insert(
+            String::from("valueType"),
+            Value::String(String::from("string")),
+        );
+        test_dictionary.insert(String::from("hidden"), Value::Boolean(true));
+
+        let results = Criterion::parse_criterion(&test_dictionary);
+        assert_eq!(results.operator, Operator::Equal);
+        assert_eq!(results.event_key, "username");
+        assert_eq!(results.value, Some(Value::String(String::from("root"))));
+        assert_eq!(results.value_type, "string");
+        assert_eq!(results.extra.get("hidden"), Some(&Value::Boolean(true)));
+        assert_eq!(results.to_dictionary(), test_dictionary);
+    }
+
+    #[test]
+    fn test_parse_nested_criteria() {
+        let mut true_dictionary = Dictionary::new();
+        true_dictionary.insert(
+            String::from("operator"),
+            Value::String(String::from("True")),
+        );
+        let mut unknown_dictionary = Dictionary::new();
+        unknown_dictionary.insert(
+            String::from("operator"),
+            Value::String(String::from("Matches")),
+        );
+        let mut or_dictionary = Dictionary::new();
+        or_dictionary.insert(String::from("operator"), Value::String(String::from("Or")));
+        or_dictionary.insert(
+            String::from("criterion"),
+            Value::Array(vec![
+                Value::Dictionary(true_dictionary),
+                Value::Dictionary(unknown_dictionary),
+            ]),
+        );
+
+        let test_value = Value::Array(vec![Value::Dictionary(or_dictionary.clone())]);
+        let mut extra = Dictionary::new();
+        let results = Criterion::parse_criteria(&test_value, &mut extra);
+        assert!(extra.is_empty());
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].operator, Operator::Or);
+        assert_eq!(results[0].criterion.len(), 2);
+        assert_eq!(results[0].criterion[0].operator, Operator::True);
+        assert_eq!(
+            results[0].criterion[1].operator,
+            Operator::Unknown(String::from("Matches"))
+        );
+        assert_eq!(results[0].to_dictionary(), or_dictionary);
+    }
+
+    #[test]
+    fn test_parse_criteria_not_array() {
+        let results =
+            Criterion::parse_criteria(&Value::String(String::from("True")), &mut Dictionary::new());
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_parse_criteria_not_dictionary() {
+        let mut true_dictionary = Dictionary::new();
+        true_dictionary.insert(
+            String::from("operator"),
+            Value::String(String::from("True")),
+        );
+        let mut or_dictionary = Dictionary::new();
+        or_dictionary.insert(String::from("operator"), Value::String(String::from("Or")));
+        or_dictionary.insert(
+            String::from("criterion"),
+            Value::Array(vec![
+                Value::String(String::from("True")),
+                Value::Dictionary(true_dictionary.clone()),
+            ]),
+        );
+
+        let test_value = Value::Array(vec![
+            Value::Dictionary(or_dictionary.clone()),
+            Value::Integer(1.into()),
+        ]);
+        let mut extra = Dictionary::new();
+        let results = Criterion::parse_criteria(&test_value, &mut extra);
+        assert_eq!(results.len(), 1);
+        assert_eq!(extra.len(), 1);
+        assert_eq!(extra.get("criterion[1]"), Some(&Value::Integer(1.into())));
+        assert_eq!(results[0].criterion.len(), 1);
+        assert_eq!(
+            results[0].extra.get("criterion[0]"),
+            Some(&Value::String(String::from("True")))
+        );
+        assert_eq!(results[0].to_dictionary(), or_dictionary);
+    }
+
+    #[test]
+    fn test_parse_criterion_wrong_types() {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(String::from("operator"), Value::Integer(1.into()));
+        test_dictionary.insert(String::from("eventKey"), Value::Boolean(true));
+        test_dictionary.insert(
+            String::from("criterion"),
+            Value::String(String::from("True")),
+        );
+
+        let results = Criterion::parse_criterion(&test_dictionary);
+        assert_eq!(results.operator, Operator::Unknown(String::new()));
+        assert!(results.event_key.is_empty());
+        assert!(results.criterion.is_empty());
+        assert_eq!(results.extra, test_dictionary);
+        assert_eq!(results.to_dictionary(), test_dictionary);
+    }
+
+    #[test]
+    fn test_criterion_diagnostics() {
+        let mut nested_dictionary = Dictionary::new();
+        nested_dictionary.insert(
+            String::from("operator"),
+            Value::String(String::from("Matches")),
+        );
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(String::from("operator"), Value::String(String::from("Or")));
+        test_dictionary.insert(
+            String::from("criterion"),
+            Value::Array(vec![
+                Value::Dictionary(nested_dictionary),
+                Value::String(String::from("True")),
+            ]),
+        );
+
+        let results = Criterion::diagnostics(&test_dictionary, "criterion[0]");
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].kind, DiagnosticKind::UnknownValue);
+        assert_eq!(results[0].key_path, "criterion[0].criterion[0].operator");
+        assert_eq!(results[1].kind, DiagnosticKind::WrongType);
+        assert_eq!(results[1].key_path, "criterion[0].criterion[1]");
+        assert_eq!(results[1].expected, "dictionary");
+    }
+}
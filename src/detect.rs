@@ -0,0 +1,379 @@
+//! Score Emond Rules for persistence triage
+//!
+//! Emond runs RunCommand actions as root when a rule fires, which attackers use for persistence (MITRE ATT&CK T1546.014).
+//! Each rule gets indicators with a score and the reason it was raised. Scores add up to at most 100
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{
+    actions::{command::Command, Action},
+    config::DEFAULT_RULES_PATH,
+    emond::EmondData,
+    snapshot::EmondSnapshot,
+};
+
+/// MITRE ATT&CK technique for Event Triggered Execution: Emond
+pub const MITRE_TECHNIQUE: &str = "T1546.014";
+
+/// Highest score a rule can get
+pub const MAX_SCORE: u32 = 100;
+
+/// Rule names in Apple's default `SampleRules.plist`
+pub const APPLE_RULE_NAMES: [&str; 1] = ["sample rule"];
+
+/// Directories any user can write to. Commands in them can be replaced without root
+pub const WRITABLE_PATHS: [&str; 6] = [
+    "/tmp/",
+    "/private/tmp/",
+    "/var/tmp/",
+    "/private/var/tmp/",
+    "/Users/",
+    "/Library/Caches/",
+];
+
+/// Tools used to download payloads, open connections or run scripts
+pub const NETWORK_TOOLS: [&str; 10] = [
+    "nc",
+    "ncat",
+    "netcat",
+    "curl",
+    "wget",
+    "osascript",
+    "ssh",
+    "scp",
+    "telnet",
+    "socat",
+];
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+pub enum IndicatorKind {
+    /// Enabled rule runs a command
+    EnabledRunCommand,
+    /// Command is in a temporary or user writable directory
+    WritableCommandPath,
+    /// Command, arguments or script use a network or scripting tool
+    NetworkTool,
+    /// Rules file is outside the default rules directory
+    NonDefaultRulesDirectory,
+    /// Rule is not one of Apple's sample rules
+    NonAppleRule,
+    /// Emond is enabled by files in the clients directory
+    ClientsPresent,
+}
+
+impl IndicatorKind {
+    /// Score added to a rule for the indicator
+    pub fn score(&self) -> u32 {
+        match self {
+            IndicatorKind::EnabledRunCommand => 30,
+            IndicatorKind::WritableCommandPath => 25,
+            IndicatorKind::NetworkTool => 25,
+            IndicatorKind::NonDefaultRulesDirectory => 15,
+            IndicatorKind::NonAppleRule => 10,
+            IndicatorKind::ClientsPresent => 10,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq, Clone)]
+pub struct Indicator {
+    pub kind: IndicatorKind,
+    pub score: u32,
+    /// Location of the value in the rule (ex: actions[0].command). Empty for indicators about the whole rule
+    pub key_path: String,
+    /// Why the indicator was raised
+    pub rationale: String,
+}
+
+/// Triage result of a single rule
+#[derive(Debug, Serialize, PartialEq, Clone)]
+pub struct Detection {
+    /// Rules file the rule was parsed from
+    pub file: String,
+    pub rule_name: String,
+    /// Position of the rule within its rules file
+    pub rule_index: usize,
+    /// Sum of the indicator scores, at most `MAX_SCORE`
+    pub score: u32,
+    /// MITRE ATT&CK technique the rule is tagged with
+    pub technique: String,
+    pub indicators: Vec<Indicator>,
+}
+
+impl fmt::Display for Detection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Score {}", self.score)?;
+        if !self.file.is_empty() {
+            write!(f, " {}", self.file)?;
+        }
+        write!(
+            f,
+            " rule {} ({}) [{}]",
+            self.rule_index, self.rule_name, self.technique
+        )
+    }
+}
+
+impl Detection {
+    fn add(&mut self, kind: IndicatorKind, key_path: &str, rationale: String) {
+        self.indicators.push(Indicator {
+            kind,
+            score: kind.score(),
+            key_path: key_path.to_string(),
+            rationale,
+        });
+        self.score = (self.score + kind.score()).min(MAX_SCORE);
+    }
+}
+
+/// Score Emond Rules. Detections are in rule order
+pub fn detect_rules(rules: &[EmondData]) -> Vec<Detection> {
+    rules.iter().map(detect_rule).collect()
+}
+
+/// Score every rule in a snapshot. Rules with indicators get another if Emond is enabled by client files
+pub fn detect_snapshot(snapshot: &EmondSnapshot) -> Vec<Detection> {
+    let mut detections = detect_rules(&snapshot.rules);
+    let clients = match &snapshot.clients {
+        Some(clients) if clients.enabled => clients,
+        _ => return detections,
+    };
+    for detection in detections
+        .iter_mut()
+        .filter(|detection| detection.score > 0)
+    {
+        detection.add(
+            IndicatorKind::ClientsPresent,
+            "",
+            format!(
+                "Emond is enabled by {} client file(s) in {}",
+                clients.clients.len(),
+                clients.path
+            ),
+        );
+    }
+    detections
+}
+
+/// Score a single Emond Rule
+pub fn detect_rule(rule: &EmondData) -> Detection {
+    let mut detection = Detection {
+        file: rule.source_file.clone(),
+        rule_name: rule.name.clone(),
+        rule_index: rule.rule_index,
+        score: 0,
+        technique: String::from(MITRE_TECHNIQUE),
+        indicators: Vec::new(),
+    };
+
+    if !APPLE_RULE_NAMES.contains(&rule.name.as_str()) {
+        detection.add(
+            IndicatorKind::NonAppleRule,
+            "name",
+            format!(
+                "Rule name {} does not match a rule in Apple's SampleRules.plist",
+                rule.name
+            ),
+        );
+    }
+
+    let default_directories = [DEFAULT_RULES_PATH, "/private/etc/emond.d/rules"];
+    if !rule.rules_directory.is_empty()
+        && !default_directories.contains(&rule.rules_directory.as_str())
+    {
+        detection.add(
+            IndicatorKind::NonDefaultRulesDirectory,
+            "",
+            format!(
+                "Rules file is in {}, outside the default rules directory {}",
+                rule.rules_directory, DEFAULT_RULES_PATH
+            ),
+        );
+    }
+
+    for rule_action in &rule.actions {
+        if let Action::Command(command) = &rule_action.action {
+            let key_path = format!("actions[{}]", rule_action.index);
+            detect_command(&mut detection, rule, command, &key_path);
+        }
+    }
+    detection
+}
+
+// Score a RunCommand action
+fn detect_command(detection: &mut Detection, rule: &EmondData, command: &Command, key_path: &str) {
+    let command_path = format!("{}.command", key_path);
+    if rule.enabled {
+        detection.add(
+            IndicatorKind::EnabledRunCommand,
+            &command_path,
+            format!(
+                "Enabled rule runs {} as {}",
+                command.command,
+                if command.user.is_empty() {
+                    "root"
+                } else {
+                    &command.user
+                }
+            ),
+        );
+    }
+
+    // A symlink can point a harmless looking command at a writable location
+    let mut paths = vec![command.command.as_str()];
+    if let Some(target) = &command.target {
+        paths.push(&target.resolved_path);
+    }
+    if let Some(path) = paths.into_iter().find(|path| writable_path(path)) {
+        detection.add(
+            IndicatorKind::WritableCommandPath,
+            &command_path,
+            format!("Command runs {} from a user writable directory", path),
+        );
+    }
+
+    let mut tools = network_tools(&command.command);
+    for argument in &command.arguements {
+        tools.append(&mut network_tools(argument));
+    }
+    if let Some(preview) = command
+        .target
+        .as_ref()
+        .and_then(|target| target.preview.as_ref())
+    {
+        tools.append(&mut network_tools(preview));
+    }
+    tools.sort_unstable();
+    tools.dedup();
+    if !tools.is_empty() {
+        detection.add(
+            IndicatorKind::NetworkTool,
+            key_path,
+            format!("Command uses {}", tools.join(", ")),
+        );
+    }
+}
+
+// Check if the path is in a temporary or user writable directory
+fn writable_path(path: &str) -> bool {
+    WRITABLE_PATHS.iter().any(|prefix| path.starts_with(prefix))
+}
+
+// Find the network and scripting tools run in a command line or script
+fn network_tools(text: &str) -> Vec<&'static str> {
+    let separators = |character: char| {
+        character.is_whitespace()
+            || ['|', ';', '&', '(', ')', '`', '\'', '"', '$'].contains(&character)
+    };
+    let mut tools: Vec<&'static str> = Vec::new();
+    for word in text.split(separators) {
+        let program = word.rsplit('/').next().unwrap_or_default();
+        if let Some(tool) = NETWORK_TOOLS.iter().find(|tool| **tool == program) {
+            tools.push(tool);
+        }
+    }
+    tools
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        actions::command::Command,
+        builder::EmondRuleBuilder,
+        context::{ParseContext, ParseOptions},
+        snapshot::EmondSnapshot,
+    };
+
+    use super::{
+        detect_rule, detect_snapshot, network_tools, writable_path, IndicatorKind, MAX_SCORE,
+        MITRE_TECHNIQUE,
+    };
+
+    #[test]
+    fn test_detect_snapshot() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+        let context =
+            ParseContext::new(&test_location.display().to_string()).with_options(ParseOptions {
+                resolve_commands: true,
+                ..Default::default()
+            });
+
+        let snapshot = EmondSnapshot::collect(&context);
+        let results = detect_snapshot(&snapshot);
+        assert_eq!(results.len(), 2);
+
+        // Apple's disabled sample rule is not suspicious, even though Emond is enabled
+        assert_eq!(results[0].rule_name, "sample rule");
+        assert_eq!(results[0].score, 0);
+        assert!(results[0].indicators.is_empty());
+
+        assert_eq!(results[1].rule_name, "update rule");
+        assert_eq!(results[1].technique, MITRE_TECHNIQUE);
+        assert_eq!(results[1].score, MAX_SCORE);
+        let kinds: Vec<IndicatorKind> = results[1]
+            .indicators
+            .iter()
+            .map(|indicator| indicator.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                IndicatorKind::NonAppleRule,
+                IndicatorKind::NonDefaultRulesDirectory,
+                IndicatorKind::EnabledRunCommand,
+                IndicatorKind::WritableCommandPath,
+                IndicatorKind::NetworkTool,
+                IndicatorKind::ClientsPresent,
+            ]
+        );
+        assert_eq!(results[1].indicators[4].rationale, "Command uses curl");
+        assert_eq!(
+            results[1].to_string(),
+            "Score 100 /usr/local/etc/emond.d/rules/Persistence.plist rule 0 (update rule) [T1546.014]"
+        );
+    }
+
+    #[test]
+    fn test_detect_rule() {
+        let rule = EmondRuleBuilder::new("sample rule")
+            .enabled(false)
+            .event_type("startup")
+            .run_command(Command {
+                command: String::from("/bin/sh"),
+                arguements: vec![String::from("-c"), String::from("/usr/bin/nc -l 4444")],
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+
+        let results = detect_rule(&rule);
+        assert_eq!(results.score, 25);
+        assert_eq!(results.indicators.len(), 1);
+        assert_eq!(results.indicators[0].kind, IndicatorKind::NetworkTool);
+        assert_eq!(results.indicators[0].key_path, "actions[0]");
+        assert!(results.file.is_empty());
+    }
+
+    #[test]
+    fn test_writable_path() {
+        assert!(writable_path("/tmp/.update.sh"));
+        assert!(writable_path("/Users/sur/Library/Python/payload.sh"));
+        assert!(!writable_path("/usr/local/bin/update"));
+        assert!(!writable_path("/tmpfile"));
+    }
+
+    #[test]
+    fn test_network_tools() {
+        assert_eq!(
+            network_tools("curl -s https://example.com | /usr/bin/osascript -"),
+            ["curl", "osascript"]
+        );
+        assert_eq!(network_tools("$(wget -q example.com)"), ["wget"]);
+        assert!(network_tools("/usr/bin/ncurses-config --curl").is_empty());
+    }
+}
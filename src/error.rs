@@ -1,26 +1,197 @@
-use std::fmt;
+use std::{error::Error, fmt, io};
+
+use crate::diagnostic::Diagnostic;
 
 #[derive(Debug)]
 pub enum EmondError {
-    Path,
-    Plist,
-    Rule,
-    EventType,
-    ActionArray,
-    ActionDictionary,
+    /// Directory or file could not be read
+    Path { path: String, source: io::Error },
+    /// File or reader contents could not be read
+    Read { path: String, source: io::Error },
+    /// File is not a valid PLIST
+    Plist { path: String, source: plist::Error },
+    /// PLIST data is nested deeper than `limit` arrays or dictionaries
+    Nesting { path: String, limit: usize },
+    /// Rules could not be written as a PLIST
+    Write { path: String, source: plist::Error },
+    /// Detection rule file could not be parsed or has an invalid field, value or condition
+    DetectionRule { path: String, message: String },
+    /// Rule built with `EmondRuleBuilder` is not valid
+    Build {
+        name: String,
+        diagnostics: Vec<Diagnostic>,
+    },
+    /// Rules file is not an array of rules, or the rule at `rule_index` is not a dictionary
+    Rule {
+        path: String,
+        rule_index: Option<usize>,
+    },
+    /// Rule in a malformed XML rules file could not be salvaged
+    Salvage {
+        path: String,
+        rule_index: usize,
+        source: plist::Error,
+    },
+    /// Rule actions are not an array
+    ActionArray {
+        path: String,
+        rule_index: usize,
+        key: String,
+    },
 }
 
-impl std::error::Error for EmondError {}
+impl EmondError {
+    /// Path of the file or directory the error occurred in. Empty for rules that are not in a file
+    pub fn path(&self) -> &str {
+        match self {
+            EmondError::Build { .. } => "",
+            EmondError::Path { path, .. }
+            | EmondError::Read { path, .. }
+            | EmondError::Plist { path, .. }
+            | EmondError::Nesting { path, .. }
+            | EmondError::Write { path, .. }
+            | EmondError::DetectionRule { path, .. }
+            | EmondError::Rule { path, .. }
+            | EmondError::Salvage { path, .. }
+            | EmondError::ActionArray { path, .. } => path,
+        }
+    }
+
+    /// Position of the rule within its rules file, if the error is in a rule
+    pub fn rule_index(&self) -> Option<usize> {
+        match self {
+            EmondError::Path { .. }
+            | EmondError::Read { .. }
+            | EmondError::Plist { .. }
+            | EmondError::Nesting { .. }
+            | EmondError::Write { .. }
+            | EmondError::DetectionRule { .. }
+            | EmondError::Build { .. } => None,
+            EmondError::Rule { rule_index, .. } => *rule_index,
+            EmondError::Salvage { rule_index, .. } | EmondError::ActionArray { rule_index, .. } => {
+                Some(*rule_index)
+            }
+        }
+    }
+}
+
+impl Error for EmondError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EmondError::Path { source, .. } => Some(source),
+            EmondError::Read { source, .. } => Some(source),
+            EmondError::Plist { source, .. } => Some(source),
+            EmondError::Write { source, .. } => Some(source),
+            EmondError::Salvage { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for EmondError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            EmondError::Path => write!(f, "Failed to get directory path"),
-            EmondError::Plist => write!(f, "Failed to parse PLIST file"),
-            EmondError::Rule => write!(f, "Failed to parse rule file"),
-            EmondError::EventType => write!(f, "Failed to parse Emond Event Type"),
-            EmondError::ActionArray => write!(f, "Failed to parse Emond Action Array"),
-            EmondError::ActionDictionary => write!(f, "Failed to parse Emond Action Dictionary"),
+            EmondError::Path { path, source } => {
+                write!(f, "Failed to get directory path {}: {}", path, source)
+            }
+            EmondError::Read { path, source } => write!(f, "Failed to read {}: {}", path, source),
+            EmondError::Plist { path, source } => {
+                write!(f, "Failed to parse PLIST file {}: {}", path, source)
+            }
+            EmondError::Nesting { path, limit } => write!(
+                f,
+                "Failed to parse PLIST file {}: nested deeper than {} levels",
+                path, limit
+            ),
+            EmondError::Write { path, source } => {
+                write!(f, "Failed to write PLIST file {}: {}", path, source)
+            }
+            EmondError::DetectionRule { path, message } => {
+                write!(f, "Failed to load detection rule {}: {}", path, message)
+            }
+            EmondError::Build { name, diagnostics } => {
+                let messages: Vec<String> = diagnostics
+                    .iter()
+                    .map(|diagnostic| diagnostic.message.clone())
+                    .collect();
+                write!(
+                    f,
+                    "Failed to build Emond rule {}: {}",
+                    name,
+                    messages.join("; ")
+                )
+            }
+            EmondError::Rule {
+                path,
+                rule_index: Some(rule_index),
+            } => write!(
+                f,
+                "Failed to parse rule file {}: rule at index {} is not a dictionary",
+                path, rule_index
+            ),
+            EmondError::Rule {
+                path,
+                rule_index: None,
+            } => write!(
+                f,
+                "Failed to parse rule file {}: rules are not an array",
+                path
+            ),
+            EmondError::Salvage {
+                path,
+                rule_index,
+                source,
+            } => write!(
+                f,
+                "Failed to salvage rule {} in {}: {}",
+                rule_index, path, source
+            ),
+            EmondError::ActionArray {
+                path,
+                rule_index,
+                key,
+            } => write!(
+                f,
+                "Failed to parse Emond Action Array {} of rule {} in {}",
+                key, rule_index, path
+            ),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, io};
+
+    use super::EmondError;
+
+    #[test]
+    fn test_error_source() {
+        let error = EmondError::Path {
+            path: String::from("/etc/emond.d/rules"),
+            source: io::Error::new(io::ErrorKind::NotFound, "missing"),
+        };
+        assert_eq!(error.path(), "/etc/emond.d/rules");
+        assert_eq!(error.rule_index(), None);
+        assert_eq!(error.source().unwrap().to_string(), "missing");
+        assert_eq!(
+            error.to_string(),
+            "Failed to get directory path /etc/emond.d/rules: missing"
+        );
+    }
+
+    #[test]
+    fn test_error_rule_context() {
+        let error = EmondError::ActionArray {
+            path: String::from("/etc/emond.d/rules/test.plist"),
+            rule_index: 2,
+            key: String::from("actions"),
+        };
+        assert_eq!(error.rule_index(), Some(2));
+        assert!(error.source().is_none());
+        assert_eq!(
+            error.to_string(),
+            "Failed to parse Emond Action Array actions of rule 2 in /etc/emond.d/rules/test.plist"
+        );
+    }
+}
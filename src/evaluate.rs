@@ -0,0 +1,530 @@
+//! Evaluate Emond Rules against synthetic events
+//!
+//! Answers "would this rule fire for this event?" without a Mac. Every criterion decision is recorded in a trace
+
+use std::cmp::Ordering;
+
+use plist::{Dictionary, Value};
+use serde::Serialize;
+
+use crate::{
+    criterion::{Criterion, Operator},
+    emond::EmondData,
+};
+
+/// A synthetic Emond event
+#[derive(Debug, Clone)]
+pub struct EmondEvent {
+    /// Event type such as `startup` or `auth:login`
+    pub event_type: String,
+    /// Event values criteria are checked against
+    pub payload: Dictionary,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Evaluation {
+    pub rule_name: String,
+    pub rule_enabled: bool,
+    pub event_type_matched: bool,
+    pub criteria_matched: bool,
+    /// Rule is enabled, listens for the event type and its criteria matched
+    pub fires: bool,
+    pub trace: Vec<CriterionTrace>,
+}
+
+/// Decision made for a single criterion
+#[derive(Debug, Serialize)]
+pub struct CriterionTrace {
+    pub operator: String,
+    pub event_key: String,
+    pub expected: Option<Value>,
+    pub actual: Option<Value>,
+    pub matched: bool,
+    pub reason: String,
+    pub children: Vec<CriterionTrace>,
+}
+
+impl EmondEvent {
+    pub fn new(event_type: &str) -> EmondEvent {
+        EmondEvent {
+            event_type: event_type.to_string(),
+            payload: Dictionary::new(),
+        }
+    }
+
+    /// Add a value to the event payload
+    pub fn with_value(mut self, key: &str, value: Value) -> EmondEvent {
+        self.payload.insert(key.to_string(), value);
+        self
+    }
+
+    // Get an event value. Nested dictionaries can be accessed with `.` (ex: user.name)
+    fn get_value(&self, event_key: &str) -> Option<&Value> {
+        if let Some(value) = self.payload.get(event_key) {
+            return Some(value);
+        }
+
+        let mut keys = event_key.split('.');
+        let mut value = self.payload.get(keys.next()?)?;
+        for key in keys {
+            value = value.as_dictionary()?.get(key)?;
+        }
+        Some(value)
+    }
+}
+
+/// Evaluate an Emond Rule against an event
+pub fn evaluate_rule(rule: &EmondData, event: &EmondEvent) -> Evaluation {
+    let event_type_matched = rule
+        .event_types
+        .iter()
+        .any(|event_type| event_type == &event.event_type);
+
+    let trace: Vec<CriterionTrace> = rule
+        .criterion
+        .iter()
+        .map(|criterion| evaluate_criterion(criterion, event))
+        .collect();
+
+    // A rule without criteria matches every event of its event types
+    let criteria_matched = if trace.is_empty() {
+        true
+    } else if rule.allow_partial_criterion_match {
+        trace.iter().any(|criterion| criterion.matched)
+    } else {
+        trace.iter().all(|criterion| criterion.matched)
+    };
+
+    Evaluation {
+        rule_name: rule.name.clone(),
+        rule_enabled: rule.enabled,
+        event_type_matched,
+        criteria_matched,
+        fires: rule.enabled && event_type_matched && criteria_matched,
+        trace,
+    }
+}
+
+/// Evaluate a single criterion against an event
+pub fn evaluate_criterion(criterion: &Criterion, event: &EmondEvent) -> CriterionTrace {
+    let mut trace = CriterionTrace {
+        operator: criterion.operator.as_str().to_string(),
+        event_key: criterion.event_key.clone(),
+        expected: criterion.value.clone(),
+        actual: None,
+        matched: false,
+        reason: String::new(),
+        children: Vec::new(),
+    };
+
+    if !criterion.event_type.is_empty() && criterion.event_type != event.event_type {
+        trace.reason = format!(
+            "criterion applies to event type {}, not {}",
+            criterion.event_type, event.event_type
+        );
+        return trace;
+    }
+
+    if criterion.operator.is_logical() {
+        trace.children = criterion
+            .criterion
+            .iter()
+            .map(|nested| evaluate_criterion(nested, event))
+            .collect();
+        let matched_count = trace.children.iter().filter(|child| child.matched).count();
+        let all_matched = matched_count == trace.children.len();
+
+        (trace.matched, trace.reason) = match criterion.operator {
+            Operator::And => (
+                all_matched,
+                format!(
+                    "{} of {} nested criteria matched, And requires all",
+                    matched_count,
+                    trace.children.len()
+                ),
+            ),
+            Operator::Or => (
+                matched_count > 0,
+                format!(
+                    "{} of {} nested criteria matched, Or requires one",
+                    matched_count,
+                    trace.children.len()
+                ),
+            ),
+            _ => (
+                !all_matched,
+                format!(
+                    "{} of {} nested criteria matched, Not requires at least one failure",
+                    matched_count,
+                    trace.children.len()
+                ),
+            ),
+        };
+        return trace;
+    }
+
+    match criterion.operator {
+        Operator::True => {
+            trace.matched = true;
+            trace.reason = String::from("True always matches");
+            return trace;
+        }
+        Operator::False => {
+            trace.reason = String::from("False never matches");
+            return trace;
+        }
+        Operator::Unknown(ref operator) => {
+            trace.reason = format!("unknown operator {:?} never matches", operator);
+            return trace;
+        }
+        _ => {}
+    }
+
+    let actual = match event.get_value(&criterion.event_key) {
+        Some(value) => value,
+        None => {
+            trace.reason = format!("event has no value for key {:?}", criterion.event_key);
+            return trace;
+        }
+    };
+    trace.actual = Some(actual.clone());
+
+    let expected = match &criterion.value {
+        Some(value) => value,
+        None => {
+            trace.reason = String::from("criterion has no value to compare");
+            return trace;
+        }
+    };
+
+    (trace.matched, trace.reason) = compare(&criterion.operator, actual, expected);
+    trace
+}
+
+// Compare the event value against the criterion value
+fn compare(operator: &Operator, actual: &Value, expected: &Value) -> (bool, String) {
+    let actual_string = value_string(actual);
+    let expected_string = value_string(expected);
+
+    let matched = match operator {
+        Operator::Equal => compare_order(actual, expected) == Some(Ordering::Equal),
+        Operator::NotEqual => compare_order(actual, expected) != Some(Ordering::Equal),
+        Operator::GreaterThan => compare_order(actual, expected) == Some(Ordering::Greater),
+        Operator::GreaterThanOrEqual => matches!(
+            compare_order(actual, expected),
+            Some(Ordering::Greater | Ordering::Equal)
+        ),
+        Operator::LessThan => compare_order(actual, expected) == Some(Ordering::Less),
+        Operator::LessThanOrEqual => matches!(
+            compare_order(actual, expected),
+            Some(Ordering::Less | Ordering::Equal)
+        ),
+        Operator::Like => wildcard_match(&expected_string, &actual_string),
+        Operator::NotLike => !wildcard_match(&expected_string, &actual_string),
+        Operator::Contains => contains(actual, expected),
+        Operator::NotContains => !contains(actual, expected),
+        Operator::BeginsWith => actual_string.starts_with(&expected_string),
+        Operator::EndsWith => actual_string.ends_with(&expected_string),
+        _ => false,
+    };
+
+    let outcome = if matched { "matched" } else { "did not match" };
+    (
+        matched,
+        format!(
+            "{:?} {} {:?} {}",
+            actual_string,
+            operator.as_str(),
+            expected_string,
+            outcome
+        ),
+    )
+}
+
+// Order two values. Numbers (or numeric strings) compare numerically, everything else compares as strings
+fn compare_order(actual: &Value, expected: &Value) -> Option<Ordering> {
+    if let (Some(actual_number), Some(expected_number)) =
+        (value_number(actual), value_number(expected))
+    {
+        return actual_number.partial_cmp(&expected_number);
+    }
+    Some(value_string(actual).cmp(&value_string(expected)))
+}
+
+// Check if a string contains a substring or an array contains a value
+fn contains(actual: &Value, expected: &Value) -> bool {
+    match actual {
+        Value::Array(values) => values
+            .iter()
+            .any(|value| compare_order(value, expected) == Some(Ordering::Equal)),
+        _ => value_string(actual).contains(&value_string(expected)),
+    }
+}
+
+fn value_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(number) => number
+            .as_signed()
+            .map(|number| number as f64)
+            .or_else(|| number.as_unsigned().map(|number| number as f64)),
+        Value::Real(number) => Some(*number),
+        Value::String(data) => data.trim().parse::<f64>().ok(),
+        _ => None,
+    }
+}
+
+fn value_string(value: &Value) -> String {
+    match value {
+        Value::String(data) => data.clone(),
+        Value::Boolean(data) => data.to_string(),
+        Value::Real(data) => data.to_string(),
+        Value::Integer(data) => data.to_string(),
+        Value::Date(data) => data.to_xml_format(),
+        _ => format!("{:?}", value),
+    }
+}
+
+// Match a string against a pattern where `*` matches any characters and `?` matches a single character
+fn wildcard_match(pattern: &str, data: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let data: Vec<char> = data.chars().collect();
+
+    let (mut pattern_index, mut data_index) = (0, 0);
+    let mut star_index: Option<usize> = None;
+    let mut star_data_index = 0;
+
+    while data_index < data.len() {
+        if pattern_index < pattern.len()
+            && (pattern[pattern_index] == '?' || pattern[pattern_index] == data[data_index])
+        {
+            pattern_index += 1;
+            data_index += 1;
+        } else if pattern_index < pattern.len() && pattern[pattern_index] == '*' {
+            star_index = Some(pattern_index);
+            star_data_index = data_index;
+            pattern_index += 1;
+        } else if let Some(star) = star_index {
+            pattern_index = star + 1;
+            star_data_index += 1;
+            data_index = star_data_index;
+        } else {
+            return false;
+        }
+    }
+
+    while pattern_index < pattern.len() && pattern[pattern_index] == '*' {
+        pattern_index += 1;
+    }
+    pattern_index == pattern.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use plist::{Dictionary, Value};
+
+    use crate::{
+        context::ParseContext,
+        criterion::{Criterion, Operator},
+        emond::EmondData,
+    };
+
+    use super::{evaluate_criterion, evaluate_rule, wildcard_match, EmondEvent};
+
+    fn comparison(operator: &str, event_key: &str, value: Value) -> Criterion {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(
+            String::from("operator"),
+            Value::String(operator.to_string()),
+        );
+        test_dictionary.insert(
+            String::from("eventKey"),
+            Value::String(event_key.to_string()),
+        );
+        test_dictionary.insert(String::from("value"), value);
+        Criterion::parse_criterion(&test_dictionary)
+    }
+
+    fn sample_rule() -> EmondData {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/SampleRules.plist");
+
+        let mut results = EmondData::parse_emond_data(
+            &ParseContext::live(),
+            &test_location.display().to_string(),
+        )
+        .unwrap();
+        results.remove(0)
+    }
+
+    #[test]
+    fn test_evaluate_rule() {
+        let mut rule = sample_rule();
+        let results = evaluate_rule(&rule, &EmondEvent::new("startup"));
+        assert!(results.event_type_matched);
+        assert!(results.criteria_matched);
+        assert!(!results.fires);
+        assert_eq!(results.trace[0].reason, "True always matches");
+
+        rule.enabled = true;
+        let results = evaluate_rule(&rule, &EmondEvent::new("startup"));
+        assert!(results.fires);
+
+        let results = evaluate_rule(&rule, &EmondEvent::new("auth:login"));
+        assert!(!results.event_type_matched);
+        assert!(!results.fires);
+    }
+
+    #[test]
+    fn test_evaluate_rule_partial_match() {
+        let mut rule = sample_rule();
+        rule.enabled = true;
+        rule.event_types = vec![String::from("auth:login")];
+        rule.criterion = vec![
+            comparison("Equal", "username", Value::String(String::from("root"))),
+            comparison("Equal", "tty", Value::String(String::from("console"))),
+        ];
+
+        let event = EmondEvent::new("auth:login")
+            .with_value("username", Value::String(String::from("root")))
+            .with_value("tty", Value::String(String::from("ssh")));
+        let results = evaluate_rule(&rule, &event);
+        assert!(!results.fires);
+        assert!(results.trace[0].matched);
+        assert!(!results.trace[1].matched);
+        assert_eq!(
+            results.trace[1].reason,
+            "\"ssh\" Equal \"console\" did not match"
+        );
+
+        rule.allow_partial_criterion_match = true;
+        let results = evaluate_rule(&rule, &event);
+        assert!(results.fires);
+    }
+
+    #[test]
+    fn test_evaluate_criterion_operators() {
+        let event = EmondEvent::new("auth:login")
+            .with_value("username", Value::String(String::from("admin_user")))
+            .with_value("uid", Value::Integer(501.into()))
+            .with_value(
+                "groups",
+                Value::Array(vec![Value::String(String::from("admin"))]),
+            );
+
+        let checks = [
+            ("GreaterThan", "uid", Value::Integer(500.into()), true),
+            (
+                "LessThanOrEqual",
+                "uid",
+                Value::String(String::from("501")),
+                true,
+            ),
+            ("NotEqual", "uid", Value::Integer(501.into()), false),
+            (
+                "Like",
+                "username",
+                Value::String(String::from("admin*")),
+                true,
+            ),
+            (
+                "NotLike",
+                "username",
+                Value::String(String::from("?dmin*")),
+                false,
+            ),
+            (
+                "Contains",
+                "groups",
+                Value::String(String::from("admin")),
+                true,
+            ),
+            (
+                "Contains",
+                "username",
+                Value::String(String::from("_us")),
+                true,
+            ),
+            (
+                "BeginsWith",
+                "username",
+                Value::String(String::from("root")),
+                false,
+            ),
+            (
+                "EndsWith",
+                "username",
+                Value::String(String::from("user")),
+                true,
+            ),
+        ];
+        for (operator, event_key, value, expected) in checks {
+            let results = evaluate_criterion(&comparison(operator, event_key, value), &event);
+            assert_eq!(results.matched, expected, "{}", results.reason);
+        }
+
+        let results = evaluate_criterion(
+            &comparison("Equal", "missing", Value::String(String::new())),
+            &event,
+        );
+        assert!(!results.matched);
+        assert_eq!(results.reason, "event has no value for key \"missing\"");
+    }
+
+    #[test]
+    fn test_evaluate_logical_criteria() {
+        let event = EmondEvent::new("startup").with_value("uid", Value::Integer(0.into()));
+
+        let mut not_criterion = comparison("Not", "", Value::Boolean(true));
+        not_criterion.criterion = vec![comparison("Equal", "uid", Value::Integer(0.into()))];
+        let results = evaluate_criterion(&not_criterion, &event);
+        assert!(!results.matched);
+        assert!(results.children[0].matched);
+
+        let mut or_criterion = comparison("Or", "", Value::Boolean(true));
+        or_criterion.criterion = vec![
+            comparison("Equal", "uid", Value::Integer(501.into())),
+            comparison("Equal", "uid", Value::Integer(0.into())),
+        ];
+        assert!(evaluate_criterion(&or_criterion, &event).matched);
+
+        or_criterion.operator = Operator::And;
+        assert!(!evaluate_criterion(&or_criterion, &event).matched);
+
+        or_criterion.operator = Operator::Unknown(String::from("Xor"));
+        assert!(!evaluate_criterion(&or_criterion, &event).matched);
+    }
+
+    #[test]
+    fn test_evaluate_criterion_event_type() {
+        let mut criterion = comparison("True", "", Value::Boolean(true));
+        criterion.event_type = String::from("auth:login");
+
+        assert!(!evaluate_criterion(&criterion, &EmondEvent::new("startup")).matched);
+        assert!(evaluate_criterion(&criterion, &EmondEvent::new("auth:login")).matched);
+    }
+
+    #[test]
+    fn test_event_nested_value() {
+        let mut user = Dictionary::new();
+        user.insert(String::from("name"), Value::String(String::from("root")));
+        let event = EmondEvent::new("startup").with_value("user", Value::Dictionary(user));
+
+        let results = evaluate_criterion(
+            &comparison("Equal", "user.name", Value::String(String::from("root"))),
+            &event,
+        );
+        assert!(results.matched);
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("a*c", "abbbc"));
+        assert!(wildcard_match("a?c", "abc"));
+        assert!(!wildcard_match("a?c", "abbc"));
+        assert!(wildcard_match("*.sh", "/tmp/run.sh"));
+        assert!(!wildcard_match("*.sh", "/tmp/run.py"));
+    }
+}
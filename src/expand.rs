@@ -0,0 +1,402 @@
+//! Expand `${...}` tokens in Emond action strings
+//!
+//! Action strings can reference builtins (`${builtin:now}`), event values (`${event:username}`),
+//! globals from `initialGlobals` (`${global:notificationContacts}`) and rule variables (`${name}` or `${variable:name}`).
+//! Expanding them shows what Emond would actually log, send or execute
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use plist::{Dictionary, Value};
+use serde::Serialize;
+
+use crate::{
+    actions::{send_email::SendEmail, send_sms::SendSms, Action},
+    emond::EmondData,
+    evaluate::EmondEvent,
+    util::format_unix_time,
+};
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+pub enum TokenKind {
+    Builtin,
+    EventField,
+    Global,
+    RuleVariable,
+}
+
+/// A `${...}` token in an action string
+#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+pub struct Token {
+    /// Full token text including `${` and `}`
+    pub raw: String,
+    pub kind: TokenKind,
+    /// Token name without its prefix
+    pub name: String,
+    /// Byte offset of the token in the action string
+    pub offset: usize,
+}
+
+/// An action string that contains at least one token
+#[derive(Debug, Serialize)]
+pub struct ActionTemplate {
+    /// Location of the string in the rule (ex: actions[0].arguments[1])
+    pub location: String,
+    pub template: String,
+    pub tokens: Vec<Token>,
+}
+
+/// Values used to expand tokens
+#[derive(Debug, Clone)]
+pub struct ExpansionContext {
+    pub event: Option<EmondEvent>,
+    /// Clock used for time builtins
+    pub now: SystemTime,
+    pub hostname: String,
+    pub globals: Dictionary,
+    pub variables: Dictionary,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Expansion {
+    pub location: String,
+    pub template: String,
+    pub rendered: String,
+    /// Tokens that could not be resolved. They are left as-is in the rendered string
+    pub unresolved: Vec<Token>,
+}
+
+impl Default for ExpansionContext {
+    fn default() -> Self {
+        ExpansionContext {
+            event: None,
+            now: SystemTime::now(),
+            hostname: String::new(),
+            globals: Dictionary::new(),
+            variables: Dictionary::new(),
+        }
+    }
+}
+
+impl ExpansionContext {
+    /// Expansion context for an event, using the rule variables of the provided rule
+    pub fn for_rule(rule: &EmondData, event: Option<EmondEvent>) -> ExpansionContext {
+        let mut context = ExpansionContext {
+            event,
+            ..Default::default()
+        };
+
+        for variable in &rule.variables {
+            if let Some(value) = &variable.value {
+                context
+                    .variables
+                    .insert(variable.name.clone(), value.clone());
+            }
+        }
+        context
+    }
+
+    // Resolve a single token
+    fn resolve(&self, token: &Token) -> Option<String> {
+        let value = match token.kind {
+            TokenKind::Builtin => return self.resolve_builtin(&token.name),
+            TokenKind::EventField => self
+                .event
+                .as_ref()
+                .and_then(|event| event.payload.get(&token.name)),
+            TokenKind::Global => self.globals.get(&token.name),
+            TokenKind::RuleVariable => self.variables.get(&token.name),
+        };
+        value.map(render_value)
+    }
+
+    fn resolve_builtin(&self, name: &str) -> Option<String> {
+        let timestamp = match self.now.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs() as i64,
+            Err(_) => 0,
+        };
+        let now = format_unix_time(timestamp);
+
+        match name {
+            "now" => Some(now),
+            "date" => now.get(0..10).map(str::to_string),
+            "time" => now.get(11..19).map(str::to_string),
+            "timestamp" => Some(timestamp.to_string()),
+            "hostname" if !self.hostname.is_empty() => Some(self.hostname.clone()),
+            "eventType" => self.event.as_ref().map(|event| event.event_type.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Parse all `${...}` tokens in a string
+pub fn parse_tokens(template: &str) -> Vec<Token> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut search_offset = 0;
+
+    while let Some(start) = template[search_offset..].find("${") {
+        let token_start = search_offset + start;
+        let end = match template[token_start..].find('}') {
+            Some(end) => token_start + end,
+            None => break,
+        };
+
+        let body = &template[token_start + 2..end];
+        let (kind, name) = classify_token(body);
+        tokens.push(Token {
+            raw: template[token_start..=end].to_string(),
+            kind,
+            name: name.to_string(),
+            offset: token_start,
+        });
+        search_offset = end + 1;
+    }
+    tokens
+}
+
+// Get the token type from its prefix
+fn classify_token(body: &str) -> (TokenKind, &str) {
+    let prefixes = [
+        ("builtin:", TokenKind::Builtin),
+        ("event:", TokenKind::EventField),
+        ("global:", TokenKind::Global),
+        ("variable:", TokenKind::RuleVariable),
+    ];
+    for (prefix, kind) in prefixes {
+        if let Some(name) = body.strip_prefix(prefix) {
+            return (kind, name);
+        }
+    }
+    (TokenKind::RuleVariable, body)
+}
+
+/// Get every action string in the rule that contains a token
+pub fn action_templates(rule: &EmondData) -> Vec<ActionTemplate> {
+    let mut strings: Vec<(String, &str)> = Vec::new();
+
+    for rule_action in &rule.actions {
+        let key_path = format!("actions[{}]", rule_action.index);
+        match &rule_action.action {
+            Action::Command(command) => {
+                strings.push((format!("{}.command", key_path), &command.command));
+                strings.push((format!("{}.user", key_path), &command.user));
+                strings.push((format!("{}.group", key_path), &command.group));
+                for (arg_index, arg) in command.arguements.iter().enumerate() {
+                    strings.push((format!("{}.arguments[{}]", key_path, arg_index), arg));
+                }
+            }
+            Action::Log(log) => strings.push((format!("{}.message", key_path), &log.message)),
+            Action::SendEmail(SendEmail {
+                message,
+                subject,
+                admin_email,
+                recipient_addresses,
+                ..
+            })
+            | Action::SendSms(SendSms {
+                message,
+                subject,
+                admin_email,
+                recipient_addresses,
+                ..
+            }) => {
+                strings.push((format!("{}.message", key_path), message));
+                strings.push((format!("{}.subject", key_path), subject));
+                strings.push((format!("{}.admin_email", key_path), admin_email));
+                for (address_index, address) in recipient_addresses.iter().enumerate() {
+                    strings.push((
+                        format!("{}.recipient_addresses[{}]", key_path, address_index),
+                        address,
+                    ));
+                }
+            }
+            Action::SendNotification(notification) => {
+                strings.push((format!("{}.name", key_path), &notification.name));
+                strings.push((format!("{}.message", key_path), &notification.message));
+            }
+            Action::Unknown(_) => continue,
+        }
+    }
+
+    let mut templates: Vec<ActionTemplate> = Vec::new();
+    for (location, template) in strings {
+        let tokens = parse_tokens(template);
+        if tokens.is_empty() {
+            continue;
+        }
+        templates.push(ActionTemplate {
+            location,
+            template: template.to_string(),
+            tokens,
+        });
+    }
+    templates
+}
+
+/// Expand all tokens in a string
+pub fn expand(template: &str, context: &ExpansionContext) -> (String, Vec<Token>) {
+    let mut rendered = String::new();
+    let mut unresolved: Vec<Token> = Vec::new();
+    let mut last_offset = 0;
+
+    for token in parse_tokens(template) {
+        rendered.push_str(&template[last_offset..token.offset]);
+        match context.resolve(&token) {
+            Some(value) => rendered.push_str(&value),
+            None => {
+                rendered.push_str(&token.raw);
+                unresolved.push(token.clone());
+            }
+        }
+        last_offset = token.offset + token.raw.len();
+    }
+    rendered.push_str(&template[last_offset..]);
+    (rendered, unresolved)
+}
+
+/// Expand every action string in the rule that contains a token
+pub fn expand_rule(rule: &EmondData, context: &ExpansionContext) -> Vec<Expansion> {
+    action_templates(rule)
+        .into_iter()
+        .map(|action_template| {
+            let (rendered, unresolved) = expand(&action_template.template, context);
+            Expansion {
+                location: action_template.location,
+                template: action_template.template,
+                rendered,
+                unresolved,
+            }
+        })
+        .collect()
+}
+
+fn render_value(value: &Value) -> String {
+    match value {
+        Value::String(data) => data.clone(),
+        Value::Boolean(data) => data.to_string(),
+        Value::Integer(data) => data.to_string(),
+        Value::Real(data) => data.to_string(),
+        Value::Array(values) => values
+            .iter()
+            .map(render_value)
+            .collect::<Vec<String>>()
+            .join(","),
+        _ => format!("{:?}", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use plist::{Dictionary, Value};
+
+    use crate::{
+        context::ParseContext, emond::EmondData, evaluate::EmondEvent, variable::Variable,
+    };
+
+    use super::{action_templates, expand, expand_rule, parse_tokens, ExpansionContext, TokenKind};
+
+    fn sample_rule() -> EmondData {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/SampleRules.plist");
+
+        let mut results = EmondData::parse_emond_data(
+            &ParseContext::live(),
+            &test_location.display().to_string(),
+        )
+        .unwrap();
+        results.remove(0)
+    }
+
+    #[test]
+    fn test_parse_tokens() {
+        let results = parse_tokens(
+            "${builtin:now} ${event:username} ${global:host} ${path} ${variable:x} ${broken",
+        );
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].kind, TokenKind::Builtin);
+        assert_eq!(results[0].name, "now");
+        assert_eq!(results[0].raw, "${builtin:now}");
+        assert_eq!(results[0].offset, 0);
+        assert_eq!(results[1].kind, TokenKind::EventField);
+        assert_eq!(results[1].name, "username");
+        assert_eq!(results[2].kind, TokenKind::Global);
+        assert_eq!(results[3].kind, TokenKind::RuleVariable);
+        assert_eq!(results[3].name, "path");
+        assert_eq!(results[4].kind, TokenKind::RuleVariable);
+        assert_eq!(results[4].name, "x");
+
+        assert!(parse_tokens("no tokens $ here {}").is_empty());
+    }
+
+    #[test]
+    fn test_action_templates() {
+        let results = action_templates(&sample_rule());
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].location, "actions[0].message");
+        assert_eq!(
+            results[0].template,
+            "Event Monitor started at ${builtin:now}"
+        );
+        assert_eq!(results[0].tokens[0].name, "now");
+    }
+
+    #[test]
+    fn test_expand() {
+        let mut context = ExpansionContext {
+            now: UNIX_EPOCH + Duration::from_secs(1662386589),
+            hostname: String::from("mac-mini"),
+            event: Some(
+                EmondEvent::new("auth:login")
+                    .with_value("username", Value::String(String::from("root"))),
+            ),
+            ..Default::default()
+        };
+        context.variables.insert(
+            String::from("script"),
+            Value::String(String::from("/tmp/a.sh")),
+        );
+
+        let (rendered, unresolved) = expand(
+            "${event:username}@${builtin:hostname} ran ${script} on ${builtin:date} ${builtin:eventType} ${missing}",
+            &context,
+        );
+        assert_eq!(
+            rendered,
+            "root@mac-mini ran /tmp/a.sh on 2022-09-05 auth:login ${missing}"
+        );
+        assert_eq!(unresolved.len(), 1);
+        assert_eq!(unresolved[0].name, "missing");
+    }
+
+    #[test]
+    fn test_expand_rule() {
+        let rule = sample_rule();
+        let mut context = ExpansionContext::for_rule(&rule, None);
+        context.now = UNIX_EPOCH + Duration::from_secs(0);
+
+        let results = expand_rule(&rule, &context);
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0].rendered,
+            "Event Monitor started at 1970-01-01 00:00:00 +0000"
+        );
+        assert!(results[0].unresolved.is_empty());
+    }
+
+    #[test]
+    fn test_for_rule_variables() {
+        let mut rule = sample_rule();
+        let mut variable = Dictionary::new();
+        variable.insert(String::from("name"), Value::String(String::from("user")));
+        variable.insert(String::from("value"), Value::String(String::from("admin")));
+        rule.variables.push(Variable::parse_variable(&variable));
+
+        let context = ExpansionContext::for_rule(&rule, None);
+        let (rendered, _) = expand("hello ${user}", &context);
+        assert_eq!(rendered, "hello admin");
+    }
+}
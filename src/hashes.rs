@@ -0,0 +1,82 @@
+//! Hash Emond files for IOC matching
+//!
+//! Hashing is opt-in with `ParseOptions { hashes: true }` so bulk scans can skip reading every file twice
+
+use std::{fs::read, path::Path};
+
+use log::warn;
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::size::get_file_size;
+
+/// MD5, SHA-1 and SHA-256 hashes of a file as lowercase hex strings
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Hashes {
+    pub md5: String,
+    pub sha1: String,
+    pub sha256: String,
+}
+
+impl Hashes {
+    /// Hash data in memory
+    pub fn hash_data(data: &[u8]) -> Hashes {
+        Hashes {
+            md5: format!("{:x}", Md5::digest(data)),
+            sha1: format!("{:x}", Sha1::digest(data)),
+            sha256: format!("{:x}", Sha256::digest(data)),
+        }
+    }
+
+    /// Hash the file at provided path. Returns None if the file cannot be read or is too large
+    pub fn hash_file(path: &Path) -> Option<Hashes> {
+        if !path.is_file() || !get_file_size(&path.display().to_string()) {
+            return None;
+        }
+        match read(path) {
+            Ok(data) => Some(Hashes::hash_data(&data)),
+            Err(err) => {
+                warn!(
+                    "[macos-emond] Failed to read {} for hashing: {:?}",
+                    path.display(),
+                    err
+                );
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::Hashes;
+
+    #[test]
+    fn test_hash_data() {
+        let results = Hashes::hash_data(b"test");
+        assert_eq!(results.md5, "098f6bcd4621d373cade4e832627b4f6");
+        assert_eq!(results.sha1, "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3");
+        assert_eq!(
+            results.sha256,
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+    }
+
+    #[test]
+    fn test_hash_file() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system/private/var/db/emondClients/com.update.client");
+        let results = Hashes::hash_file(&test_location).unwrap();
+        assert_eq!(
+            results.sha256,
+            "8e1e9f4203e94a15b4c5c85f85e1f0052fe5c734fafa357671ff9f15d9c0d802"
+        );
+
+        assert!(Hashes::hash_file(Path::new("/tmp/macos_emond_missing_file")).is_none());
+        assert!(Hashes::hash_file(Path::new(env!("CARGO_MANIFEST_DIR"))).is_none());
+    }
+}
@@ -1,6 +1,27 @@
-mod actions;
+pub mod actions;
+pub mod builder;
+pub mod clients;
+pub mod config;
+pub mod context;
+pub mod criterion;
+pub mod detect;
+pub mod detection_rules;
+pub mod diagnostic;
 pub mod emond;
 mod error;
+pub mod evaluate;
+pub mod expand;
+pub mod hashes;
+pub mod metadata;
 pub mod parser;
+pub mod reader;
+mod salvage;
 mod size;
+pub mod snapshot;
+pub mod target;
 mod util;
+pub mod validate;
+pub mod variable;
+pub mod writer;
+
+pub use error::EmondError;
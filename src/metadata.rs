@@ -0,0 +1,158 @@
+use std::{
+    fs::Metadata,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// Filesystem metadata for an Emond artifact. Timestamps are seconds since the UNIX epoch
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileMetadata {
+    pub size: u64,
+    pub uid: u32,
+    pub gid: u32,
+    /// Full mode bits including the file type
+    pub mode: u32,
+    /// Permissions in `ls -l` notation
+    pub permissions: String,
+    pub modified: i64,
+    pub accessed: i64,
+    pub changed: i64,
+    /// Birth time, not available on every filesystem
+    pub created: Option<i64>,
+}
+
+impl FileMetadata {
+    /// Get the metadata of the file at provided path without following a final symlink
+    pub fn get_metadata(path: &Path) -> Option<FileMetadata> {
+        let metadata_results = path.symlink_metadata();
+        match metadata_results {
+            Ok(metadata) => Some(FileMetadata::from_metadata(&metadata)),
+            Err(err) => {
+                warn!(
+                    "[macos-emond] Failed to get metadata for {}: {:?}",
+                    path.display(),
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn from_metadata(metadata: &Metadata) -> FileMetadata {
+        use std::os::unix::fs::MetadataExt;
+
+        FileMetadata {
+            size: metadata.len(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            mode: metadata.mode(),
+            permissions: permissions_string(metadata.mode()),
+            modified: metadata.mtime(),
+            accessed: metadata.atime(),
+            changed: metadata.ctime(),
+            created: metadata.created().ok().and_then(unix_seconds),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn from_metadata(metadata: &Metadata) -> FileMetadata {
+        FileMetadata {
+            size: metadata.len(),
+            uid: 0,
+            gid: 0,
+            mode: 0,
+            permissions: String::new(),
+            modified: metadata.modified().ok().and_then(unix_seconds).unwrap_or(0),
+            accessed: metadata.accessed().ok().and_then(unix_seconds).unwrap_or(0),
+            changed: 0,
+            created: metadata.created().ok().and_then(unix_seconds),
+        }
+    }
+}
+
+// Convert a system time into seconds since the UNIX epoch
+fn unix_seconds(time: SystemTime) -> Option<i64> {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => i64::try_from(duration.as_secs()).ok(),
+        Err(err) => i64::try_from(err.duration().as_secs())
+            .ok()
+            .map(|secs| -secs),
+    }
+}
+
+// Format mode bits like `ls -l` (ex: -rwxr-xr-x)
+fn permissions_string(mode: u32) -> String {
+    let file_type = match mode & 0o170000 {
+        0o040000 => 'd',
+        0o120000 => 'l',
+        0o010000 => 'p',
+        0o020000 => 'c',
+        0o060000 => 'b',
+        0o140000 => 's',
+        _ => '-',
+    };
+
+    let mut permissions = String::from(file_type);
+    let flags = ['r', 'w', 'x'];
+    for shift in [6, 3, 0] {
+        for (index, flag) in flags.iter().enumerate() {
+            if mode >> shift & (0o4 >> index) != 0 {
+                permissions.push(*flag);
+            } else {
+                permissions.push('-');
+            }
+        }
+    }
+
+    // setuid, setgid and sticky bits replace the execute flags
+    let special = [(0o4000, 3, 's'), (0o2000, 6, 's'), (0o1000, 9, 't')];
+    let mut chars: Vec<char> = permissions.chars().collect();
+    for (bit, position, flag) in special {
+        if mode & bit != 0 {
+            chars[position] = if chars[position] == 'x' {
+                flag
+            } else {
+                flag.to_ascii_uppercase()
+            };
+        }
+    }
+    chars.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{permissions_string, FileMetadata};
+
+    #[test]
+    fn test_get_metadata() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/test123.plist");
+
+        let results = FileMetadata::get_metadata(&test_location).unwrap();
+        assert_eq!(results.size, 739);
+        assert!(results.modified > 0);
+        assert!(results.permissions.starts_with('-'));
+    }
+
+    #[test]
+    fn test_get_metadata_missing() {
+        let results = FileMetadata::get_metadata(&PathBuf::from("/tmp/macos_emond_missing"));
+        assert!(results.is_none());
+    }
+
+    #[test]
+    fn test_permissions_string() {
+        assert_eq!(permissions_string(0o100755), "-rwxr-xr-x");
+        assert_eq!(permissions_string(0o100644), "-rw-r--r--");
+        assert_eq!(permissions_string(0o040700), "drwx------");
+        assert_eq!(permissions_string(0o104755), "-rwsr-xr-x");
+        assert_eq!(permissions_string(0o041777), "drwxrwxrwt");
+        assert_eq!(permissions_string(0o120644), "lrw-r--r--");
+    }
+}
@@ -1,64 +1,158 @@
-use log::error;
-use plist::{Dictionary, Value};
+use std::io::{Read, Seek};
 
-use crate::{emond::EmondData, error::EmondError, size::get_file_size, util};
+use crate::{
+    clients::{EmondClient, EmondClients},
+    config::{EmondConfig, EMOND_CONFIG_PATH},
+    context::{ParseContext, ParseOptions},
+    emond::EmondData,
+    error::EmondError,
+    hashes::Hashes,
+    reader::read_plist_file,
+    size::get_file_size,
+    snapshot::EmondSnapshot,
+};
 
 /// Parse the Emond Config PLIST to get any additional Emond Rules directories besides the default path
 pub fn get_emond_rules_paths() -> Result<Vec<String>, EmondError> {
-    let emond_plist_path: String = String::from("/etc/emond.d/emond.plist");
-    if !get_file_size(&emond_plist_path) {
-        return Ok(Vec::new());
-    }
-    let emond_plist_result: Result<Dictionary, plist::Error> = plist::from_file(emond_plist_path);
-
-    let emond_plist = match emond_plist_result {
-        Ok(results) => results,
-        Err(err) => {
-            error!("Failed to parse Emond Config PLIST file: {:?}", err);
-            return Err(EmondError::Plist);
-        }
+    get_emond_rules_paths_with_context(&ParseContext::live())
+}
+
+/// Parse the Emond Config PLIST under the context root to get all Emond Rules directories.
+/// Returned paths are paths on the analyzed system, resolve them with the same context
+pub fn get_emond_rules_paths_with_context(
+    context: &ParseContext,
+) -> Result<Vec<String>, EmondError> {
+    let emond_config = match get_emond_config_with_context(context)? {
+        Some(results) => results,
+        None => return Ok(Vec::new()),
     };
+    Ok(emond_config.rules_paths())
+}
 
-    let mut emond_rules_paths: Vec<String> = Vec::new();
-    let default_path = String::from("/etc/emond.d/rules");
-    emond_rules_paths.push(default_path);
-
-    for (key, value) in emond_plist {
-        if key != "config" {
-            continue;
-        }
-        // Parse the config dictionary and get all the additional paths at additionalRulesPaths
-        match value {
-            Value::Dictionary(value_dictionary) => {
-                for (subkey, subvalue) in value_dictionary {
-                    if subkey == "additionalRulesPaths" {
-                        // Additional paths are stored as an array. Loop and get all the paths (if any)
-                        match subvalue {
-                            Value::Array(value_array) => {
-                                for additional_path in value_array {
-                                    let path_string = util::get_string_value(&additional_path);
-                                    emond_rules_paths.push(path_string.to_string());
-                                }
-                            }
-                            _ => continue,
-                        }
-                    }
-                }
-            }
-            _ => continue,
-        }
+/// Parse the Emond Config PLIST at `/etc/emond.d/emond.plist`
+pub fn get_emond_config() -> Result<Option<EmondConfig>, EmondError> {
+    get_emond_config_with_context(&ParseContext::live())
+}
+
+/// Parse the Emond Config PLIST under the context root. Returns None if the config does not exist
+pub fn get_emond_config_with_context(
+    context: &ParseContext,
+) -> Result<Option<EmondConfig>, EmondError> {
+    let emond_plist_path = context.resolve(EMOND_CONFIG_PATH).display().to_string();
+    if !get_file_size(&emond_plist_path) {
+        return Ok(None);
+    }
+    let data = read_plist_file(&emond_plist_path, EMOND_CONFIG_PATH)?;
+    let mut emond_config = EmondConfig::parse_config_data(&data, EMOND_CONFIG_PATH)?;
+    if context.options().hashes {
+        emond_config.hashes = Some(Hashes::hash_data(&data));
     }
-    Ok(emond_rules_paths)
+    Ok(Some(emond_config))
+}
+
+/// Parse all files in the Emond clients directory at `/private/var/db/emondClients`
+pub fn get_emond_clients() -> Result<EmondClients, EmondError> {
+    EmondClients::parse_clients(&ParseContext::live())
+}
+
+/// Parse all files in the Emond clients directory under the context root
+pub fn get_emond_clients_with_context(context: &ParseContext) -> Result<EmondClients, EmondError> {
+    EmondClients::parse_clients(context)
+}
+
+/// Collect the Emond config, rules and clients under the provided filesystem root.
+/// Use `/` to collect from the live system
+pub fn collect_emond(root: &str) -> EmondSnapshot {
+    EmondSnapshot::collect(&ParseContext::new(root))
 }
 
 /// Parse all the Emond Rules at provided path
 pub fn parse_emond_rules(path: &str) -> Result<Vec<EmondData>, EmondError> {
-    EmondData::parse_emond_rules(path)
+    EmondData::parse_emond_rules(&ParseContext::live(), path)
+}
+
+/// Parse all the Emond Rules at provided path under the context root
+pub fn parse_emond_rules_with_context(
+    context: &ParseContext,
+    path: &str,
+) -> Result<Vec<EmondData>, EmondError> {
+    EmondData::parse_emond_rules(context, path)
 }
 
 /// Parse the Emond Rules file at provided path
 pub fn parse_emond_file(path: &str) -> Result<Vec<EmondData>, EmondError> {
-    EmondData::parse_emond_data(path)
+    EmondData::parse_emond_data(&ParseContext::live(), path)
+}
+
+/// Parse the Emond Rules file at provided path under the context root
+pub fn parse_emond_file_with_context(
+    context: &ParseContext,
+    path: &str,
+) -> Result<Vec<EmondData>, EmondError> {
+    EmondData::parse_emond_data(context, path)
+}
+
+/// Parse the Emond Rules file at provided path and return the outcome of every rule in the file
+pub fn parse_emond_file_results(
+    path: &str,
+) -> Result<Vec<Result<EmondData, EmondError>>, EmondError> {
+    EmondData::parse_emond_rule_results(&ParseContext::live(), path)
+}
+
+/// Parse Emond Rules from PLIST data in memory. `source` labels where the data came from
+pub fn parse_emond_bytes(data: &[u8], source: &str) -> Result<Vec<EmondData>, EmondError> {
+    EmondData::parse_emond_bytes(data, source)
+}
+
+/// Parse Emond Rules from PLIST data in memory and return the outcome of every rule in the data
+pub fn parse_emond_bytes_results(
+    data: &[u8],
+    source: &str,
+) -> Result<Vec<Result<EmondData, EmondError>>, EmondError> {
+    EmondData::parse_emond_bytes_results(data, source)
+}
+
+/// Parse Emond Rules from PLIST data in memory with the provided parse options and return the outcome of every rule in the data
+pub fn parse_emond_bytes_results_with_options(
+    data: &[u8],
+    source: &str,
+    options: &ParseOptions,
+) -> Result<Vec<Result<EmondData, EmondError>>, EmondError> {
+    EmondData::parse_emond_bytes_results_with_options(data, source, options)
+}
+
+/// Parse Emond Rules from a reader (ex: a tarball or zip entry). `source` labels where the data came from
+pub fn parse_emond_reader<R: Read + Seek>(
+    reader: R,
+    source: &str,
+) -> Result<Vec<EmondData>, EmondError> {
+    EmondData::parse_emond_reader(reader, source)
+}
+
+/// Parse the Emond Config from PLIST data in memory. `source` labels where the data came from
+pub fn parse_emond_config_bytes(data: &[u8], source: &str) -> Result<EmondConfig, EmondError> {
+    EmondConfig::parse_emond_config_bytes(data, source)
+}
+
+/// Parse the Emond Config from a reader. `source` labels where the data came from
+pub fn parse_emond_config_reader<R: Read + Seek>(
+    reader: R,
+    source: &str,
+) -> Result<EmondConfig, EmondError> {
+    EmondConfig::parse_emond_config_reader(reader, source)
+}
+
+/// Parse an Emond client file from data in memory. `source` labels where the data came from
+pub fn parse_emond_client_bytes(data: &[u8], source: &str) -> EmondClient {
+    EmondClient::parse_client_bytes(data, source)
+}
+
+/// Parse an Emond client file from a reader. `source` labels where the data came from
+pub fn parse_emond_client_reader<R: Read + Seek>(
+    reader: R,
+    source: &str,
+) -> Result<EmondClient, EmondError> {
+    EmondClient::parse_client_reader(reader, source)
 }
 
 #[cfg(test)]
@@ -67,7 +161,15 @@ mod tests {
 
     use plist::{Dictionary, Value};
 
-    use crate::parser::parse_emond_file;
+    use crate::{
+        context::{ParseContext, ParseOptions},
+        criterion::Operator,
+        parser::{
+            collect_emond, get_emond_clients_with_context, get_emond_config_with_context,
+            get_emond_rules_paths_with_context, parse_emond_file, parse_emond_file_results,
+            parse_emond_file_with_context, parse_emond_rules_with_context,
+        },
+    };
 
     use super::{get_emond_rules_paths, parse_emond_rules};
 
@@ -86,10 +188,10 @@ mod tests {
         println!("{:?}", results);
 
         assert_eq!(results.len(), 1);
-        assert_eq!(results[0].enabled, false);
+        assert!(!results[0].enabled);
         assert_eq!(results[0].name, "sample rule");
         assert_eq!(results[0].event_types, ["startup"]);
-        assert_eq!(results[0].allow_partial_criterion_match, false);
+        assert!(!results[0].allow_partial_criterion_match);
         assert_eq!(results[0].criterion.len(), 1);
 
         let mut test_dictionary = Dictionary::new();
@@ -98,23 +200,24 @@ mod tests {
             Value::String(String::from("True")),
         );
 
-        assert_eq!(results[0].criterion[0], test_dictionary);
+        assert_eq!(results[0].criterion[0].operator, Operator::True);
+        assert_eq!(results[0].criterion[0].to_dictionary(), test_dictionary);
 
-        assert_eq!(results[0].send_notification.is_empty(), true);
-        assert_eq!(results[0].send_email_actions.is_empty(), true);
-        assert_eq!(results[0].variables.is_empty(), true);
-        assert_eq!(results[0].command_actions.is_empty(), true);
+        assert!(results[0].send_notification().is_empty());
+        assert!(results[0].send_email_actions().is_empty());
+        assert!(results[0].variables.is_empty());
+        assert!(results[0].command_actions().is_empty());
 
-        assert_eq!(results[0].log_actions.len(), 1);
+        assert_eq!(results[0].log_actions().len(), 1);
 
         assert_eq!(
-            results[0].log_actions[0].message,
+            results[0].log_actions()[0].message,
             "Event Monitor started at ${builtin:now}"
         );
-        assert_eq!(results[0].log_actions[0].facility, String::new());
-        assert_eq!(results[0].log_actions[0].log_level, "Notice");
-        assert_eq!(results[0].log_actions[0].log_type, "syslog");
-        assert_eq!(results[0].log_actions[0].parameters, Dictionary::new());
+        assert_eq!(results[0].log_actions()[0].facility, String::new());
+        assert_eq!(results[0].log_actions()[0].log_level, "Notice");
+        assert_eq!(results[0].log_actions()[0].log_type, "syslog");
+        assert_eq!(results[0].log_actions()[0].parameters, Dictionary::new());
     }
 
     #[test]
@@ -124,20 +227,124 @@ mod tests {
 
         let results = parse_emond_file(&test_location.display().to_string()).unwrap();
         assert_eq!(results.len(), 1);
-        assert_eq!(results[0].enabled, true);
+        assert!(results[0].enabled);
         assert_eq!(results[0].name, "poisonapple rule");
         assert_eq!(results[0].event_types, ["startup"]);
-        assert_eq!(results[0].allow_partial_criterion_match, false);
-        assert_eq!(results[0].criterion.is_empty(), true);
-        assert_eq!(results[0].log_actions.is_empty(), true);
-        assert_eq!(results[0].send_notification.is_empty(), true);
-        assert_eq!(results[0].send_email_actions.is_empty(), true);
-        assert_eq!(results[0].variables.is_empty(), true);
-
-        assert_eq!(results[0].command_actions.len(), 1);
-        assert_eq!(results[0].command_actions[0].command, "/Users/sur/Library/Python/3.8/lib/python/site-packages/poisonapple/auxiliary/poisonapple.sh");
-        assert_eq!(results[0].command_actions[0].group, String::new());
-        assert_eq!(results[0].command_actions[0].user, "root");
-        assert_eq!(results[0].command_actions[0].arguements, ["Emond"]);
+        assert!(!results[0].allow_partial_criterion_match);
+        assert!(results[0].criterion.is_empty());
+        assert!(results[0].log_actions().is_empty());
+        assert!(results[0].send_notification().is_empty());
+        assert!(results[0].send_email_actions().is_empty());
+        assert!(results[0].variables.is_empty());
+
+        assert_eq!(results[0].command_actions().len(), 1);
+        assert_eq!(results[0].command_actions()[0].command, "/Users/sur/Library/Python/3.8/lib/python/site-packages/poisonapple/auxiliary/poisonapple.sh");
+        assert_eq!(results[0].command_actions()[0].group, String::new());
+        assert_eq!(results[0].command_actions()[0].user, "root");
+        assert_eq!(results[0].command_actions()[0].arguements, ["Emond"]);
+    }
+
+    #[test]
+    fn test_parse_emond_file_results() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/multi_rules/MultipleRules.plist");
+
+        let results = parse_emond_file_results(&test_location.display().to_string()).unwrap();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 4);
+        assert_eq!(results[4].as_ref().unwrap().rule_index, 4);
+    }
+
+    #[test]
+    fn test_get_emond_rules_paths_with_context() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+        let context = ParseContext::new(&test_location.display().to_string());
+
+        let results = get_emond_rules_paths_with_context(&context).unwrap();
+        assert_eq!(
+            results,
+            ["/etc/emond.d/rules", "/usr/local/etc/emond.d/rules"]
+        );
+    }
+
+    #[test]
+    fn test_get_emond_config_hashes() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+        let context = ParseContext::new(&test_location.display().to_string());
+        let results = get_emond_config_with_context(&context).unwrap().unwrap();
+        assert!(results.hashes.is_none());
+
+        let context = context.with_options(ParseOptions {
+            hashes: true,
+            ..Default::default()
+        });
+        let results = get_emond_config_with_context(&context).unwrap().unwrap();
+        assert_eq!(
+            results.hashes.unwrap().sha256,
+            "91d1adbf05b340d2e50aeca69a69f301d52f935bcc56777c129a997038ee7795"
+        );
+
+        let results = get_emond_clients_with_context(&context).unwrap();
+        let hashes = results.clients[0].hashes.as_ref().unwrap();
+        assert_eq!(
+            hashes.sha256,
+            "8e1e9f4203e94a15b4c5c85f85e1f0052fe5c734fafa357671ff9f15d9c0d802"
+        );
+        assert_eq!(hashes.md5.len(), 32);
+        assert_eq!(hashes.sha1.len(), 40);
+    }
+
+    #[test]
+    fn test_parse_emond_rules_with_context() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+        let context = ParseContext::new(&test_location.display().to_string());
+
+        let results = parse_emond_rules_with_context(&context, "/etc/emond.d/rules").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "sample rule");
+
+        let results =
+            parse_emond_rules_with_context(&context, "/usr/local/etc/emond.d/rules").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "update rule");
+        assert_eq!(results[0].command_actions()[0].command, "/tmp/.update.sh");
+    }
+
+    #[test]
+    fn test_parse_emond_file_with_context() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+        let context = ParseContext::new(&test_location.display().to_string());
+
+        let results =
+            parse_emond_file_with_context(&context, "/private/etc/emond.d/rules/SampleRules.plist")
+                .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "sample rule");
+    }
+
+    #[test]
+    fn test_collect_emond() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+
+        let results = collect_emond(&test_location.display().to_string());
+        assert_eq!(results.rules_paths.len(), 2);
+        assert_eq!(results.rules.len(), 2);
+        assert_eq!(results.clients.unwrap().clients.len(), 1);
+    }
+
+    #[test]
+    fn test_get_emond_clients_with_context() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+        let context = ParseContext::new(&test_location.display().to_string());
+
+        let results = get_emond_clients_with_context(&context).unwrap();
+        assert!(results.enabled);
+        assert_eq!(results.clients[0].name, "com.update.client");
     }
 }
@@ -0,0 +1,446 @@
+//! Read PLIST data before handing it to the PLIST parser
+//!
+//! The PLIST parser builds values recursively, so deeply nested arrays or dictionaries can overflow the stack and abort the process.
+//! Emond rules are attacker controlled, so the nesting depth is checked before parsing
+
+use std::{
+    fs::read,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::EmondError, size::MAX_FILE_SIZE};
+
+/// Deepest array or dictionary nesting accepted in a PLIST file
+pub const MAX_PLIST_DEPTH: usize = 128;
+
+const BINARY_HEADER: &[u8] = b"bplist00";
+const BINARY_TRAILER_SIZE: usize = 32;
+
+/// Serialization format of PLIST data
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum PlistFormat {
+    Xml,
+    Binary,
+}
+
+impl PlistFormat {
+    /// Detect the PLIST format from the data header. Anything that is not a binary PLIST is treated as XML
+    pub fn detect(data: &[u8]) -> PlistFormat {
+        if data.starts_with(BINARY_HEADER) {
+            return PlistFormat::Binary;
+        }
+        PlistFormat::Xml
+    }
+}
+
+/// Read a PLIST file and check its nesting depth
+pub(crate) fn read_plist_file(path: &str, source: &str) -> Result<Vec<u8>, EmondError> {
+    let data = match read(path) {
+        Ok(data) => data,
+        Err(err) => {
+            error!("Failed to read PLIST file {}: {:?}", path, err);
+            return Err(EmondError::Read {
+                path: source.to_string(),
+                source: err,
+            });
+        }
+    };
+    check_plist_depth(&data, source)?;
+    Ok(data)
+}
+
+/// Read all remaining PLIST data from a reader and check its nesting depth
+pub(crate) fn read_plist_reader<R: Read + Seek>(
+    reader: R,
+    source: &str,
+) -> Result<Vec<u8>, EmondError> {
+    let data = read_reader(reader, source)?;
+    check_plist_depth(&data, source)?;
+    Ok(data)
+}
+
+/// Read all remaining data from a reader, up to the max Emond file size
+pub(crate) fn read_reader<R: Read + Seek>(
+    mut reader: R,
+    source: &str,
+) -> Result<Vec<u8>, EmondError> {
+    let mut data: Vec<u8> = Vec::new();
+    let read_result = remaining_size(&mut reader).and_then(|size| {
+        if size >= MAX_FILE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("data is larger than {} bytes", MAX_FILE_SIZE),
+            ));
+        }
+        reader.read_to_end(&mut data)
+    });
+    if let Err(err) = read_result {
+        error!("Failed to read data {}: {:?}", source, err);
+        return Err(EmondError::Read {
+            path: source.to_string(),
+            source: err,
+        });
+    }
+    Ok(data)
+}
+
+// Get the number of bytes between the current position and the end of the reader
+fn remaining_size<R: Seek>(reader: &mut R) -> io::Result<u64> {
+    let position = reader.stream_position()?;
+    let end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(position))?;
+    Ok(end.saturating_sub(position))
+}
+
+/// Check that the PLIST data does not nest deeper than `MAX_PLIST_DEPTH`
+pub(crate) fn check_plist_depth(data: &[u8], source: &str) -> Result<(), EmondError> {
+    let exceeded = match PlistFormat::detect(data) {
+        PlistFormat::Binary => binary_depth_exceeded(data, MAX_PLIST_DEPTH),
+        PlistFormat::Xml => xml_depth_exceeded(data, MAX_PLIST_DEPTH),
+    };
+
+    if exceeded {
+        error!(
+            "PLIST data {} is nested deeper than {} levels",
+            source, MAX_PLIST_DEPTH
+        );
+        return Err(EmondError::Nesting {
+            path: source.to_string(),
+            limit: MAX_PLIST_DEPTH,
+        });
+    }
+    Ok(())
+}
+
+// Count open and close array and dict tags. Self closing tags (<array/>) do not nest
+fn xml_depth_exceeded(data: &[u8], limit: usize) -> bool {
+    let mut depth: usize = 0;
+    for index in 0..data.len() {
+        let tag = match container_tag(data, index) {
+            Some(tag) => tag,
+            None => continue,
+        };
+
+        if tag.closing {
+            depth = depth.saturating_sub(1);
+        } else if !tag.self_closing {
+            depth += 1;
+            if depth > limit {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Array or dict tag in XML PLIST data
+pub(crate) struct ContainerTag {
+    pub(crate) closing: bool,
+    pub(crate) self_closing: bool,
+    pub(crate) dictionary: bool,
+}
+
+/// Check if an array or dict tag starts at the index
+pub(crate) fn container_tag(data: &[u8], index: usize) -> Option<ContainerTag> {
+    if data.get(index) != Some(&b'<') {
+        return None;
+    }
+
+    let tag = &data[index + 1..];
+    let (closing, name) = match tag.strip_prefix(b"/") {
+        Some(name) => (true, name),
+        None => (false, tag),
+    };
+    let (dictionary, name_len) = if name.starts_with(b"array") {
+        (false, 5)
+    } else if name.starts_with(b"dict") {
+        (true, 4)
+    } else {
+        return None;
+    };
+
+    // Make sure the tag is not a longer name (ex: <dictionary>)
+    match name.get(name_len) {
+        Some(byte) if *byte == b'>' || *byte == b'/' || byte.is_ascii_whitespace() => {}
+        _ => return None,
+    }
+
+    Some(ContainerTag {
+        closing,
+        self_closing: !closing && self_closing(&name[name_len..]),
+        dictionary,
+    })
+}
+
+// Check if the rest of the tag ends with />
+fn self_closing(tag_rest: &[u8]) -> bool {
+    match tag_rest.iter().position(|byte| *byte == b'>') {
+        Some(end) => end > 0 && tag_rest[end - 1] == b'/',
+        None => false,
+    }
+}
+
+// Walk the binary PLIST object graph from the top object and track the deepest container nesting.
+// Malformed data is left for the PLIST parser to reject
+fn binary_depth_exceeded(data: &[u8], limit: usize) -> bool {
+    let trailer = match data
+        .len()
+        .checked_sub(BINARY_TRAILER_SIZE)
+        .and_then(|start| data.get(start..))
+    {
+        Some(trailer) if data.len() >= BINARY_HEADER.len() + BINARY_TRAILER_SIZE => trailer,
+        _ => return false,
+    };
+
+    let offset_size = usize::from(trailer[6]);
+    let ref_size = usize::from(trailer[7]);
+    if !(1..=8).contains(&offset_size) || !(1..=8).contains(&ref_size) {
+        return false;
+    }
+    let (num_objects, top_object, offset_table) = match (
+        read_uint(&trailer[8..16]),
+        read_uint(&trailer[16..24]),
+        read_uint(&trailer[24..32]),
+    ) {
+        (Some(num_objects), Some(top_object), Some(offset_table)) => {
+            (num_objects, top_object, offset_table)
+        }
+        _ => return false,
+    };
+    // Every object is at least one byte
+    if num_objects > data.len() || top_object >= num_objects {
+        return false;
+    }
+
+    let table = BinaryTable {
+        data,
+        offset_size,
+        ref_size,
+        num_objects,
+        offset_table,
+    };
+
+    // Depth below each finished object. In progress objects are part of a cycle, which the PLIST parser rejects
+    let mut object_depth: Vec<Option<usize>> = vec![None; num_objects];
+    let mut in_progress: Vec<bool> = vec![false; num_objects];
+
+    struct Frame {
+        object: usize,
+        children: Vec<usize>,
+        next_child: usize,
+        deepest_child: usize,
+    }
+
+    let mut stack: Vec<Frame> = vec![Frame {
+        object: top_object,
+        children: table.children(top_object).unwrap_or_default(),
+        next_child: 0,
+        deepest_child: 0,
+    }];
+    in_progress[top_object] = true;
+
+    while let Some(frame) = stack.last_mut() {
+        if frame.next_child == frame.children.len() {
+            let depth = frame.deepest_child + 1;
+            object_depth[frame.object] = Some(depth);
+            in_progress[frame.object] = false;
+            stack.pop();
+            if let Some(parent) = stack.last_mut() {
+                parent.deepest_child = parent.deepest_child.max(depth);
+            }
+            continue;
+        }
+
+        let child = frame.children[frame.next_child];
+        frame.next_child += 1;
+        if child >= num_objects || in_progress[child] {
+            continue;
+        }
+        if let Some(depth) = object_depth[child] {
+            frame.deepest_child = frame.deepest_child.max(depth);
+            if stack.len() + depth > limit {
+                return true;
+            }
+            continue;
+        }
+
+        let children = match table.children(child) {
+            Some(children) => children,
+            None => {
+                // Not a container
+                object_depth[child] = Some(0);
+                continue;
+            }
+        };
+        if stack.len() + 1 > limit {
+            return true;
+        }
+        in_progress[child] = true;
+        stack.push(Frame {
+            object: child,
+            children,
+            next_child: 0,
+            deepest_child: 0,
+        });
+    }
+    false
+}
+
+struct BinaryTable<'a> {
+    data: &'a [u8],
+    offset_size: usize,
+    ref_size: usize,
+    num_objects: usize,
+    offset_table: usize,
+}
+
+impl BinaryTable<'_> {
+    // Get the object references of an array, set or dictionary. Returns None for other objects or malformed data
+    fn children(&self, object: usize) -> Option<Vec<usize>> {
+        if object >= self.num_objects {
+            return None;
+        }
+        let entry = object
+            .checked_mul(self.offset_size)?
+            .checked_add(self.offset_table)?;
+        let offset = read_uint(self.data.get(entry..entry.checked_add(self.offset_size)?)?)?;
+
+        let marker = *self.data.get(offset)?;
+        let object_type = marker >> 4;
+        if object_type != 0xA && object_type != 0xC && object_type != 0xD {
+            return None;
+        }
+
+        let mut position = offset + 1;
+        let mut count = usize::from(marker & 0xF);
+        if count == 0xF {
+            // Count is stored in a following integer object
+            let int_marker = *self.data.get(position)?;
+            if int_marker >> 4 != 0x1 {
+                return None;
+            }
+            let int_size = 1usize.checked_shl(u32::from(int_marker & 0xF))?;
+            if int_size > 8 {
+                return None;
+            }
+            position += 1;
+            count = read_uint(self.data.get(position..position.checked_add(int_size)?)?)?;
+            position += int_size;
+        }
+
+        // Dictionaries store all key references before the value references. Keys are strings
+        if object_type == 0xD {
+            position = position.checked_add(count.checked_mul(self.ref_size)?)?;
+        }
+        let refs_end = position.checked_add(count.checked_mul(self.ref_size)?)?;
+        let refs = self.data.get(position..refs_end)?;
+
+        refs.chunks(self.ref_size).map(read_uint).collect()
+    }
+}
+
+// Read a big endian unsigned integer of up to 8 bytes
+fn read_uint(bytes: &[u8]) -> Option<usize> {
+    if bytes.is_empty() || bytes.len() > 8 {
+        return None;
+    }
+    let value = bytes
+        .iter()
+        .fold(0u64, |value, byte| (value << 8) | u64::from(*byte));
+    usize::try_from(value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    use super::{
+        binary_depth_exceeded, check_plist_depth, read_plist_reader, xml_depth_exceeded,
+        PlistFormat,
+    };
+
+    fn nested_xml(depth: usize) -> Vec<u8> {
+        let mut xml = String::from("<?xml version=\"1.0\"?><plist version=\"1.0\">");
+        xml.push_str(&"<array>".repeat(depth));
+        xml.push_str("<dict/><array />");
+        xml.push_str(&"</array>".repeat(depth));
+        xml.push_str("</plist>");
+        xml.into_bytes()
+    }
+
+    // Binary PLIST where each object is an array holding the next object
+    fn nested_binary(depth: usize) -> Vec<u8> {
+        let mut data = b"bplist00".to_vec();
+        let mut offsets: Vec<usize> = Vec::new();
+        for object in 0..depth {
+            offsets.push(data.len());
+            if object + 1 == depth {
+                data.push(0xA0);
+            } else {
+                data.push(0xA1);
+                data.extend_from_slice(&((object + 1) as u16).to_be_bytes());
+            }
+        }
+        let offset_table = data.len();
+        for offset in offsets {
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+        }
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 4, 2]);
+        data.extend_from_slice(&(depth as u64).to_be_bytes());
+        data.extend_from_slice(&0u64.to_be_bytes());
+        data.extend_from_slice(&(offset_table as u64).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn test_xml_depth() {
+        assert!(!xml_depth_exceeded(&nested_xml(10), 10));
+        assert!(xml_depth_exceeded(&nested_xml(11), 10));
+        assert!(!xml_depth_exceeded(b"<dictionary><<<", 1));
+        assert!(xml_depth_exceeded(b"<array><dict >", 1));
+    }
+
+    #[test]
+    fn test_binary_depth() {
+        let data = nested_binary(10);
+        let value: plist::Value = plist::from_bytes(&data).unwrap();
+        assert!(value.as_array().is_some());
+
+        assert!(!binary_depth_exceeded(&data, 10));
+        assert!(binary_depth_exceeded(&nested_binary(11), 10));
+        assert!(!binary_depth_exceeded(b"bplist00", 10));
+        assert!(!binary_depth_exceeded(&[0xff; 64], 10));
+    }
+
+    #[test]
+    fn test_check_plist_depth() {
+        assert!(check_plist_depth(&nested_xml(20), "test.plist").is_ok());
+        let err = check_plist_depth(&nested_binary(5000), "test.plist").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to parse PLIST file test.plist: nested deeper than 128 levels"
+        );
+    }
+
+    #[test]
+    fn test_plist_format() {
+        assert_eq!(PlistFormat::detect(&nested_binary(2)), PlistFormat::Binary);
+        assert_eq!(PlistFormat::detect(&nested_xml(2)), PlistFormat::Xml);
+        assert_eq!(PlistFormat::detect(b""), PlistFormat::Xml);
+    }
+
+    #[test]
+    fn test_read_plist_reader() {
+        let mut reader = Cursor::new(nested_xml(2));
+        reader.seek(SeekFrom::Start(5)).unwrap();
+
+        let results = read_plist_reader(reader, "memory").unwrap();
+        assert_eq!(results, nested_xml(2)[5..]);
+
+        let err = read_plist_reader(Cursor::new(nested_xml(200)), "memory").unwrap_err();
+        assert_eq!(err.path(), "memory");
+    }
+}
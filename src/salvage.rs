@@ -0,0 +1,182 @@
+//! Salvage rules from malformed XML rules files
+//!
+//! A truncated or hand edited XML rules file fails to parse as a whole. Each rule dictionary in the root array is located
+//! by its tags and parsed on its own, so one broken rule does not hide the others. A rule that still fails to parse keeps the keys before the malformed XML
+
+use plist::Dictionary;
+
+use crate::reader::container_tag;
+
+const PLIST_START: &[u8] = b"<?xml version=\"1.0\" encoding=\"UTF-8\"?><plist version=\"1.0\">";
+const PLIST_END: &[u8] = b"</plist>";
+
+/// Rule dictionary recovered from malformed XML
+pub(crate) struct SalvagedRule {
+    pub(crate) rule: Result<Dictionary, plist::Error>,
+    /// Error that stopped the rule from parsing fully, if the rule is incomplete
+    pub(crate) incomplete: Option<plist::Error>,
+}
+
+/// Salvage every rule dictionary in the root array of XML rules data
+pub(crate) fn salvage_xml_rules(data: &[u8]) -> Vec<SalvagedRule> {
+    rule_spans(data)
+        .into_iter()
+        .map(|(start, end)| salvage_rule(&data[start..end]))
+        .collect()
+}
+
+// Find the byte ranges of the dictionaries in the root array. A dictionary cut off by the end of the data runs to the end
+fn rule_spans(data: &[u8]) -> Vec<(usize, usize)> {
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    let mut depth: usize = 0;
+    let mut rule_start: Option<usize> = None;
+
+    for index in 0..data.len() {
+        let tag = match container_tag(data, index) {
+            Some(tag) => tag,
+            None => continue,
+        };
+        let tag_end = match data[index..].iter().position(|byte| *byte == b'>') {
+            Some(end) => index + end + 1,
+            None => data.len(),
+        };
+
+        if tag.closing {
+            depth = depth.saturating_sub(1);
+            if depth == 1 && tag.dictionary {
+                if let Some(start) = rule_start.take() {
+                    spans.push((start, tag_end));
+                }
+            }
+        } else if tag.self_closing {
+            if depth == 1 && tag.dictionary {
+                spans.push((index, tag_end));
+            }
+        } else {
+            if depth == 1 && tag.dictionary {
+                rule_start = Some(index);
+            }
+            depth += 1;
+        }
+    }
+
+    if let Some(start) = rule_start {
+        spans.push((start, data.len()));
+    }
+    spans
+}
+
+// Parse a single rule dictionary. If it is malformed, keep the rule keys before the malformed XML
+fn salvage_rule(rule_data: &[u8]) -> SalvagedRule {
+    let err = match parse_rule_dictionary(rule_data, &[]) {
+        Ok(dictionary) => {
+            return SalvagedRule {
+                rule: Ok(dictionary),
+                incomplete: None,
+            }
+        }
+        Err(err) => err,
+    };
+
+    // If the rule parses when cut before a key, it also parses when cut before any earlier key.
+    // Search for the last key the rule can be cut before
+    let key_starts = rule_key_starts(rule_data);
+    let mut salvaged: Option<Dictionary> = None;
+    let (mut low, mut high) = (0, key_starts.len());
+    while low < high {
+        let middle = low + (high - low) / 2;
+        match parse_rule_dictionary(&rule_data[..key_starts[middle]], b"</dict>") {
+            Ok(dictionary) => {
+                salvaged = Some(dictionary);
+                low = middle + 1;
+            }
+            Err(_) => high = middle,
+        }
+    }
+
+    match salvaged {
+        Some(dictionary) if !dictionary.is_empty() => SalvagedRule {
+            rule: Ok(dictionary),
+            incomplete: Some(err),
+        },
+        _ => SalvagedRule {
+            rule: Err(err),
+            incomplete: None,
+        },
+    }
+}
+
+// Wrap the rule data in a PLIST document and parse it
+fn parse_rule_dictionary(rule_data: &[u8], closing: &[u8]) -> Result<Dictionary, plist::Error> {
+    let mut plist_data = PLIST_START.to_vec();
+    plist_data.extend_from_slice(rule_data);
+    plist_data.extend_from_slice(closing);
+    plist_data.extend_from_slice(PLIST_END);
+    plist::from_bytes(&plist_data)
+}
+
+// Find the start of every key tag directly in the rule dictionary
+fn rule_key_starts(rule_data: &[u8]) -> Vec<usize> {
+    let mut key_starts: Vec<usize> = Vec::new();
+    let mut depth: usize = 0;
+    for index in 0..rule_data.len() {
+        if depth == 1 && rule_data[index..].starts_with(b"<key>") {
+            key_starts.push(index);
+            continue;
+        }
+        match container_tag(rule_data, index) {
+            Some(tag) if tag.closing => depth = depth.saturating_sub(1),
+            Some(tag) if !tag.self_closing => depth += 1,
+            _ => continue,
+        }
+    }
+    key_starts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rule_key_starts, rule_spans, salvage_xml_rules};
+
+    #[test]
+    fn test_rule_spans() {
+        let data =
+            b"<plist><array><dict><key>a</key><array><dict/></array></dict><dict/><dict><key>b";
+        let results = rule_spans(data);
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            &data[results[0].0..results[0].1],
+            b"<dict><key>a</key><array><dict/></array></dict>"
+        );
+        assert_eq!(&data[results[1].0..results[1].1], b"<dict/>");
+        assert_eq!(&data[results[2].0..results[2].1], b"<dict><key>b");
+    }
+
+    #[test]
+    fn test_rule_key_starts() {
+        let data = b"<dict><key>a</key><dict><key>b</key></dict><key>c</key><array/><key>d";
+        assert_eq!(rule_key_starts(data), [6, 43, 63]);
+    }
+
+    #[test]
+    fn test_salvage_xml_rules() {
+        let data = b"<plist><array>
+            <dict><key>name</key><string>first</string></dict>
+            <dict><key>name</key><string>second</strin></dict>
+            <dict><key>name</key><string>third</string><key>eventTypes</key><array><string>startup</string>";
+        let results = salvage_xml_rules(data);
+        assert_eq!(results.len(), 3);
+
+        let first = results[0].rule.as_ref().unwrap();
+        assert_eq!(first.get("name").unwrap().as_string(), Some("first"));
+        assert!(results[0].incomplete.is_none());
+
+        // No keys before the malformed value could be kept
+        assert!(results[1].rule.is_err());
+
+        // The truncated event types are dropped
+        let third = results[2].rule.as_ref().unwrap();
+        assert_eq!(third.get("name").unwrap().as_string(), Some("third"));
+        assert!(third.get("eventTypes").is_none());
+        assert!(results[2].incomplete.is_some());
+    }
+}
@@ -2,6 +2,9 @@ use std::path::Path;
 
 use log::warn;
 
+/// Largest Emond file that will be read (2GB)
+pub(crate) const MAX_FILE_SIZE: u64 = 2147483648;
+
 // Check if provided file path is larger the max file size
 pub(crate) fn get_file_size(path: &str) -> bool {
     let size_results = Path::new(&path).metadata();
@@ -16,8 +19,7 @@ pub(crate) fn get_file_size(path: &str) -> bool {
         }
     };
 
-    let max_size = 2147483648; // 2GB
-    if file_size < max_size {
+    if file_size < MAX_FILE_SIZE {
         return true;
     }
     false
@@ -31,6 +33,6 @@ mod tests {
     fn test_get_file_size() {
         let path = "/bin/ls";
         let result = get_file_size(path);
-        assert_eq!(result, true)
+        assert!(result)
     }
 }
@@ -0,0 +1,240 @@
+use log::warn;
+use serde::Serialize;
+
+use crate::{
+    clients::{EmondClients, EMOND_CLIENTS_PATH},
+    config::{EmondConfig, DEFAULT_RULES_PATH, EMOND_CONFIG_PATH},
+    context::ParseContext,
+    detection_rules::{match_rules, DetectionRule, RuleMatch},
+    diagnostic::{Diagnostic, DiagnosticKind, Severity},
+    emond::EmondData,
+    parser::get_emond_config_with_context,
+};
+
+/// Everything Emond related found on a system
+#[derive(Debug, Serialize)]
+pub struct EmondSnapshot {
+    /// Filesystem root the snapshot was collected from
+    pub root: String,
+    /// Emond config PLIST at `/etc/emond.d/emond.plist`, if present
+    pub config: Option<EmondConfig>,
+    /// Default and additional Emond Rules directories
+    pub rules_paths: Vec<String>,
+    /// Every rule parsed from every rules directory
+    pub rules: Vec<EmondData>,
+    /// Files in `/private/var/db/emondClients`, if the directory exists
+    pub clients: Option<EmondClients>,
+    /// Rules matched by user defined detection rules. Empty until `match_detection_rules` is called
+    pub matches: Vec<RuleMatch>,
+    /// Files, directories and rules that could not be read or parsed.
+    /// Issues inside a parsed rule are in the diagnostics of the rule
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl EmondSnapshot {
+    /// Collect the Emond config, rules and clients under the context root
+    pub fn collect(context: &ParseContext) -> EmondSnapshot {
+        let mut snapshot = EmondSnapshot {
+            root: context.root().display().to_string(),
+            config: None,
+            rules_paths: Vec::new(),
+            rules: Vec::new(),
+            clients: None,
+            matches: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+
+        match get_emond_config_with_context(context) {
+            Ok(Some(config)) => {
+                snapshot.rules_paths = config.rules_paths();
+                snapshot.config = Some(config);
+            }
+            Ok(None) => {
+                let mut diagnostic = Diagnostic::new(
+                    Severity::Warning,
+                    DiagnosticKind::Missing,
+                    "",
+                    String::from("Emond config /etc/emond.d/emond.plist not found"),
+                );
+                diagnostic.file = String::from(EMOND_CONFIG_PATH);
+                snapshot.add_diagnostic(diagnostic);
+                snapshot.rules_paths.push(String::from(DEFAULT_RULES_PATH));
+            }
+            Err(err) => {
+                snapshot.add_diagnostic(Diagnostic::unparsable(
+                    EMOND_CONFIG_PATH,
+                    format!(
+                        "Failed to parse Emond config /etc/emond.d/emond.plist: {}",
+                        err
+                    ),
+                ));
+                snapshot.rules_paths.push(String::from(DEFAULT_RULES_PATH));
+            }
+        }
+
+        for rules_path in snapshot.rules_paths.clone() {
+            snapshot.collect_rules(context, &rules_path);
+        }
+
+        match EmondClients::parse_clients(context) {
+            Ok(clients) => snapshot.clients = Some(clients),
+            Err(err) => snapshot.add_diagnostic(Diagnostic::unparsable(
+                EMOND_CLIENTS_PATH,
+                format!(
+                    "Failed to read Emond clients directory {}: {}",
+                    EMOND_CLIENTS_PATH, err
+                ),
+            )),
+        }
+        snapshot
+    }
+
+    /// Match the collected rules against detection rules and keep the matches with the snapshot
+    pub fn match_detection_rules(&mut self, detection_rules: &[DetectionRule]) {
+        self.matches = match_rules(detection_rules, &self.rules);
+    }
+
+    // Parse all rules in a rules directory, recording any failures as diagnostics
+    fn collect_rules(&mut self, context: &ParseContext, rules_path: &str) {
+        let rules_files = match EmondData::parse_rules_directory(context, rules_path) {
+            Ok(results) => results,
+            Err(err) => {
+                self.add_diagnostic(Diagnostic::unparsable(
+                    rules_path,
+                    format!(
+                        "Failed to read Emond rules directory {}: {}",
+                        rules_path, err
+                    ),
+                ));
+                return;
+            }
+        };
+
+        for rules_file in rules_files {
+            let rule_results = match rules_file.rules {
+                Ok(results) => results,
+                Err(err) => {
+                    self.add_diagnostic(Diagnostic::unparsable(
+                        &rules_file.path,
+                        format!(
+                            "Failed to parse Emond rules file {}: {}",
+                            rules_file.path, err
+                        ),
+                    ));
+                    continue;
+                }
+            };
+
+            for (rule_index, rule_result) in rule_results.into_iter().enumerate() {
+                match rule_result {
+                    Ok(emond_data) => self.rules.push(emond_data),
+                    Err(err) => {
+                        let mut diagnostic = Diagnostic::unparsable(
+                            &rules_file.path,
+                            format!(
+                                "Failed to parse Emond rule at index {} in {}: {}",
+                                rule_index, rules_file.path, err
+                            ),
+                        );
+                        diagnostic.rule_index = Some(rule_index);
+                        self.add_diagnostic(diagnostic);
+                    }
+                }
+            }
+        }
+    }
+
+    fn add_diagnostic(&mut self, diagnostic: Diagnostic) {
+        warn!("[macos-emond] {}", diagnostic);
+        self.diagnostics.push(diagnostic);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        context::ParseContext, detection_rules::load_detection_rules, diagnostic::DiagnosticKind,
+    };
+
+    use super::EmondSnapshot;
+
+    #[test]
+    fn test_collect() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+        let context = ParseContext::new(&test_location.display().to_string());
+
+        let results = EmondSnapshot::collect(&context);
+        let config = results.config.unwrap();
+        assert_eq!(config.source, "/etc/emond.d/emond.plist");
+        assert_eq!(
+            config.config.error_log_path,
+            "/Library/Logs/EventMonitor/EventMonitor.error.log"
+        );
+        assert_eq!(
+            results.rules_paths,
+            ["/etc/emond.d/rules", "/usr/local/etc/emond.d/rules"]
+        );
+        assert_eq!(results.rules.len(), 2);
+        assert_eq!(results.rules[0].name, "sample rule");
+        assert_eq!(
+            results.rules[0].source_file,
+            "/etc/emond.d/rules/SampleRules.plist"
+        );
+        assert_eq!(results.rules[1].name, "update rule");
+        assert_eq!(
+            results.rules[1].source_file,
+            "/usr/local/etc/emond.d/rules/Persistence.plist"
+        );
+        let clients = results.clients.unwrap();
+        assert!(clients.enabled);
+        assert_eq!(clients.clients[0].name, "com.update.client");
+        assert!(results.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_match_detection_rules() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data");
+        let detection_rules: Vec<_> =
+            load_detection_rules(&format!("{}/detection_rules", test_location.display()))
+                .unwrap()
+                .into_iter()
+                .flatten()
+                .collect();
+
+        test_location.push("system");
+        let context = ParseContext::new(&test_location.display().to_string());
+        let mut results = EmondSnapshot::collect(&context);
+        assert!(results.matches.is_empty());
+
+        results.match_detection_rules(&detection_rules);
+        assert_eq!(results.matches.len(), 2);
+        assert_eq!(results.matches[0].id, "emond-log-message");
+        assert_eq!(results.matches[0].rule_name, "sample rule");
+        assert_eq!(results.matches[1].id, "emond-tmp-command");
+        assert_eq!(results.matches[1].level, "high");
+        assert_eq!(
+            results.matches[1].file,
+            "/usr/local/etc/emond.d/rules/Persistence.plist"
+        );
+    }
+
+    #[test]
+    fn test_collect_missing_root() {
+        let context = ParseContext::new("/tmp/macos_emond_missing_root");
+
+        let results = EmondSnapshot::collect(&context);
+        assert!(results.config.is_none());
+        assert_eq!(results.rules_paths, ["/etc/emond.d/rules"]);
+        assert!(results.rules.is_empty());
+        assert!(results.clients.is_none());
+        assert_eq!(results.diagnostics.len(), 3);
+        assert_eq!(results.diagnostics[0].kind, DiagnosticKind::Missing);
+        assert_eq!(results.diagnostics[0].file, "/etc/emond.d/emond.plist");
+        assert_eq!(results.diagnostics[1].kind, DiagnosticKind::Unparsable);
+        assert_eq!(results.diagnostics[1].file, "/etc/emond.d/rules");
+    }
+}
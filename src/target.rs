@@ -0,0 +1,277 @@
+//! Resolve and inspect the files RunCommand actions run
+//!
+//! Emond runs the command path of a RunCommand action as root at boot or login, so the file it points to is usually the
+//! persistence payload. The target is resolved under the parse root, symlinks are recorded and scripts are previewed
+
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    context::{ParseContext, Symlink},
+    metadata::FileMetadata,
+};
+
+/// Number of script bytes embedded in a target when parsing rules with `resolve_commands`
+pub const DEFAULT_PREVIEW_SIZE: usize = 4096;
+
+/// Bytes read to detect the target type, enough for a shebang line
+const HEADER_SIZE: usize = 512;
+
+/// Type of a RunCommand target, detected from magic bytes or the script shebang
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub enum TargetType {
+    /// Mach-O executable, including universal binaries
+    MachO,
+    ShellScript,
+    Python,
+    /// AppleScript or JavaScript for Automation run by osascript
+    AppleScript,
+    CompiledAppleScript,
+    Perl,
+    Ruby,
+    /// Script run by another interpreter
+    Script,
+    Directory,
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommandTarget {
+    /// Command path on the analyzed system
+    pub path: String,
+    /// Path on the analyzed system after following symlinks
+    pub resolved_path: String,
+    pub exists: bool,
+    /// Symlinks followed to reach the target, in order
+    pub symlinks: Vec<Symlink>,
+    pub file_type: TargetType,
+    /// Interpreter in the script shebang (ex: /usr/bin/env python3)
+    pub interpreter: String,
+    /// Target file metadata (size, owner, permissions and timestamps)
+    pub metadata: Option<FileMetadata>,
+    /// First bytes of a script, invalid UTF-8 is replaced
+    pub preview: Option<String>,
+}
+
+impl CommandTarget {
+    /// Resolve the command path under the context root and inspect the file it points to.
+    /// Scripts embed their first `preview_size` bytes. Relative command paths cannot be resolved and do not exist
+    pub fn resolve(context: &ParseContext, command: &str, preview_size: usize) -> CommandTarget {
+        let mut target = CommandTarget {
+            path: command.to_string(),
+            resolved_path: command.to_string(),
+            exists: false,
+            symlinks: Vec::new(),
+            file_type: TargetType::Unknown,
+            interpreter: String::new(),
+            metadata: None,
+            preview: None,
+        };
+        if !command.starts_with('/') {
+            return target;
+        }
+
+        let (host_path, symlinks) = context.resolve_symlinks(command);
+        target.resolved_path = system_path(context, &host_path);
+        target.symlinks = symlinks;
+        target.metadata = FileMetadata::get_metadata(&host_path);
+        target.exists = target.metadata.is_some();
+
+        if host_path.is_dir() {
+            target.file_type = TargetType::Directory;
+            return target;
+        }
+        if !host_path.is_file() {
+            return target;
+        }
+
+        let data = match read_header(&host_path, preview_size.max(HEADER_SIZE)) {
+            Some(data) => data,
+            None => return target,
+        };
+        let (file_type, interpreter) = detect_target_type(&data);
+        if is_script(&file_type) {
+            let preview_end = preview_size.min(data.len());
+            target.preview = Some(String::from_utf8_lossy(&data[..preview_end]).to_string());
+        }
+        target.file_type = file_type;
+        target.interpreter = interpreter;
+        target
+    }
+}
+
+/// Detect the target type from the start of the file. Returns the type and the shebang interpreter, if any
+pub fn detect_target_type(data: &[u8]) -> (TargetType, String) {
+    let mach_o_magic: [[u8; 4]; 5] = [
+        [0xfe, 0xed, 0xfa, 0xce],
+        [0xce, 0xfa, 0xed, 0xfe],
+        [0xfe, 0xed, 0xfa, 0xcf],
+        [0xcf, 0xfa, 0xed, 0xfe],
+        [0xca, 0xfe, 0xba, 0xbe],
+    ];
+    if mach_o_magic.iter().any(|magic| data.starts_with(magic)) {
+        return (TargetType::MachO, String::new());
+    }
+    if data.starts_with(b"FasdUAS") {
+        return (TargetType::CompiledAppleScript, String::new());
+    }
+    if !data.starts_with(b"#!") {
+        return (TargetType::Unknown, String::new());
+    }
+
+    let line_end = data
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .unwrap_or(data.len());
+    let interpreter = String::from_utf8_lossy(&data[2..line_end])
+        .trim()
+        .to_string();
+
+    // `#!/usr/bin/env python3` runs the first argument that is not an option
+    let mut words = interpreter.split_whitespace();
+    let mut program = words.next().unwrap_or_default();
+    if program.rsplit('/').next() == Some("env") {
+        program = words
+            .find(|word| !word.starts_with('-'))
+            .unwrap_or_default();
+    }
+    let program_name = program.rsplit('/').next().unwrap_or_default();
+
+    let shells = ["sh", "bash", "zsh", "dash", "ksh", "csh", "tcsh", "fish"];
+    let file_type = if shells.contains(&program_name) {
+        TargetType::ShellScript
+    } else if program_name.starts_with("python") {
+        TargetType::Python
+    } else if program_name == "osascript" {
+        TargetType::AppleScript
+    } else if program_name.starts_with("perl") {
+        TargetType::Perl
+    } else if program_name.starts_with("ruby") {
+        TargetType::Ruby
+    } else {
+        TargetType::Script
+    };
+    (file_type, interpreter)
+}
+
+// Scripts are text, so their contents can be previewed
+fn is_script(file_type: &TargetType) -> bool {
+    matches!(
+        file_type,
+        TargetType::ShellScript
+            | TargetType::Python
+            | TargetType::AppleScript
+            | TargetType::Perl
+            | TargetType::Ruby
+            | TargetType::Script
+    )
+}
+
+// Read up to `size` bytes from the start of the file
+fn read_header(path: &Path, size: usize) -> Option<Vec<u8>> {
+    let file = match File::open(path) {
+        Ok(result) => result,
+        Err(err) => {
+            warn!(
+                "[macos-emond] Failed to open RunCommand target {}: {:?}",
+                path.display(),
+                err
+            );
+            return None;
+        }
+    };
+
+    let mut data: Vec<u8> = Vec::new();
+    if let Err(err) = file.take(size as u64).read_to_end(&mut data) {
+        warn!(
+            "[macos-emond] Failed to read RunCommand target {}: {:?}",
+            path.display(),
+            err
+        );
+        return None;
+    }
+    Some(data)
+}
+
+// Convert a resolved host path back into a path on the analyzed system
+fn system_path(context: &ParseContext, host_path: &Path) -> String {
+    match host_path.strip_prefix(context.root()) {
+        Ok(path) => PathBuf::from("/").join(path).display().to_string(),
+        Err(_) => host_path.display().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::context::ParseContext;
+
+    use super::{detect_target_type, CommandTarget, TargetType};
+
+    #[test]
+    fn test_resolve_target() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/system");
+        let context = ParseContext::new(&test_location.display().to_string());
+
+        let results = CommandTarget::resolve(&context, "/usr/local/bin/update", 9);
+        assert!(results.exists);
+        assert_eq!(results.resolved_path, "/private/tmp/.update.sh");
+        assert_eq!(results.symlinks[0].path, "/usr/local/bin/update");
+        assert_eq!(results.file_type, TargetType::ShellScript);
+        assert_eq!(results.interpreter, "/bin/sh");
+        assert_eq!(results.preview.as_deref(), Some("#!/bin/sh"));
+        assert_eq!(results.metadata.as_ref().unwrap().size, 50);
+    }
+
+    #[test]
+    fn test_resolve_target_missing() {
+        let context = ParseContext::new("/tmp/macos_emond_missing_root");
+        let results = CommandTarget::resolve(&context, "/tmp/payload.sh", 4096);
+        assert!(!results.exists);
+        assert_eq!(results.file_type, TargetType::Unknown);
+        assert!(results.preview.is_none());
+
+        let results = CommandTarget::resolve(&context, "payload.sh", 4096);
+        assert!(!results.exists);
+        assert_eq!(results.resolved_path, "payload.sh");
+    }
+
+    #[test]
+    fn test_detect_target_type() {
+        let test_data: [(&[u8], TargetType, &str); 8] = [
+            (&[0xcf, 0xfa, 0xed, 0xfe, 0x07], TargetType::MachO, ""),
+            (&[0xca, 0xfe, 0xba, 0xbe, 0x00], TargetType::MachO, ""),
+            (b"FasdUAS 1.101.10", TargetType::CompiledAppleScript, ""),
+            (b"#!/bin/bash\necho", TargetType::ShellScript, "/bin/bash"),
+            (
+                b"#!/usr/bin/env -S python3 -u\nimport os",
+                TargetType::Python,
+                "/usr/bin/env -S python3 -u",
+            ),
+            (
+                b"#!/usr/bin/osascript\ndisplay dialog",
+                TargetType::AppleScript,
+                "/usr/bin/osascript",
+            ),
+            (
+                b"#!/usr/local/bin/node",
+                TargetType::Script,
+                "/usr/local/bin/node",
+            ),
+            (b"echo test", TargetType::Unknown, ""),
+        ];
+        for (data, file_type, interpreter) in test_data {
+            let results = detect_target_type(data);
+            assert_eq!(results.0, file_type);
+            assert_eq!(results.1, interpreter);
+        }
+    }
+}
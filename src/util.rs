@@ -1,6 +1,8 @@
 use log::warn;
 use plist::{Dictionary, Value};
 
+use crate::diagnostic::Expected;
+
 // Get the string value from the dictionary
 pub fn get_string_value(dict_data: &Value) -> String {
     let results = dict_data.as_string();
@@ -25,6 +27,24 @@ pub fn get_boolean_value(dict_data: &Value) -> bool {
     }
 }
 
+// Get the Vec of string values from the dictionary
+pub fn get_string_values(dict_data: &Value) -> Vec<String> {
+    let mut string_vec: Vec<String> = Vec::new();
+    let results = dict_data.as_array();
+    match results {
+        Some(data) => {
+            for value in data {
+                string_vec.push(get_string_value(value));
+            }
+            string_vec
+        }
+        None => {
+            warn!("No string array in PLIST file");
+            string_vec
+        }
+    }
+}
+
 // Get the Dictionary value from the dictionary
 pub fn get_dictionary_value(dict_data: Value) -> Dictionary {
     let results = dict_data.into_dictionary();
@@ -37,31 +57,231 @@ pub fn get_dictionary_value(dict_data: Value) -> Dictionary {
     }
 }
 
-// Get the Vec of dictionaries value from the dictionary
-pub fn get_dictionary_values(dict_data: Value) -> Vec<Dictionary> {
-    let mut dictionary_vec: Vec<Dictionary> = Vec::new();
-    let results = dict_data.into_array();
-    match results {
-        Some(data) => {
-            for value in data {
-                let dictionary_value = get_dictionary_value(value);
-                dictionary_vec.push(dictionary_value);
-            }
-            dictionary_vec
+// Get the keys of the dictionary in the order they were parsed
+pub fn get_key_order(dict_data: &Dictionary) -> Vec<String> {
+    dict_data.keys().cloned().collect()
+}
+
+// Check if a value is written. Empty values are only written if the parsed dictionary had the key
+pub fn write_value(key_order: &[String], key: &str, is_empty: bool) -> bool {
+    !is_empty || key_order.iter().any(|order_key| order_key == key)
+}
+
+// Check if a required value is written. Built dictionaries always have it, parsed dictionaries only if they had the key
+pub fn write_required_value(key_order: &[String], key: &str, is_empty: bool) -> bool {
+    key_order.is_empty() || write_value(key_order, key, is_empty)
+}
+
+// Insert the string value into the dictionary. Empty strings are skipped, unless the parsed dictionary had the key
+pub fn insert_string_value(
+    dict_data: &mut Dictionary,
+    key: &str,
+    value: &str,
+    key_order: &[String],
+) {
+    if !write_value(key_order, key, value.is_empty()) {
+        return;
+    }
+    dict_data.insert(key.to_string(), Value::String(value.to_string()));
+}
+
+// Insert the Vec of string values into the dictionary. Empty arrays are skipped, unless the parsed dictionary had the key
+pub fn insert_string_values(
+    dict_data: &mut Dictionary,
+    key: &str,
+    values: &[String],
+    key_order: &[String],
+) {
+    if !write_value(key_order, key, values.is_empty()) {
+        return;
+    }
+    let array = values
+        .iter()
+        .map(|value| Value::String(value.clone()))
+        .collect();
+    dict_data.insert(key.to_string(), Value::Array(array));
+}
+
+// Insert the Dictionary value into the dictionary. Empty dictionaries are skipped, unless the parsed dictionary had the key
+pub fn insert_dictionary_value(
+    dict_data: &mut Dictionary,
+    key: &str,
+    value: &Dictionary,
+    key_order: &[String],
+) {
+    if !write_value(key_order, key, value.is_empty()) {
+        return;
+    }
+    dict_data.insert(key.to_string(), Value::Dictionary(value.clone()));
+}
+
+// Keep a value that was not parsed in extra. Unknown keys and known keys with an unexpected value type are both kept,
+// so values hidden in a rule or config are not lost
+pub fn insert_extra_value(
+    extra: &mut Dictionary,
+    known_keys: &[(&str, Expected)],
+    key: &str,
+    value: &Value,
+    dictionary_name: &str,
+) {
+    if known_keys.iter().any(|(known_key, _)| *known_key == key) {
+        warn!(
+            "Unexpected type for {} in {}. Value: {:?}",
+            key, dictionary_name, value
+        );
+    } else {
+        warn!(
+            "Unknown {} key: {}. Value: {:?}",
+            dictionary_name, key, value
+        );
+    }
+    extra.insert(key.to_string(), value.clone());
+}
+
+// Keep an array element that is not a dictionary in extra, under its position in the array (ex: criterion[1])
+pub fn insert_extra_element(extra: &mut Dictionary, array_key: &str, index: usize, value: &Value) {
+    let key = format!("{}[{}]", array_key, index);
+    warn!("Expected a dictionary at {}. Value: {:?}", key, value);
+    extra.insert(key, value.clone());
+}
+
+// Get the array position of an element kept in extra by insert_extra_element
+pub fn extra_element_index(key: &str, array_key: &str) -> Option<usize> {
+    key.strip_prefix(array_key)?
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
+
+// Get the positions of the parsed elements in the original array. Elements kept in extra keep their position and the
+// parsed elements fill the other positions in order
+pub fn element_positions(count: usize, extra: &Dictionary, array_key: &str) -> Vec<usize> {
+    let mut positions: Vec<usize> = Vec::new();
+    let mut index = 0;
+    while positions.len() < count {
+        if !extra.contains_key(&format!("{}[{}]", array_key, index)) {
+            positions.push(index);
         }
-        None => {
-            warn!("No dictionary array in PLIST file");
-            dictionary_vec
+        index += 1;
+    }
+    positions
+}
+
+// Insert every key of the extra dictionary into the dictionary, except the array elements kept by insert_extra_element
+pub fn insert_extra_values_without_elements(
+    dict_data: &mut Dictionary,
+    extra: &Dictionary,
+    array_keys: &[&str],
+) {
+    for (key, value) in extra {
+        if array_keys
+            .iter()
+            .any(|array_key| extra_element_index(key, array_key).is_some())
+        {
+            continue;
+        }
+        dict_data.insert(key.clone(), value.clone());
+    }
+}
+
+// Rebuild an array from the parsed elements and the elements kept in extra
+pub fn merge_extra_elements(values: Vec<Value>, extra: &Dictionary, array_key: &str) -> Vec<Value> {
+    let positions = element_positions(values.len(), extra, array_key);
+    let mut elements: Vec<(usize, Value)> = positions.into_iter().zip(values).collect();
+    for (key, value) in extra {
+        if let Some(index) = extra_element_index(key, array_key) {
+            elements.push((index, value.clone()));
+        }
+    }
+    elements.sort_by_key(|(index, _)| *index);
+    elements.into_iter().map(|(_, value)| value).collect()
+}
+
+// Insert every key of the extra dictionary into the dictionary
+pub fn insert_extra_values(dict_data: &mut Dictionary, extra: &Dictionary) {
+    for (key, value) in extra {
+        dict_data.insert(key.clone(), value.clone());
+    }
+}
+
+// Sort the dictionary keys into the parsed key order. Keys that were not parsed keep their place after them
+pub fn order_dictionary(dict_data: Dictionary, key_order: &[String]) -> Dictionary {
+    if key_order.is_empty() {
+        return dict_data;
+    }
+    let mut ordered = Dictionary::new();
+    for key in key_order {
+        if let Some(value) = dict_data.get(key) {
+            ordered.insert(key.clone(), value.clone());
+        }
+    }
+    for (key, value) in dict_data {
+        if !ordered.contains_key(&key) {
+            ordered.insert(key, value);
         }
     }
+    ordered
+}
+
+// Get the name of the PLIST value type
+pub fn get_value_type(dict_data: &Value) -> &'static str {
+    match dict_data {
+        Value::Array(_) => "array",
+        Value::Dictionary(_) => "dictionary",
+        Value::Boolean(_) => "boolean",
+        Value::Data(_) => "data",
+        Value::Date(_) => "date",
+        Value::Real(_) => "real",
+        Value::Integer(_) => "integer",
+        Value::String(_) => "string",
+        Value::Uid(_) => "uid",
+        _ => "unknown",
+    }
+}
+
+// Format seconds since the UNIX epoch as a UTC timestamp (ex: 2022-09-05 14:03:09 +0000)
+pub fn format_unix_time(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86400);
+    let seconds = timestamp.rem_euclid(86400);
+
+    // Convert days since the epoch to a civil date
+    let shifted_days = days + 719468;
+    let era = shifted_days.div_euclid(146097);
+    let day_of_era = shifted_days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} +0000",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use plist::{Dictionary, Value};
 
-    use crate::util::{
-        get_boolean_value, get_dictionary_value, get_dictionary_values, get_string_value,
+    use crate::{
+        diagnostic::Expected,
+        util::{
+            format_unix_time, get_boolean_value, get_dictionary_value, get_string_value,
+            get_string_values, get_value_type, insert_extra_value, order_dictionary, write_value,
+        },
     };
 
     #[test]
@@ -72,12 +292,23 @@ mod tests {
         assert_eq!(results, "test");
     }
 
+    #[test]
+    fn test_get_string_values() {
+        let test: Value = Value::Array(vec![
+            Value::String(String::from("test")),
+            Value::String(String::from("test2")),
+        ]);
+        let results = get_string_values(&test);
+
+        assert_eq!(results, ["test", "test2"]);
+    }
+
     #[test]
     fn test_get_bool_value() {
         let test: Value = Value::Boolean(false);
         let results = get_boolean_value(&test);
 
-        assert_eq!(results, false);
+        assert!(!results);
     }
 
     #[test]
@@ -89,11 +320,63 @@ mod tests {
     }
 
     #[test]
-    fn test_get_dictionary_values() {
-        let test: Value = Value::Dictionary(Dictionary::new());
-        let test_array: Value = Value::Array(vec![test]);
-        let results = get_dictionary_values(test_array);
+    fn test_order_dictionary() {
+        let mut test = Dictionary::new();
+        test.insert(String::from("name"), Value::String(String::new()));
+        test.insert(String::from("enabled"), Value::Boolean(true));
+        test.insert(String::from("added"), Value::Boolean(true));
+        let key_order = vec![String::from("enabled"), String::from("name")];
+
+        let results = order_dictionary(test, &key_order);
+        let keys: Vec<&String> = results.keys().collect();
+        assert_eq!(keys, ["enabled", "name", "added"]);
+    }
 
-        assert_eq!(results.len(), 1);
+    #[test]
+    fn test_insert_extra_value() {
+        let mut extra = Dictionary::new();
+        let known_keys = [("command", Expected::String)];
+        insert_extra_value(
+            &mut extra,
+            &known_keys,
+            "command",
+            &Value::Boolean(true),
+            "Test",
+        );
+        insert_extra_value(
+            &mut extra,
+            &known_keys,
+            "hidden",
+            &Value::Boolean(true),
+            "Test",
+        );
+        assert_eq!(extra.len(), 2);
+        assert_eq!(extra.get("command"), Some(&Value::Boolean(true)));
+    }
+
+    #[test]
+    fn test_write_value() {
+        let key_order = vec![String::from("user")];
+        assert!(write_value(&key_order, "user", true));
+        assert!(!write_value(&key_order, "group", true));
+        assert!(write_value(&[], "group", false));
+    }
+
+    #[test]
+    fn test_get_value_type() {
+        assert_eq!(
+            get_value_type(&Value::String(String::from("test"))),
+            "string"
+        );
+        assert_eq!(get_value_type(&Value::Array(Vec::new())), "array");
+        assert_eq!(get_value_type(&Value::Integer(1.into())), "integer");
+    }
+
+    #[test]
+    fn test_format_unix_time() {
+        assert_eq!(format_unix_time(0), "1970-01-01 00:00:00 +0000");
+        assert_eq!(format_unix_time(1662386589), "2022-09-05 14:03:09 +0000");
+        assert_eq!(format_unix_time(951782400), "2000-02-29 00:00:00 +0000");
+        assert_eq!(format_unix_time(-1), "1969-12-31 23:59:59 +0000");
     }
 }
@@ -0,0 +1,473 @@
+//! Validate Emond Rules against what Emond expects
+//!
+//! Diagnostics report values that are missing or have the wrong type. Validation goes further and reports rules that parse
+//! but will not work as intended, such as a RunCommand action with a relative path or a Log action with an unknown log level
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{
+    actions::{send_email::SendEmail, send_sms::SendSms, Action},
+    criterion::{Criterion, Operator},
+    diagnostic::Severity,
+    emond::EmondData,
+    util::element_positions,
+    variable::check_variables,
+};
+
+/// Event types Emond and its built in event sources generate
+pub const KNOWN_EVENT_TYPES: [&str; 6] = [
+    "startup",
+    "periodic",
+    "auth:login",
+    "auth:logout",
+    "auth:success",
+    "auth:failure",
+];
+
+/// Log levels accepted by the Log action
+pub const LOG_LEVELS: [&str; 8] = [
+    "Emergency",
+    "Alert",
+    "Critical",
+    "Error",
+    "Warning",
+    "Notice",
+    "Info",
+    "Debug",
+];
+
+/// Log types accepted by the Log action
+pub const LOG_TYPES: [&str; 2] = ["syslog", "asl"];
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+pub enum FindingKind {
+    MissingName,
+    /// Rule has no event types and never fires
+    EmptyEventTypes,
+    UnknownEventType,
+    /// Start time is not in the `YYYY-MM-DD HH:MM:SS +ZZZZ` format
+    InvalidStartTime,
+    MissingCommand,
+    /// RunCommand commands are not run through a shell and need an absolute path
+    RelativeCommand,
+    InvalidLogLevel,
+    InvalidLogType,
+    InvalidEmailAddress,
+    MissingRecipients,
+    UnknownActionType,
+    UnknownOperator,
+    /// Variable referenced by the rule is not defined
+    UndefinedVariable,
+    /// Rule does nothing when it fires
+    NoActions,
+}
+
+#[derive(Debug, Serialize, PartialEq, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+    pub kind: FindingKind,
+    /// Rules file the rule was parsed from
+    pub file: String,
+    pub rule_name: String,
+    /// Position of the rule within its rules file
+    pub rule_index: usize,
+    /// Location of the value in the rule (ex: actions[0].command)
+    pub key_path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.severity)?;
+        if !self.file.is_empty() {
+            write!(f, " {}", self.file)?;
+        }
+        write!(
+            f,
+            " rule {} ({}): {}",
+            self.rule_index, self.rule_name, self.message
+        )
+    }
+}
+
+/// Validate Emond Rules. Findings are in rule order
+pub fn validate_rules(rules: &[EmondData]) -> Vec<Finding> {
+    rules.iter().flat_map(validate_rule).collect()
+}
+
+/// Validate a single Emond Rule
+pub fn validate_rule(rule: &EmondData) -> Vec<Finding> {
+    let mut findings = RuleFindings {
+        rule,
+        findings: Vec::new(),
+    };
+
+    if rule.name.is_empty() {
+        findings.add(
+            Severity::Warning,
+            FindingKind::MissingName,
+            "name",
+            String::from("Rule has no name"),
+        );
+    }
+    if rule.event_types.is_empty() {
+        findings.add(
+            Severity::Error,
+            FindingKind::EmptyEventTypes,
+            "eventTypes",
+            String::from("Rule has no event types and never fires"),
+        );
+    }
+    for (index, event_type) in rule.event_types.iter().enumerate() {
+        if !KNOWN_EVENT_TYPES.contains(&event_type.as_str()) {
+            findings.add(
+                Severity::Warning,
+                FindingKind::UnknownEventType,
+                &format!("eventTypes[{}]", index),
+                format!("Unknown event type {}", event_type),
+            );
+        }
+    }
+    if !rule.start_time.is_empty() && !valid_start_time(&rule.start_time) {
+        findings.add(
+            Severity::Warning,
+            FindingKind::InvalidStartTime,
+            "startTime",
+            format!(
+                "Start time {} is not in the YYYY-MM-DD HH:MM:SS +ZZZZ format",
+                rule.start_time
+            ),
+        );
+    }
+
+    // Positions come from the original array, which can also hold elements kept in extra
+    let positions = element_positions(rule.criterion.len(), &rule.extra, "criterion");
+    for (index, criterion) in positions.into_iter().zip(&rule.criterion) {
+        findings.criterion(criterion, &format!("criterion[{}]", index));
+    }
+
+    if rule.actions.is_empty() {
+        findings.add(
+            Severity::Info,
+            FindingKind::NoActions,
+            "actions",
+            String::from("Rule has no actions"),
+        );
+    }
+    for rule_action in &rule.actions {
+        findings.action(
+            &rule_action.action,
+            &format!("actions[{}]", rule_action.index),
+        );
+    }
+
+    for reference in check_variables(rule).undefined {
+        findings.add(
+            Severity::Warning,
+            FindingKind::UndefinedVariable,
+            &reference.location,
+            format!("Variable {} is not defined", reference.name),
+        );
+    }
+    findings.findings
+}
+
+// Findings of the rule being validated
+struct RuleFindings<'a> {
+    rule: &'a EmondData,
+    findings: Vec<Finding>,
+}
+
+impl RuleFindings<'_> {
+    fn add(&mut self, severity: Severity, kind: FindingKind, key_path: &str, message: String) {
+        self.findings.push(Finding {
+            severity,
+            kind,
+            file: self.rule.source_file.clone(),
+            rule_name: self.rule.name.clone(),
+            rule_index: self.rule.rule_index,
+            key_path: key_path.to_string(),
+            message,
+        });
+    }
+
+    // Check a criterion and its nested criteria for unknown operators
+    fn criterion(&mut self, criterion: &Criterion, key_path: &str) {
+        if let Operator::Unknown(operator) = &criterion.operator {
+            self.add(
+                Severity::Error,
+                FindingKind::UnknownOperator,
+                &format!("{}.operator", key_path),
+                format!("Unknown criterion operator {}", operator),
+            );
+        }
+        let positions = element_positions(criterion.criterion.len(), &criterion.extra, "criterion");
+        for (index, nested) in positions.into_iter().zip(&criterion.criterion) {
+            self.criterion(nested, &format!("{}.criterion[{}]", key_path, index));
+        }
+    }
+
+    fn action(&mut self, action: &Action, key_path: &str) {
+        match action {
+            Action::Command(command) => {
+                let command_path = format!("{}.command", key_path);
+                if command.command.is_empty() {
+                    self.add(
+                        Severity::Error,
+                        FindingKind::MissingCommand,
+                        &command_path,
+                        String::from("RunCommand action has no command"),
+                    );
+                } else if !command.command.starts_with('/') {
+                    self.add(
+                        Severity::Error,
+                        FindingKind::RelativeCommand,
+                        &command_path,
+                        format!(
+                            "RunCommand command {} is not an absolute path",
+                            command.command
+                        ),
+                    );
+                }
+            }
+            Action::Log(log) => {
+                if !log.log_level.is_empty() && !LOG_LEVELS.contains(&log.log_level.as_str()) {
+                    self.add(
+                        Severity::Warning,
+                        FindingKind::InvalidLogLevel,
+                        &format!("{}.logLevel", key_path),
+                        format!("Unknown log level {}", log.log_level),
+                    );
+                }
+                if !log.log_type.is_empty() && !LOG_TYPES.contains(&log.log_type.as_str()) {
+                    self.add(
+                        Severity::Warning,
+                        FindingKind::InvalidLogType,
+                        &format!("{}.logType", key_path),
+                        format!("Unknown log type {}", log.log_type),
+                    );
+                }
+            }
+            Action::SendEmail(SendEmail {
+                admin_email,
+                recipient_addresses,
+                ..
+            })
+            | Action::SendSms(SendSms {
+                admin_email,
+                recipient_addresses,
+                ..
+            }) => {
+                if recipient_addresses.is_empty() {
+                    self.add(
+                        Severity::Warning,
+                        FindingKind::MissingRecipients,
+                        &format!("{}.recipient_addresses", key_path),
+                        format!("{} action has no recipients", action.action_type()),
+                    );
+                }
+                for (index, address) in recipient_addresses.iter().enumerate() {
+                    self.email_address(
+                        address,
+                        &format!("{}.recipient_addresses[{}]", key_path, index),
+                    );
+                }
+                if !admin_email.is_empty() {
+                    self.email_address(admin_email, &format!("{}.admin_email", key_path));
+                }
+            }
+            Action::SendNotification(_) => {}
+            Action::Unknown(_) => self.add(
+                Severity::Warning,
+                FindingKind::UnknownActionType,
+                &format!("{}.type", key_path),
+                format!("Unknown action type {}", action.action_type()),
+            ),
+        }
+    }
+
+    fn email_address(&mut self, address: &str, key_path: &str) {
+        if !valid_email_address(address) {
+            self.add(
+                Severity::Warning,
+                FindingKind::InvalidEmailAddress,
+                key_path,
+                format!("Invalid email address {}", address),
+            );
+        }
+    }
+}
+
+// Check for a local part and a dotted domain without whitespace (ex: user@example.com)
+fn valid_email_address(address: &str) -> bool {
+    let (local, domain) = match address.split_once('@') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !address.chars().any(char::is_whitespace)
+}
+
+// Check for the YYYY-MM-DD HH:MM:SS +ZZZZ format (ex: 2022-09-05 14:03:09 +0000)
+fn valid_start_time(start_time: &str) -> bool {
+    let bytes = start_time.as_bytes();
+    if bytes.len() != 25 {
+        return false;
+    }
+    let layout = b"dddd-dd-dd dd:dd:dd +dddd";
+    for (byte, expected) in bytes.iter().zip(layout) {
+        let matched = match expected {
+            b'd' => byte.is_ascii_digit(),
+            b'+' => *byte == b'+' || *byte == b'-',
+            _ => byte == expected,
+        };
+        if !matched {
+            return false;
+        }
+    }
+
+    let number = |start: usize, end: usize| start_time[start..end].parse::<u32>().unwrap_or(0);
+    (1..=12).contains(&number(5, 7))
+        && (1..=31).contains(&number(8, 10))
+        && number(11, 13) < 24
+        && number(14, 16) < 60
+        && number(17, 19) < 60
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        actions::{command::Command, log::Log, send_email::SendEmail},
+        builder::EmondRuleBuilder,
+        context::ParseContext,
+        criterion::{Criterion, Operator},
+        diagnostic::Severity,
+        emond::EmondData,
+    };
+
+    use super::{
+        valid_email_address, valid_start_time, validate_rule, validate_rules, FindingKind,
+    };
+
+    #[test]
+    fn test_validate_rules() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/");
+
+        let results = EmondData::parse_emond_rules(
+            &ParseContext::live(),
+            &test_location.display().to_string(),
+        )
+        .unwrap();
+        assert!(validate_rules(&results).is_empty());
+    }
+
+    #[test]
+    fn test_validate_rule() {
+        let mut rule = EmondRuleBuilder::new("bad rule")
+            .event_type("startup")
+            .event_type("usb:inserted")
+            .start_time("tomorrow")
+            .criterion(Criterion::new(Operator::And))
+            .run_command(Command {
+                command: String::from("payload.sh"),
+                ..Default::default()
+            })
+            .log(Log {
+                message: String::from("${missing}"),
+                log_level: String::from("Loud"),
+                log_type: String::from("syslog"),
+                ..Default::default()
+            })
+            .send_email(SendEmail {
+                recipient_addresses: vec![
+                    String::from("admin@example.com"),
+                    String::from("not an address"),
+                ],
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        rule.criterion[0]
+            .criterion
+            .push(Criterion::new(Operator::Unknown(String::from("Near"))));
+
+        let results = validate_rule(&rule);
+        let kinds: Vec<(FindingKind, &str)> = results
+            .iter()
+            .map(|finding| (finding.kind, finding.key_path.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (FindingKind::UnknownEventType, "eventTypes[1]"),
+                (FindingKind::InvalidStartTime, "startTime"),
+                (
+                    FindingKind::UnknownOperator,
+                    "criterion[0].criterion[0].operator"
+                ),
+                (FindingKind::RelativeCommand, "actions[0].command"),
+                (FindingKind::InvalidLogLevel, "actions[1].logLevel"),
+                (
+                    FindingKind::InvalidEmailAddress,
+                    "actions[2].recipient_addresses[1]"
+                ),
+                (FindingKind::UndefinedVariable, "actions[1].message"),
+            ]
+        );
+        assert_eq!(results[3].severity, Severity::Error);
+        assert_eq!(results[3].rule_name, "bad rule");
+        assert_eq!(
+            results[3].to_string(),
+            "Error rule 0 (bad rule): RunCommand command payload.sh is not an absolute path"
+        );
+    }
+
+    #[test]
+    fn test_validate_empty_rule() {
+        let mut rule = EmondRuleBuilder::new("empty")
+            .event_type("startup")
+            .build()
+            .unwrap();
+        rule.name = String::new();
+        rule.event_types.clear();
+
+        let kinds: Vec<FindingKind> = validate_rule(&rule)
+            .iter()
+            .map(|finding| finding.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                FindingKind::MissingName,
+                FindingKind::EmptyEventTypes,
+                FindingKind::NoActions
+            ]
+        );
+    }
+
+    #[test]
+    fn test_valid_email_address() {
+        assert!(valid_email_address("5555550100@txt.att.net"));
+        assert!(!valid_email_address("admin"));
+        assert!(!valid_email_address("@example.com"));
+        assert!(!valid_email_address("admin@localhost"));
+        assert!(!valid_email_address("ad min@example.com"));
+    }
+
+    #[test]
+    fn test_valid_start_time() {
+        assert!(valid_start_time("2022-09-05 14:03:09 +0000"));
+        assert!(valid_start_time("2022-09-05 14:03:09 -0700"));
+        assert!(!valid_start_time("2022-13-05 14:03:09 +0000"));
+        assert!(!valid_start_time("2022-09-05T14:03:09Z"));
+    }
+}
